@@ -3,6 +3,11 @@ use std::env;
 use uniffi_bindgen::bindings::TargetLanguage;
 
 fn main() {
+    // The bindings cover the wallet and auth, which are only built with the "native" feature.
+    if env::var_os("CARGO_FEATURE_NATIVE").is_none() {
+        return;
+    }
+
     let udl_file = Utf8Path::new("src/lipabusinesslib.udl");
     println!("cargo:rerun-if-changed={udl_file}");
 
@@ -1,4 +1,4 @@
-use bdk::bitcoin::{Address, Network};
+use bdk::bitcoin::{Address, Amount, Denomination, Network};
 use bip21::Uri;
 use std::str::FromStr;
 
@@ -10,6 +10,14 @@ pub enum AddressParsingError {
     Other,
 }
 
+pub struct PaymentUri {
+    pub address: String,
+    pub amount_sat: Option<u64>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    pub lightning: Option<String>,
+}
+
 pub fn parse_address(
     address: String,
     expected_network: Network,
@@ -38,6 +46,130 @@ pub fn parse_address(
     }
 }
 
+// Parses a plain address or a BIP-21 URI, keeping the parameters that are relevant for the send
+// screen. Unlike `parse_address()`, the case of the parameters is preserved.
+pub fn parse_payment_uri(
+    uri: String,
+    expected_network: Network,
+) -> Result<PaymentUri, AddressParsingError> {
+    let uri = uri.trim();
+    let (address, query) = match uri.get(..8) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("bitcoin:") => match uri[8..].split_once('?') {
+            Some((address, query)) => (address, Some(query)),
+            None => (&uri[8..], None),
+        },
+        _ => (uri, None),
+    };
+    // Uppercase bech32 addresses are used in QR codes, as they can be encoded more compactly.
+    let address = if address.contains(|c: char| c.is_ascii_lowercase()) {
+        address.to_string()
+    } else {
+        address.to_lowercase()
+    };
+    let address = parse_address(address, expected_network)?.to_string();
+
+    let mut payment_uri = PaymentUri {
+        address,
+        amount_sat: None,
+        label: None,
+        message: None,
+        lightning: None,
+    };
+    for param in query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+    {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        let key = key.to_lowercase();
+        let value = percent_decode(value)?;
+        match key.as_str() {
+            "amount" => {
+                let amount = Amount::from_str_in(&value, Denomination::Bitcoin)
+                    .map_err(|_| AddressParsingError::Other)?;
+                payment_uri.amount_sat = Some(amount.to_sat());
+            }
+            "label" => payment_uri.label = Some(value),
+            "message" => payment_uri.message = Some(value),
+            "lightning" => payment_uri.lightning = Some(value),
+            // BIP-21 requires rejecting URIs with unknown required parameters.
+            _ if key.starts_with("req-") => return Err(AddressParsingError::Other),
+            _ => {}
+        }
+    }
+    Ok(payment_uri)
+}
+
+fn percent_decode(value: &str) -> Result<String, AddressParsingError> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3).ok_or(AddressParsingError::Other)?;
+            let byte = u8::from_str_radix(hex, 16).map_err(|_| AddressParsingError::Other)?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| AddressParsingError::Other)
+}
+
+const SATS_PER_BTC: u64 = 100_000_000;
+
+// Builds a BIP-21 URI, e.g. bitcoin:tb1q...?amount=0.001&label=Shop. Label and message are
+// percent-encoded, keeping only the unreserved characters of RFC 3986.
+pub(crate) fn build_bip21_uri(
+    address: &Address,
+    amount_sat: Option<u64>,
+    label: Option<&str>,
+    message: Option<&str>,
+) -> String {
+    let mut params = Vec::new();
+    if let Some(amount_sat) = amount_sat {
+        params.push(format!("amount={}", format_btc_amount(amount_sat)));
+    }
+    if let Some(label) = label {
+        params.push(format!("label={}", percent_encode(label)));
+    }
+    if let Some(message) = message {
+        params.push(format!("message={}", percent_encode(message)));
+    }
+
+    if params.is_empty() {
+        format!("bitcoin:{address}")
+    } else {
+        format!("bitcoin:{address}?{}", params.join("&"))
+    }
+}
+
+// BIP-21 amounts are denominated in BTC, without trailing zeros.
+fn format_btc_amount(amount_sat: u64) -> String {
+    let btc = amount_sat / SATS_PER_BTC;
+    let sats = amount_sat % SATS_PER_BTC;
+    if sats == 0 {
+        return btc.to_string();
+    }
+    let fraction = format!("{sats:08}");
+    format!("{btc}.{}", fraction.trim_end_matches('0'))
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
 fn from_qr_uri(address: String) -> String {
     if address.starts_with("BITCOIN:") {
         address.to_lowercase()
@@ -46,10 +178,27 @@ fn from_qr_uri(address: String) -> String {
     }
 }
 
+// Entry point of the fuzz targets: parsing arbitrary user input, e.g. a scanned QR code, has to
+// fail with an error and never panic.
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_parse(input: &str) {
+    for network in [Network::Bitcoin, Network::Testnet, Network::Regtest] {
+        let _ = parse_address(input.to_string(), network);
+        let _ = parse_payment_uri(input.to_string(), network);
+    }
+    let _ = percent_decode(input);
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::address::{parse_address, AddressParsingError};
-    use bdk::bitcoin::Network;
+    use crate::address::{
+        build_bip21_uri, parse_address, parse_payment_uri, percent_decode, percent_encode,
+        AddressParsingError,
+    };
+    use bdk::bitcoin::{Address, Network};
+    use bip21::Uri;
+    use proptest::prelude::*;
+    use std::str::FromStr;
 
     const MAINNET: Network = Network::Bitcoin;
     const TESTNET: Network = Network::Testnet;
@@ -151,4 +300,215 @@ mod tests {
         let result = parse_address(ln_invoice, Network::Signet);
         assert!(matches!(result, Err(AddressParsingError::Other)));
     }
+
+    #[test]
+    fn bip21_uri() {
+        let address = Address::from_str("tb1q00000alt56z8fsczc67u7q0vsl0wrqt52x084l").unwrap();
+
+        assert_eq!(
+            build_bip21_uri(&address, None, None, None),
+            "bitcoin:tb1q00000alt56z8fsczc67u7q0vsl0wrqt52x084l"
+        );
+        assert_eq!(
+            build_bip21_uri(&address, Some(100_000), None, None),
+            "bitcoin:tb1q00000alt56z8fsczc67u7q0vsl0wrqt52x084l?amount=0.001"
+        );
+        assert_eq!(
+            build_bip21_uri(&address, Some(2_100_000_000), None, None),
+            "bitcoin:tb1q00000alt56z8fsczc67u7q0vsl0wrqt52x084l?amount=21"
+        );
+
+        let uri = build_bip21_uri(
+            &address,
+            Some(123_456_789),
+            Some("Café & Bar"),
+            Some("Order #42: 2x Espresso?"),
+        );
+        assert_eq!(
+            uri,
+            "bitcoin:tb1q00000alt56z8fsczc67u7q0vsl0wrqt52x084l?amount=1.23456789\
+             &label=Caf%C3%A9%20%26%20Bar\
+             &message=Order%20%2342%3A%202x%20Espresso%3F"
+        );
+
+        let parsed: Uri<'_> = Uri::from_str(&uri).unwrap();
+        assert_eq!(parsed.address, address);
+        assert_eq!(parsed.amount.unwrap().to_sat(), 123_456_789);
+        assert_eq!(parse_address(uri, Network::Testnet).unwrap(), address);
+    }
+
+    #[test]
+    fn payment_uri() {
+        let mainnet_p2wsh =
+            "bc1qhztydhu3p30h0ld5crucmmdrspp2xjtg8xr3f32708al70eegh7qaq50yw".to_string();
+
+        let result = parse_payment_uri(mainnet_p2wsh.clone(), MAINNET).unwrap();
+        assert_eq!(result.address, mainnet_p2wsh);
+        assert_eq!(result.amount_sat, None);
+        assert_eq!(result.label, None);
+
+        let result = parse_payment_uri(
+            "BITCOIN:BC1QHZTYDHU3P30H0LD5CRUCMMDRSPP2XJTG8XR3F32708AL70EEGH7QAQ50YW".to_string(),
+            MAINNET,
+        )
+        .unwrap();
+        assert_eq!(result.address, mainnet_p2wsh);
+
+        let result = parse_payment_uri(
+            format!("bitcoin:{mainnet_p2wsh}?amount=0.000001&label=Gude%20von%20Onleines&message=Order%20%2342&foo=bar"),
+            MAINNET,
+        )
+        .unwrap();
+        assert_eq!(result.address, mainnet_p2wsh);
+        assert_eq!(result.amount_sat, Some(100));
+        assert_eq!(result.label, Some("Gude von Onleines".to_string()));
+        assert_eq!(result.message, Some("Order #42".to_string()));
+        assert_eq!(result.lightning, None);
+
+        let invoice = "LNBC1110N1P3UHH2KDQQNP4QF9N63RP8AH4GUJ5PUXUHFWQPWA9RC4QYF4VC0QQ432MQ3H9NK6GXPP5VYFZ03QT23J8TQP0LQH8AQ3WZ7DHYUDRV0Y2KLFKTNCHAK40PWHSSP5JJXD08RDQJ2TDGN3MTHX69K8987Z8N4ZPSQ0NQL89XXGXCQVE0DQ9QYYSGQCQPCXQRRSSRZJQ2TT9KE59L8C0655MXQH2L7LF5L9GK74EM6FR86CKHFCMLWH806UJZ72CCQQKTGQQQQQQQQQQQQQQQGQ9Q5GECTCYW7CK998RDFWW0LDGDXP974S0XS6YKLZ2DJ0URRFK2QSE8WLETS3AVYAVAAE2TAM99LVCQHUXKX3T78GPPDJA8DPJGZF0H8PGP57Q0AF";
+        let result = parse_payment_uri(
+            format!("bitcoin:{mainnet_p2wsh}?amount=0.00000111&lightning={invoice}"),
+            MAINNET,
+        )
+        .unwrap();
+        assert_eq!(result.amount_sat, Some(111));
+        assert_eq!(result.lightning, Some(invoice.to_string()));
+    }
+
+    #[test]
+    fn invalid_payment_uri() {
+        let mainnet_p2wsh = "bc1qhztydhu3p30h0ld5crucmmdrspp2xjtg8xr3f32708al70eegh7qaq50yw";
+
+        let result = parse_payment_uri(format!("bitcoin:{mainnet_p2wsh}"), TESTNET);
+        assert!(matches!(
+            result,
+            Err(AddressParsingError::InvalidNetwork { .. })
+        ));
+
+        for query in [
+            "amount=0.000000001",
+            "amount=abc",
+            "label=%ZZ",
+            "label=%E2%82",
+            "req-somethingyoudontunderstand=50",
+        ] {
+            let result = parse_payment_uri(format!("bitcoin:{mainnet_p2wsh}?{query}"), MAINNET);
+            assert!(matches!(result, Err(AddressParsingError::Other)), "{query}");
+        }
+    }
+
+    const MAINNET_P2WSH: &str = "bc1qhztydhu3p30h0ld5crucmmdrspp2xjtg8xr3f32708al70eegh7qaq50yw";
+    const MAX_SAT: u64 = 21_000_000 * 100_000_000;
+
+    // Changes the case of the characters of `s` where `mask` has a set bit.
+    fn apply_case_mask(s: &str, mask: &[bool]) -> String {
+        s.chars()
+            .zip(mask.iter().cycle())
+            .map(|(c, upper)| if *upper { c.to_ascii_uppercase() } else { c })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_input_does_not_panic(input in any::<String>()) {
+            let _ = parse_address(input.clone(), MAINNET);
+            let _ = parse_payment_uri(input.clone(), MAINNET);
+            let _ = parse_payment_uri(format!("bitcoin:{input}"), MAINNET);
+            let _ = parse_payment_uri(format!("bitcoin:{MAINNET_P2WSH}?{input}"), MAINNET);
+            let _ = percent_decode(&input);
+        }
+
+        #[test]
+        fn percent_encoding_roundtrip(value in any::<String>()) {
+            prop_assert_eq!(percent_decode(&percent_encode(&value)).unwrap(), value);
+        }
+
+        #[test]
+        fn bip21_roundtrip(
+            amount_sat in proptest::option::of(0..=MAX_SAT),
+            label in proptest::option::of(any::<String>()),
+            message in proptest::option::of(any::<String>()),
+        ) {
+            let address = Address::from_str(MAINNET_P2WSH).unwrap();
+            let uri = build_bip21_uri(&address, amount_sat, label.as_deref(), message.as_deref());
+
+            let result = parse_payment_uri(uri, MAINNET).unwrap();
+            prop_assert_eq!(result.address, MAINNET_P2WSH);
+            prop_assert_eq!(result.amount_sat, amount_sat);
+            prop_assert_eq!(result.label, label);
+            prop_assert_eq!(result.message, message);
+        }
+
+        #[test]
+        fn bip21_exotic_params(
+            params in proptest::collection::vec(("[a-zA-Z][a-zA-Z0-9_.-]{0,15}", any::<String>()), 0..8),
+            scheme in "[bB][iI][tT][cC][oO][iI][nN]",
+        ) {
+            let query = params
+                .iter()
+                .map(|(key, value)| format!("{key}={}", percent_encode(value)))
+                .collect::<Vec<_>>()
+                .join("&");
+            let result = parse_payment_uri(format!("{scheme}:{MAINNET_P2WSH}?{query}"), MAINNET);
+
+            let is_known = |key: &str| {
+                ["amount", "label", "message", "lightning"].contains(&key.to_lowercase().as_str())
+            };
+            let has_required = params.iter().any(|(key, _)| key.to_lowercase().starts_with("req-"));
+            if params.iter().any(|(key, _)| is_known(key.as_str())) {
+                // Known parameters with arbitrary values may fail to parse, e.g. the amount.
+                if let Err(e) = result {
+                    prop_assert_eq!(e, AddressParsingError::Other);
+                }
+            } else if has_required {
+                prop_assert_eq!(result.err(), Some(AddressParsingError::Other));
+            } else {
+                let result = result.unwrap();
+                prop_assert_eq!(result.address, MAINNET_P2WSH);
+                prop_assert_eq!(result.amount_sat, None);
+                prop_assert_eq!(result.label, None);
+                prop_assert_eq!(result.message, None);
+                prop_assert_eq!(result.lightning, None);
+            }
+        }
+
+        #[test]
+        fn mixed_case_bech32(mask in proptest::collection::vec(any::<bool>(), 1..64)) {
+            let address = apply_case_mask(MAINNET_P2WSH, &mask);
+            let is_uppercase = !address.contains(|c: char| c.is_ascii_lowercase());
+            let is_lowercase = !address.contains(|c: char| c.is_ascii_uppercase());
+
+            // Bech32 forbids mixing cases, an address has to be either all lower or all upper case.
+            for result in [
+                parse_payment_uri(format!("bitcoin:{address}"), MAINNET).map(|uri| uri.address),
+                parse_address(address, MAINNET).map(|address| address.to_string()),
+            ] {
+                if is_uppercase || is_lowercase {
+                    prop_assert_eq!(result.unwrap(), MAINNET_P2WSH);
+                } else {
+                    prop_assert_eq!(result.err(), Some(AddressParsingError::Other));
+                }
+            }
+        }
+
+        #[test]
+        fn embedded_lightning_invoice(
+            invoice in "ln(bc|tb|bcrt)[0-9]{0,6}[munp]?1[02-9ac-hj-np-z]{50,400}",
+            uppercase in any::<bool>(),
+        ) {
+            let invoice = if uppercase { invoice.to_uppercase() } else { invoice };
+            let uri = format!("bitcoin:{MAINNET_P2WSH}?lightning={invoice}&amount=0.0001");
+
+            let result = parse_payment_uri(uri.clone(), MAINNET).unwrap();
+            prop_assert_eq!(result.lightning, Some(invoice.clone()));
+            prop_assert_eq!(result.amount_sat, Some(10_000));
+            prop_assert_eq!(parse_address(uri, MAINNET).unwrap().to_string(), MAINNET_P2WSH);
+
+            // An invoice on its own is not an on-chain address.
+            let result = parse_payment_uri(invoice.clone(), MAINNET);
+            prop_assert_eq!(result.err(), Some(AddressParsingError::Other));
+            let result = parse_address(format!("lightning:{invoice}"), MAINNET);
+            prop_assert_eq!(result.err(), Some(AddressParsingError::Other));
+        }
+    }
 }
@@ -0,0 +1,84 @@
+use crate::errors::Result;
+use bdk::bitcoin::Denomination;
+use perro::MapToError;
+
+const SATS_PER_BTC: u64 = 100_000_000;
+const MSATS_PER_SAT: u64 = 1_000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Amount {
+    pub sat: u64,
+    pub btc: String,
+}
+
+impl Amount {
+    pub(crate) fn from_sat(sat: u64) -> Self {
+        Self {
+            sat,
+            btc: format_btc(sat),
+        }
+    }
+}
+
+pub fn amount_from_sat(sat: u64) -> Amount {
+    Amount::from_sat(sat)
+}
+
+/// Rounds to the nearest sat, with half a sat rounded up.
+pub fn amount_from_msat(msat: u64) -> Amount {
+    let sat = msat / MSATS_PER_SAT + u64::from(msat % MSATS_PER_SAT >= MSATS_PER_SAT / 2);
+    Amount::from_sat(sat)
+}
+
+/// Amounts with more than 8 decimals are rejected instead of being rounded, as they can't be
+/// represented in sats.
+pub fn amount_from_btc(btc: String) -> Result<Amount> {
+    let amount = bdk::bitcoin::Amount::from_str_in(&btc, Denomination::Bitcoin)
+        .map_to_invalid_input("Invalid BTC amount")?;
+    Ok(Amount::from_sat(amount.to_sat()))
+}
+
+// Formats with integer arithmetic only, so that the result is exact and identical on all
+// platforms, e.g. 123456 sats are "0.00123456".
+fn format_btc(sat: u64) -> String {
+    format!("{}.{:08}", sat / SATS_PER_BTC, sat % SATS_PER_BTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount_from_sat() {
+        assert_eq!(amount_from_sat(0).btc, "0.00000000");
+        assert_eq!(amount_from_sat(123_456).btc, "0.00123456");
+        assert_eq!(
+            amount_from_sat(2_100_000_000_000_000).btc,
+            "21000000.00000000"
+        );
+        assert_eq!(amount_from_sat(u64::MAX).btc, "184467440737.09551615");
+    }
+
+    #[test]
+    fn test_amount_from_msat() {
+        assert_eq!(amount_from_msat(0).sat, 0);
+        assert_eq!(amount_from_msat(1_499).sat, 1);
+        assert_eq!(amount_from_msat(1_500).sat, 2);
+        assert_eq!(amount_from_msat(123_456_789).sat, 123_457);
+        assert_eq!(amount_from_msat(u64::MAX).sat, u64::MAX / 1_000 + 1);
+    }
+
+    #[test]
+    fn test_amount_from_btc() {
+        assert_eq!(
+            amount_from_btc("0.00123456".to_string()).unwrap(),
+            amount_from_sat(123_456)
+        );
+        assert_eq!(amount_from_btc("1".to_string()).unwrap().sat, SATS_PER_BTC);
+        assert_eq!(amount_from_btc("0.1".to_string()).unwrap().sat, 10_000_000);
+
+        for invalid in ["", "abc", "-1", "0.000000001", "1,5"] {
+            assert!(amount_from_btc(invalid.to_string()).is_err());
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use crate::errors::Result;
+use bdk::rusqlite::{params, Connection, OptionalExtension};
+use bdk::sled::{Db, Tree};
+use log::warn;
+use perro::{permanent_failure, MapToError};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+const AUDIT_LOG_TREE: &str = "audit-log";
+// The time of the latest successful sync is stored separately as well, so that it can be looked up
+// without going through the journal.
+const LAST_SYNC_TREE: &str = "audit-log-last-sync";
+const LAST_SYNCED_AT_KEY: &str = "last_synced_at";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditEventKind {
+    TxPrepared,
+    TxSigned,
+    TxBroadcast,
+    SyncSucceeded,
+    SyncFailed,
+}
+
+impl AuditEventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventKind::TxPrepared => "TxPrepared",
+            AuditEventKind::TxSigned => "TxSigned",
+            AuditEventKind::TxBroadcast => "TxBroadcast",
+            AuditEventKind::SyncSucceeded => "SyncSucceeded",
+            AuditEventKind::SyncFailed => "SyncFailed",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "TxPrepared" => Some(AuditEventKind::TxPrepared),
+            "TxSigned" => Some(AuditEventKind::TxSigned),
+            "TxBroadcast" => Some(AuditEventKind::TxBroadcast),
+            "SyncSucceeded" => Some(AuditEventKind::SyncSucceeded),
+            "SyncFailed" => Some(AuditEventKind::SyncFailed),
+            _ => None,
+        }
+    }
+}
+
+pub struct AuditEvent {
+    pub recorded_at: SystemTime,
+    pub kind: AuditEventKind,
+    pub txid: Option<String>,
+    pub details: String,
+}
+
+// A journal of wallet operations stored next to the BDK wallet databases. Events are never removed.
+pub(crate) struct AuditLog {
+    storage: AuditLogStorage,
+}
+
+enum AuditLogStorage {
+    // Keys are the timestamp in milliseconds followed by a unique id, both big endian, so that
+    // iterating the tree yields the events in chronological order.
+    Sled {
+        db: Db,
+        tree: Tree,
+        last_sync_tree: Tree,
+    },
+    Sqlite {
+        connection: Mutex<Connection>,
+    },
+}
+
+impl AuditLog {
+    pub fn open_sled(db: &Db) -> Result<Self> {
+        let tree = db
+            .open_tree(AUDIT_LOG_TREE)
+            .map_to_permanent_failure("Failed to open audit log tree")?;
+        let last_sync_tree = db
+            .open_tree(LAST_SYNC_TREE)
+            .map_to_permanent_failure("Failed to open last sync tree")?;
+        Ok(Self {
+            storage: AuditLogStorage::Sled {
+                db: db.clone(),
+                tree,
+                last_sync_tree,
+            },
+        })
+    }
+
+    pub fn open_sqlite(path: &Path) -> Result<Self> {
+        let connection =
+            Connection::open(path).map_to_permanent_failure("Failed to open audit log database")?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS audit_log (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    recorded_at INTEGER NOT NULL,
+                    kind TEXT NOT NULL,
+                    txid TEXT,
+                    details TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS audit_log_recorded_at ON audit_log (recorded_at);
+                CREATE TABLE IF NOT EXISTS last_sync (
+                    id INTEGER PRIMARY KEY CHECK (id = 0),
+                    synced_at INTEGER NOT NULL
+                );",
+            )
+            .map_to_permanent_failure("Failed to create audit log table")?;
+        Ok(Self {
+            storage: AuditLogStorage::Sqlite {
+                connection: Mutex::new(connection),
+            },
+        })
+    }
+
+    // Failing to write the audit log must not fail the wallet operation that already happened, so
+    // errors are only logged.
+    pub fn record(&self, kind: AuditEventKind, txid: Option<String>, details: impl Into<String>) {
+        if let Err(e) = self.record_at(SystemTime::now(), kind, txid, details.into()) {
+            warn!("Failed to write to the audit log: {e}");
+        }
+    }
+
+    fn record_at(
+        &self,
+        timestamp: SystemTime,
+        kind: AuditEventKind,
+        txid: Option<String>,
+        details: String,
+    ) -> Result<()> {
+        let synced = kind == AuditEventKind::SyncSucceeded;
+        match &self.storage {
+            AuditLogStorage::Sled {
+                db,
+                tree,
+                last_sync_tree,
+            } => {
+                let id = db
+                    .generate_id()
+                    .map_to_permanent_failure("Failed to generate audit log id")?;
+                let mut key = to_millis(timestamp).to_be_bytes().to_vec();
+                key.extend_from_slice(&id.to_be_bytes());
+
+                let value = json!({
+                    "kind": kind.as_str(),
+                    "txid": txid,
+                    "details": details,
+                });
+                tree.insert(key, value.to_string().as_bytes())
+                    .map_to_permanent_failure("Failed to insert into audit log tree")?;
+                tree.flush()
+                    .map_to_permanent_failure("Failed to flush audit log tree")?;
+                if synced {
+                    last_sync_tree
+                        .insert(LAST_SYNCED_AT_KEY, &to_millis(timestamp).to_be_bytes())
+                        .map_to_permanent_failure("Failed to insert into last sync tree")?;
+                    last_sync_tree
+                        .flush()
+                        .map_to_permanent_failure("Failed to flush last sync tree")?;
+                }
+            }
+            AuditLogStorage::Sqlite { connection } => {
+                let connection = connection.lock().unwrap();
+                connection
+                    .execute(
+                        "INSERT INTO audit_log (recorded_at, kind, txid, details) VALUES (?1, ?2, ?3, ?4)",
+                        params![to_millis(timestamp) as i64, kind.as_str(), txid, details],
+                    )
+                    .map_to_permanent_failure("Failed to insert into audit log table")?;
+                if synced {
+                    connection
+                        .execute(
+                            "INSERT OR REPLACE INTO last_sync (id, synced_at) VALUES (0, ?1)",
+                            params![to_millis(timestamp) as i64],
+                        )
+                        .map_to_permanent_failure("Failed to insert into last sync table")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The time of the latest SyncSucceeded event. None if the wallet has never been synced.
+    pub fn get_last_synced_at(&self) -> Result<Option<SystemTime>> {
+        let millis = match &self.storage {
+            AuditLogStorage::Sled { last_sync_tree, .. } => {
+                match last_sync_tree
+                    .get(LAST_SYNCED_AT_KEY)
+                    .map_to_permanent_failure("Failed to read last sync tree")?
+                {
+                    Some(value) => Some(u64::from_be_bytes(
+                        value[..]
+                            .try_into()
+                            .map_to_permanent_failure("Invalid last sync entry")?,
+                    )),
+                    None => None,
+                }
+            }
+            AuditLogStorage::Sqlite { connection } => connection
+                .lock()
+                .unwrap()
+                .query_row("SELECT synced_at FROM last_sync WHERE id = 0", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .optional()
+                .map_to_permanent_failure("Failed to query last sync table")?
+                .map(|millis| millis as u64),
+        };
+        Ok(millis.map(|millis| SystemTime::UNIX_EPOCH + Duration::from_millis(millis)))
+    }
+
+    // Returns the events recorded in the provided time range (both bounds inclusive) in
+    // chronological order.
+    pub fn get(&self, from: Option<SystemTime>, to: Option<SystemTime>) -> Result<Vec<AuditEvent>> {
+        let from = from.map(to_millis).unwrap_or(0);
+        let to = to.map(to_millis).unwrap_or(u64::MAX);
+        match &self.storage {
+            AuditLogStorage::Sled { tree, .. } => {
+                let mut events = Vec::new();
+                for entry in tree.range(from.to_be_bytes()..) {
+                    let (key, value) =
+                        entry.map_to_permanent_failure("Failed to read audit log tree")?;
+                    let timestamp_millis = u64::from_be_bytes(
+                        key[..8]
+                            .try_into()
+                            .map_to_permanent_failure("Invalid audit log key")?,
+                    );
+                    if timestamp_millis > to {
+                        break;
+                    }
+                    events.push(parse_event(timestamp_millis, &value)?);
+                }
+                Ok(events)
+            }
+            AuditLogStorage::Sqlite { connection } => {
+                let connection = connection.lock().unwrap();
+                let mut statement = connection
+                    .prepare(
+                        "SELECT recorded_at, kind, txid, details FROM audit_log
+                        WHERE recorded_at BETWEEN ?1 AND ?2 ORDER BY recorded_at, id",
+                    )
+                    .map_to_permanent_failure("Failed to prepare audit log query")?;
+                let to = i64::try_from(to).unwrap_or(i64::MAX);
+                let rows = statement
+                    .query_map(params![from as i64, to], |row| {
+                        Ok((
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, Option<String>>(2)?,
+                            row.get::<_, String>(3)?,
+                        ))
+                    })
+                    .map_to_permanent_failure("Failed to query audit log table")?;
+
+                let mut events = Vec::new();
+                for row in rows {
+                    let (recorded_at, kind, txid, details) =
+                        row.map_to_permanent_failure("Failed to read audit log table")?;
+                    events.push(AuditEvent {
+                        recorded_at: SystemTime::UNIX_EPOCH
+                            + Duration::from_millis(recorded_at as u64),
+                        kind: AuditEventKind::parse(&kind).ok_or_else(|| {
+                            permanent_failure("Invalid audit log entry: unknown kind")
+                        })?,
+                        txid,
+                        details,
+                    });
+                }
+                Ok(events)
+            }
+        }
+    }
+}
+
+// Exports the events as a JSON array.
+pub(crate) fn to_json(events: &[AuditEvent]) -> String {
+    let events: Vec<Value> = events
+        .iter()
+        .map(|event| {
+            json!({
+                "timestamp": to_millis(event.recorded_at),
+                "kind": event.kind.as_str(),
+                "txid": event.txid,
+                "details": event.details,
+            })
+        })
+        .collect();
+    Value::Array(events).to_string()
+}
+
+fn parse_event(timestamp_millis: u64, value: &[u8]) -> Result<AuditEvent> {
+    let value: Value =
+        serde_json::from_slice(value).map_to_permanent_failure("Invalid audit log entry")?;
+    let kind = value["kind"]
+        .as_str()
+        .and_then(AuditEventKind::parse)
+        .ok_or_else(|| permanent_failure("Invalid audit log entry: unknown kind"))?;
+    Ok(AuditEvent {
+        recorded_at: SystemTime::UNIX_EPOCH + Duration::from_millis(timestamp_millis),
+        kind,
+        txid: value["txid"].as_str().map(String::from),
+        details: value["details"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a";
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_record_and_get_sled() {
+        let db = bdk::sled::Config::new().temporary(true).open().unwrap();
+        test_record_and_get(AuditLog::open_sled(&db).unwrap());
+    }
+
+    #[test]
+    fn test_record_and_get_sqlite() {
+        test_record_and_get(AuditLog::open_sqlite(Path::new(":memory:")).unwrap());
+    }
+
+    fn test_record_and_get(audit_log: AuditLog) {
+        audit_log
+            .record_at(at(30), AuditEventKind::SyncSucceeded, None, String::new())
+            .unwrap();
+        audit_log
+            .record_at(
+                at(10),
+                AuditEventKind::TxPrepared,
+                Some(TXID.to_string()),
+                "Send tx".to_string(),
+            )
+            .unwrap();
+        audit_log
+            .record_at(
+                at(20),
+                AuditEventKind::TxBroadcast,
+                Some(TXID.to_string()),
+                String::new(),
+            )
+            .unwrap();
+
+        let events = audit_log.get(None, None).unwrap();
+        let kinds: Vec<AuditEventKind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                AuditEventKind::TxPrepared,
+                AuditEventKind::TxBroadcast,
+                AuditEventKind::SyncSucceeded,
+            ]
+        );
+        assert_eq!(events[0].recorded_at, at(10));
+        assert_eq!(events[0].txid, Some(TXID.to_string()));
+        assert_eq!(events[0].details, "Send tx");
+        assert_eq!(events[2].txid, None);
+
+        let events = audit_log.get(Some(at(20)), Some(at(30))).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, AuditEventKind::TxBroadcast);
+
+        let events = audit_log.get(None, Some(at(19))).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, AuditEventKind::TxPrepared);
+
+        assert!(audit_log.get(Some(at(31)), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_last_synced_at_sled() {
+        let db = bdk::sled::Config::new().temporary(true).open().unwrap();
+        test_last_synced_at(AuditLog::open_sled(&db).unwrap());
+    }
+
+    #[test]
+    fn test_last_synced_at_sqlite() {
+        test_last_synced_at(AuditLog::open_sqlite(Path::new(":memory:")).unwrap());
+    }
+
+    fn test_last_synced_at(audit_log: AuditLog) {
+        assert_eq!(audit_log.get_last_synced_at().unwrap(), None);
+
+        for secs in 20..30 {
+            audit_log
+                .record_at(at(secs), AuditEventKind::SyncSucceeded, None, String::new())
+                .unwrap();
+        }
+        audit_log
+            .record_at(
+                at(30),
+                AuditEventKind::SyncFailed,
+                None,
+                "Offline".to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(audit_log.get_last_synced_at().unwrap(), Some(at(29)));
+        // Every sync outcome is journaled.
+        let events = audit_log.get(None, None).unwrap();
+        assert_eq!(events.len(), 11);
+        assert_eq!(events[10].kind, AuditEventKind::SyncFailed);
+        assert_eq!(events[10].details, "Offline");
+    }
+
+    #[test]
+    fn test_to_json() {
+        let events = vec![AuditEvent {
+            recorded_at: at(10),
+            kind: AuditEventKind::TxSigned,
+            txid: Some(TXID.to_string()),
+            details: String::new(),
+        }];
+
+        let json: Value = serde_json::from_str(&to_json(&events)).unwrap();
+        assert_eq!(
+            json,
+            json!([{
+                "timestamp": 10_000,
+                "kind": "TxSigned",
+                "txid": TXID,
+                "details": "",
+            }])
+        );
+    }
+}
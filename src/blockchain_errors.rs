@@ -0,0 +1,262 @@
+use crate::errors::Error;
+use crate::WalletRuntimeErrorCode;
+use bdk::electrum_client;
+use perro::runtime_error;
+use serde_json::Value;
+use std::io::ErrorKind;
+
+// Errors of the blockchain backend, e.g. from fee estimations and syncs.
+pub(crate) fn map_blockchain_error(error: bdk::Error, msg: &str) -> Error {
+    let code = match &error {
+        bdk::Error::Electrum(e) => get_electrum_error_code(e),
+        bdk::Error::Esplora(_) => WalletRuntimeErrorCode::RemoteServiceUnavailable,
+        _ => WalletRuntimeErrorCode::GenericError,
+    };
+    runtime_error(code, format!("{msg}: {error}"))
+}
+
+// Servers respond to a broadcast with an error if the tx isn't accepted to their mempool. Other
+// errors are mapped like the ones of any other request.
+pub(crate) fn map_broadcast_error(error: bdk::Error, tx_vsize: usize, msg: &str) -> Error {
+    if let bdk::Error::Electrum(electrum_client::Error::Protocol(response)) = &error {
+        let reason = get_protocol_error_message(response);
+        if !is_server_busy(&reason) && !is_protocol_mismatch(&reason) {
+            let code = get_rejection_error_code(&reason, tx_vsize);
+            return runtime_error(code, format!("{msg}: {reason}"));
+        }
+    }
+    map_blockchain_error(error, msg)
+}
+
+// The reasons are the ones of Bitcoin Core's mempool policy, which electrum servers pass on.
+fn get_rejection_error_code(reason: &str, tx_vsize: usize) -> WalletRuntimeErrorCode {
+    let lowercase_reason = reason.to_lowercase();
+    if lowercase_reason.contains("fee not met") || lowercase_reason.contains("insufficient fee") {
+        WalletRuntimeErrorCode::TxFeeTooLow {
+            min_fee_rate_sat_per_vbyte: get_min_fee_rate(&lowercase_reason, tx_vsize),
+        }
+    } else if lowercase_reason.contains("dust") {
+        WalletRuntimeErrorCode::TxDustOutput
+    } else if lowercase_reason.contains("missingorspent")
+        || lowercase_reason.contains("missing-inputs")
+        || lowercase_reason.contains("mempool-conflict")
+    {
+        WalletRuntimeErrorCode::TxInputsMissingOrSpent
+    } else {
+        WalletRuntimeErrorCode::TxRejected {
+            reason: reason.to_string(),
+        }
+    }
+}
+
+// Bitcoin Core reports the fee of the tx and the minimum fee for its size in sats, e.g.
+// "min relay fee not met, 110 < 141" or "mempool min fee not met, 150 < 200". The rate is rounded
+// up, so that a tx paying it is accepted. Replacements are rejected with a message that doesn't
+// include the minimum.
+fn get_min_fee_rate(reason: &str, tx_vsize: usize) -> Option<u64> {
+    let (_, fees) = reason.split_once("fee not met, ")?;
+    let (_, min_fee) = fees.split_once(" < ")?;
+    let min_fee: u64 = min_fee
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()?;
+    let tx_vsize = u64::try_from(tx_vsize).ok().filter(|vsize| *vsize > 0)?;
+    Some((min_fee + tx_vsize - 1) / tx_vsize)
+}
+
+// Electrum servers don't use standardized error codes, so errors are classified by the kind of the
+// IO error or by their message.
+fn get_electrum_error_code(error: &electrum_client::Error) -> WalletRuntimeErrorCode {
+    match error {
+        electrum_client::Error::IOError(e) => get_io_error_code(e.kind(), &e.to_string()),
+        electrum_client::Error::SharedIOError(e) => get_io_error_code(e.kind(), &e.to_string()),
+        // Every attempt usually fails for the same reason, so the last one is representative.
+        electrum_client::Error::AllAttemptsErrored(errors) => match errors.last() {
+            Some(e) => get_electrum_error_code(e),
+            None => WalletRuntimeErrorCode::ElectrumServiceUnavailable,
+        },
+        electrum_client::Error::Protocol(response) => {
+            get_message_error_code(&get_protocol_error_message(response))
+        }
+        e => get_message_error_code(&e.to_string()),
+    }
+}
+
+fn get_io_error_code(kind: ErrorKind, message: &str) -> WalletRuntimeErrorCode {
+    match kind {
+        ErrorKind::ConnectionRefused => WalletRuntimeErrorCode::ElectrumConnectionRefused,
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => WalletRuntimeErrorCode::ElectrumTimeout,
+        _ => get_message_error_code(message),
+    }
+}
+
+fn get_message_error_code(message: &str) -> WalletRuntimeErrorCode {
+    if is_server_busy(message) {
+        WalletRuntimeErrorCode::ElectrumServerBusy
+    } else if is_protocol_mismatch(message) {
+        WalletRuntimeErrorCode::ElectrumProtocolMismatch
+    } else if is_tls_failure(message) {
+        WalletRuntimeErrorCode::ElectrumTlsFailure
+    } else {
+        WalletRuntimeErrorCode::ElectrumServiceUnavailable
+    }
+}
+
+// E.g. "server busy - request timed out" or "excessive resource usage" by ElectrumX.
+fn is_server_busy(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("busy") || message.contains("excessive resource usage")
+}
+
+// E.g. "unsupported protocol version: 1.4" by ElectrumX.
+fn is_protocol_mismatch(message: &str) -> bool {
+    message.to_lowercase().contains("protocol version")
+}
+
+// E.g. "invalid peer certificate: UnknownIssuer" by rustls.
+fn is_tls_failure(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("certificate") || message.contains("tls")
+}
+
+// The error object of a JSON-RPC response, e.g. {"code": 1, "message": "..."}.
+fn get_protocol_error_message(response: &Value) -> String {
+    match response {
+        Value::String(message) => message.clone(),
+        response => response["message"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| response.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io;
+
+    fn get_code(error: perro::Error<WalletRuntimeErrorCode>) -> WalletRuntimeErrorCode {
+        match error {
+            perro::Error::RuntimeError { code, .. } => code,
+            e => panic!("Unexpected error: {e}"),
+        }
+    }
+
+    fn io_error(kind: ErrorKind, message: &str) -> bdk::Error {
+        bdk::Error::Electrum(electrum_client::Error::IOError(io::Error::new(
+            kind, message,
+        )))
+    }
+
+    fn protocol_error(message: &str) -> bdk::Error {
+        bdk::Error::Electrum(electrum_client::Error::Protocol(
+            json!({"code": 1, "message": message}),
+        ))
+    }
+
+    #[test]
+    fn test_map_blockchain_error() {
+        let cases = [
+            (
+                io_error(ErrorKind::ConnectionRefused, "Connection refused"),
+                WalletRuntimeErrorCode::ElectrumConnectionRefused,
+            ),
+            (
+                io_error(ErrorKind::WouldBlock, "Resource temporarily unavailable"),
+                WalletRuntimeErrorCode::ElectrumTimeout,
+            ),
+            (
+                io_error(
+                    ErrorKind::InvalidData,
+                    "invalid peer certificate: UnknownIssuer",
+                ),
+                WalletRuntimeErrorCode::ElectrumTlsFailure,
+            ),
+            (
+                protocol_error("unsupported protocol version: 1.4"),
+                WalletRuntimeErrorCode::ElectrumProtocolMismatch,
+            ),
+            (
+                protocol_error("server busy - request timed out"),
+                WalletRuntimeErrorCode::ElectrumServerBusy,
+            ),
+            (
+                bdk::Error::Electrum(electrum_client::Error::AllAttemptsErrored(vec![
+                    electrum_client::Error::Message("Unknown".to_string()),
+                    electrum_client::Error::IOError(io::Error::new(
+                        ErrorKind::ConnectionRefused,
+                        "Connection refused",
+                    )),
+                ])),
+                WalletRuntimeErrorCode::ElectrumConnectionRefused,
+            ),
+            (
+                io_error(ErrorKind::ConnectionReset, "Connection reset by peer"),
+                WalletRuntimeErrorCode::ElectrumServiceUnavailable,
+            ),
+            (
+                bdk::Error::Generic("Unexpected".to_string()),
+                WalletRuntimeErrorCode::GenericError,
+            ),
+        ];
+        for (error, code) in cases {
+            assert_eq!(get_code(map_blockchain_error(error, "Failed")), code);
+        }
+    }
+
+    #[test]
+    fn test_map_broadcast_error() {
+        let cases = [
+            (
+                protocol_error("the transaction was rejected by network rules.\n\nmin relay fee not met, 110 < 141"),
+                WalletRuntimeErrorCode::TxFeeTooLow {
+                    min_fee_rate_sat_per_vbyte: Some(1),
+                },
+            ),
+            (
+                protocol_error("mempool min fee not met, 150 < 1411"),
+                WalletRuntimeErrorCode::TxFeeTooLow {
+                    min_fee_rate_sat_per_vbyte: Some(11),
+                },
+            ),
+            (
+                protocol_error("insufficient fee, rejecting replacement 5a1c, not enough additional fees to relay; 0.00 < 0.00000141"),
+                WalletRuntimeErrorCode::TxFeeTooLow {
+                    min_fee_rate_sat_per_vbyte: None,
+                },
+            ),
+            (
+                protocol_error("dust"),
+                WalletRuntimeErrorCode::TxDustOutput,
+            ),
+            (
+                protocol_error("the transaction was rejected by network rules.\n\nbad-txns-inputs-missingorspent"),
+                WalletRuntimeErrorCode::TxInputsMissingOrSpent,
+            ),
+            (
+                protocol_error("txn-mempool-conflict"),
+                WalletRuntimeErrorCode::TxInputsMissingOrSpent,
+            ),
+            (
+                protocol_error("non-final"),
+                WalletRuntimeErrorCode::TxRejected {
+                    reason: "non-final".to_string(),
+                },
+            ),
+            (
+                protocol_error("server busy - request timed out"),
+                WalletRuntimeErrorCode::ElectrumServerBusy,
+            ),
+            (
+                io_error(ErrorKind::TimedOut, "Timed out"),
+                WalletRuntimeErrorCode::ElectrumTimeout,
+            ),
+        ];
+        for (error, code) in cases {
+            assert_eq!(get_code(map_broadcast_error(error, 141, "Failed")), code);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use crate::descriptor::get_descriptor_networks;
+use crate::wallet::{get_confirm_in_blocks_range, MAX_CONFIRM_IN_BLOCKS, MAX_STOP_GAP};
+use crate::{BlockchainBackend, Config};
+
+use bdk::bitcoin::Network;
+use std::fs::{remove_file, File};
+use std::path::Path;
+
+// A problem of a Config that prevents creating a Wallet from it. All problems of a config are
+// reported at once, so that the app can show actionable setup errors.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ConfigProblem {
+    #[error(
+        "Invalid electrum url {url}. Please use an url like ssl://host:port or tcp://host:port"
+    )]
+    InvalidElectrumUrl { url: String },
+    #[error("At least one electrum url is required")]
+    NoElectrumUrl,
+    #[error("Invalid esplora url {url}. Please use an http:// or https:// url")]
+    InvalidEsploraUrl { url: String },
+    #[error("Invalid broadcast fallback url {url}. Please use an http:// or https:// url")]
+    InvalidBroadcastFallbackUrl { url: String },
+    #[error("{msg}")]
+    InvalidDescriptor { msg: String },
+    #[error("The watch descriptor is for {descriptor_network}, but the network is {network}")]
+    DescriptorNetworkMismatch {
+        network: Network,
+        descriptor_network: Network,
+    },
+    #[error("The wallet db path {path} isn't a writable directory")]
+    DatabasePathNotWritable { path: String },
+    #[error(
+        "Invalid stop gap. Please use a stop gap in the range [1; {}]",
+        MAX_STOP_GAP
+    )]
+    InvalidStopGap,
+    #[error("Invalid timeout. Please use a timeout of at least 1 second")]
+    InvalidTimeout,
+    #[error("A timeout can't be combined with a SOCKS5 proxy for an electrum backend")]
+    TimeoutWithSocks5Proxy,
+    #[error(
+        "Invalid block confirmation target range. Please use a range within [1; {}]",
+        MAX_CONFIRM_IN_BLOCKS
+    )]
+    InvalidConfirmationTargetRange,
+}
+
+impl Config {
+    // Only checks the config locally, neither the blockchain backend nor the database are
+    // accessed. An empty list means that the config is valid.
+    pub fn validate(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+
+        let is_electrum = match &self.blockchain_backend {
+            BlockchainBackend::Electrum { url } => {
+                check_electrum_url(url, &mut problems);
+                true
+            }
+            BlockchainBackend::ElectrumPool { urls } => {
+                if urls.is_empty() {
+                    problems.push(ConfigProblem::NoElectrumUrl);
+                }
+                for url in urls {
+                    check_electrum_url(url, &mut problems);
+                }
+                true
+            }
+            BlockchainBackend::Esplora { url } => {
+                if !is_http_url(url) {
+                    problems.push(ConfigProblem::InvalidEsploraUrl { url: url.clone() });
+                }
+                false
+            }
+        };
+        if let Some(url) = &self.broadcast_fallback_url {
+            if !is_http_url(url) {
+                problems.push(ConfigProblem::InvalidBroadcastFallbackUrl { url: url.clone() });
+            }
+        }
+
+        match get_descriptor_networks(&self.watch_descriptor) {
+            Ok(networks) => {
+                if let Some(descriptor_network) = networks
+                    .into_iter()
+                    .find(|n| !is_same_kind_of_network(*n, self.network))
+                {
+                    problems.push(ConfigProblem::DescriptorNetworkMismatch {
+                        network: self.network,
+                        descriptor_network,
+                    });
+                }
+            }
+            Err(perro::Error::InvalidInput { msg }) => {
+                problems.push(ConfigProblem::InvalidDescriptor { msg })
+            }
+            Err(e) => problems.push(ConfigProblem::InvalidDescriptor { msg: e.to_string() }),
+        }
+
+        if !is_writable_dir(Path::new(&self.wallet_db_path)) {
+            problems.push(ConfigProblem::DatabasePathNotWritable {
+                path: self.wallet_db_path.clone(),
+            });
+        }
+
+        if let Some(stop_gap) = self.stop_gap {
+            if !(1..=MAX_STOP_GAP).contains(&stop_gap) {
+                problems.push(ConfigProblem::InvalidStopGap);
+            }
+        }
+        if self.timeout_secs == Some(0) {
+            problems.push(ConfigProblem::InvalidTimeout);
+        }
+        if is_electrum && self.socks5_proxy.is_some() && self.timeout_secs.is_some() {
+            problems.push(ConfigProblem::TimeoutWithSocks5Proxy);
+        }
+
+        if get_confirm_in_blocks_range(self.min_confirm_in_blocks, self.max_confirm_in_blocks)
+            .is_err()
+        {
+            problems.push(ConfigProblem::InvalidConfirmationTargetRange);
+        }
+
+        problems
+    }
+}
+
+pub fn validate_config(config: Config) -> Vec<ConfigProblem> {
+    config.validate()
+}
+
+// Without a scheme, the electrum client connects over plain TCP, same as with "tcp://".
+fn check_electrum_url(url: &str, problems: &mut Vec<ConfigProblem>) {
+    let address = match url.split_once("://") {
+        Some(("ssl" | "tcp", address)) => address,
+        Some(_) => "",
+        None => url,
+    };
+    let is_valid = matches!(
+        address.rsplit_once(':'),
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok()
+    );
+    if !is_valid {
+        problems.push(ConfigProblem::InvalidElectrumUrl {
+            url: url.to_string(),
+        });
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    matches!(
+        url.split_once("://"),
+        Some(("http" | "https", rest)) if !rest.is_empty()
+    )
+}
+
+// Extended keys only tell mainnet from the test networks apart.
+fn is_same_kind_of_network(a: Network, b: Network) -> bool {
+    (a == Network::Bitcoin) == (b == Network::Bitcoin)
+}
+
+// The databases are created below the nearest existing ancestor of the path. Permissions alone
+// don't tell whether the app may write there (e.g. sandboxes), so a probe file is written.
+fn is_writable_dir(path: &Path) -> bool {
+    if path.as_os_str().is_empty() {
+        return false;
+    }
+    let dir = path
+        .ancestors()
+        .map(|p| {
+            if p.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                p
+            }
+        })
+        .find(|p| p.exists());
+    let dir = match dir {
+        Some(dir) if dir.is_dir() => dir,
+        _ => return false,
+    };
+    let probe = dir.join(format!(".lbl-write-probe-{}", std::process::id()));
+    match File::create(&probe) {
+        Ok(_) => {
+            let _ = remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DatabaseBackend;
+    use std::fs::{remove_dir_all, write};
+
+    const TESTNET_WATCH_DESCRIPTOR: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/0/*)";
+    const MAINNET_WATCH_DESCRIPTOR: &str = "wpkh([ddd71d79/84'/0'/0']xpub6Cg6Y9ynKKSjZ1EwscvwerJMU1PPPcdhjr2tQ783zE31NUfAF1EMY4qiEBfKkExF3eBruUiSpGZLeCaFiJZSeh3HzAjNANx3TT8QxdN8GUd/0/*)";
+
+    fn config() -> Config {
+        Config {
+            blockchain_backend: BlockchainBackend::Electrum {
+                url: "ssl://electrum.blockstream.info:60002".to_string(),
+            },
+            wallet_db_path: ".bdk-database-validate-config/wallet".to_string(),
+            network: Network::Testnet,
+            watch_descriptor: TESTNET_WATCH_DESCRIPTOR.to_string(),
+            stop_gap: None,
+            database: DatabaseBackend::Sled,
+            socks5_proxy: None,
+            timeout_secs: None,
+            electrum_retries: None,
+            broadcast_fallback_url: None,
+            min_confirm_in_blocks: None,
+            max_confirm_in_blocks: None,
+        }
+    }
+
+    #[test]
+    fn test_valid_config() {
+        assert_eq!(config().validate(), Vec::new());
+
+        let config = Config {
+            blockchain_backend: BlockchainBackend::ElectrumPool {
+                urls: vec![
+                    "tcp://127.0.0.1:50001".to_string(),
+                    "localhost:50000".to_string(),
+                ],
+            },
+            network: Network::Regtest,
+            broadcast_fallback_url: Some("https://mempool.space/testnet/api".to_string()),
+            min_confirm_in_blocks: Some(6),
+            ..config()
+        };
+        assert_eq!(config.validate(), Vec::new());
+    }
+
+    #[test]
+    fn test_invalid_urls() {
+        for url in [
+            "https://electrum.blockstream.info:60002",
+            "ssl://electrum.blockstream.info",
+            "ssl://:60002",
+            "tcp://localhost:port",
+            "",
+        ] {
+            let config = Config {
+                blockchain_backend: BlockchainBackend::Electrum {
+                    url: url.to_string(),
+                },
+                ..config()
+            };
+            assert_eq!(
+                config.validate(),
+                vec![ConfigProblem::InvalidElectrumUrl {
+                    url: url.to_string()
+                }],
+                "{url}"
+            );
+        }
+
+        let config = Config {
+            blockchain_backend: BlockchainBackend::ElectrumPool { urls: Vec::new() },
+            ..config()
+        };
+        assert_eq!(config.validate(), vec![ConfigProblem::NoElectrumUrl]);
+
+        let config = Config {
+            blockchain_backend: BlockchainBackend::Esplora {
+                url: "blockstream.info/testnet/api".to_string(),
+            },
+            broadcast_fallback_url: Some("ssl://mempool.space".to_string()),
+            ..config()
+        };
+        assert_eq!(
+            config.validate(),
+            vec![
+                ConfigProblem::InvalidEsploraUrl {
+                    url: "blockstream.info/testnet/api".to_string()
+                },
+                ConfigProblem::InvalidBroadcastFallbackUrl {
+                    url: "ssl://mempool.space".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_descriptor_network_mismatch() {
+        let config = Config {
+            network: Network::Bitcoin,
+            ..config()
+        };
+        assert_eq!(
+            config.validate(),
+            vec![ConfigProblem::DescriptorNetworkMismatch {
+                network: Network::Bitcoin,
+                descriptor_network: Network::Testnet,
+            }]
+        );
+
+        let config = Config {
+            network: Network::Signet,
+            watch_descriptor: MAINNET_WATCH_DESCRIPTOR.to_string(),
+            ..config
+        };
+        assert_eq!(
+            config.validate(),
+            vec![ConfigProblem::DescriptorNetworkMismatch {
+                network: Network::Signet,
+                descriptor_network: Network::Bitcoin,
+            }]
+        );
+
+        let config = Config {
+            watch_descriptor: "wpkh(invalid)".to_string(),
+            ..config
+        };
+        assert!(matches!(
+            config.validate().as_slice(),
+            [ConfigProblem::InvalidDescriptor { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_db_path_not_writable() {
+        let _ = remove_dir_all(".bdk-database-not-writable");
+        std::fs::create_dir_all(".bdk-database-not-writable").unwrap();
+        write(".bdk-database-not-writable/file", "").unwrap();
+
+        for path in ["", ".bdk-database-not-writable/file/wallet"] {
+            let config = Config {
+                wallet_db_path: path.to_string(),
+                ..config()
+            };
+            assert_eq!(
+                config.validate(),
+                vec![ConfigProblem::DatabasePathNotWritable {
+                    path: path.to_string()
+                }]
+            );
+        }
+
+        remove_dir_all(".bdk-database-not-writable").unwrap();
+    }
+
+    #[test]
+    fn test_all_problems_are_reported() {
+        let config = Config {
+            blockchain_backend: BlockchainBackend::Electrum {
+                url: "electrum.blockstream.info".to_string(),
+            },
+            network: Network::Bitcoin,
+            stop_gap: Some(0),
+            socks5_proxy: Some("127.0.0.1:9050".to_string()),
+            timeout_secs: Some(0),
+            min_confirm_in_blocks: Some(30),
+            ..config()
+        };
+        assert_eq!(
+            config.validate(),
+            vec![
+                ConfigProblem::InvalidElectrumUrl {
+                    url: "electrum.blockstream.info".to_string()
+                },
+                ConfigProblem::DescriptorNetworkMismatch {
+                    network: Network::Bitcoin,
+                    descriptor_network: Network::Testnet,
+                },
+                ConfigProblem::InvalidStopGap,
+                ConfigProblem::InvalidTimeout,
+                ConfigProblem::TimeoutWithSocks5Proxy,
+                ConfigProblem::InvalidConfirmationTargetRange,
+            ]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::errors::Result;
+use crate::signing::parse_secret_key;
+use bdk::bitcoin::hashes::hex::FromHex;
+use bdk::bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bdk::bitcoin::hashes::{sha256, Hash, HashEngine};
+use bdk::bitcoin::secp256k1::ecdh::SharedSecret;
+use bdk::bitcoin::secp256k1::PublicKey;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use perro::{invalid_input, MapToError};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use secp256k1::SECP256K1;
+
+// ECIES encryption to secp256k1 public keys.
+//
+// Ciphertext format v1:
+// version (1 byte) | ephemeral public key (33 bytes) | nonce (24 bytes) | ciphertext
+//
+// The encryption key is derived with HKDF-SHA256 from the ECDH shared secret (the SHA256 of the
+// compressed shared point, as computed by libsecp256k1), using the ephemeral public key as salt.
+// The payload is encrypted using XChaCha20-Poly1305 with the version byte as associated data.
+const VERSION_1: u8 = 1;
+const PUBLIC_KEY_LEN: usize = 33;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 1 + PUBLIC_KEY_LEN + NONCE_LEN;
+const HKDF_INFO: &[u8] = b"lipa-business-lib ecies v1";
+
+pub fn encrypt_to_pubkey(plaintext: Vec<u8>, public_key: String) -> Result<Vec<u8>> {
+    let public_key_bytes =
+        Vec::from_hex(&public_key).map_to_invalid_input("Invalid public key string")?;
+    let public_key = PublicKey::from_slice(public_key_bytes.as_slice())
+        .map_to_invalid_input("Invalid public key string")?;
+
+    let (ephemeral_secret_key, ephemeral_public_key) = SECP256K1.generate_keypair(&mut OsRng);
+
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng
+        .try_fill_bytes(&mut nonce)
+        .map_to_permanent_failure("Failed to generate random bytes using OsRng")?;
+
+    let shared_secret = SharedSecret::new(&public_key, &ephemeral_secret_key);
+    let cipher = derive_cipher(&shared_secret, &ephemeral_public_key);
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: &[VERSION_1],
+            },
+        )
+        .map_to_permanent_failure("Failed to encrypt payload")?;
+
+    let mut blob = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    blob.push(VERSION_1);
+    blob.extend_from_slice(&ephemeral_public_key.serialize());
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&ciphertext);
+    Ok(blob)
+}
+
+pub fn decrypt_with_secret_key(ciphertext: Vec<u8>, secret_key: String) -> Result<Vec<u8>> {
+    let secret_key = parse_secret_key(&secret_key)?;
+
+    if ciphertext.len() < HEADER_LEN {
+        return Err(invalid_input("Invalid ciphertext: too short"));
+    }
+    let (version, rest) = ciphertext.split_at(1);
+    if version[0] != VERSION_1 {
+        return Err(invalid_input(format!(
+            "Invalid ciphertext: unsupported version {}",
+            version[0]
+        )));
+    }
+    let (ephemeral_public_key, rest) = rest.split_at(PUBLIC_KEY_LEN);
+    let (nonce, encrypted_payload) = rest.split_at(NONCE_LEN);
+    let ephemeral_public_key = PublicKey::from_slice(ephemeral_public_key)
+        .map_to_invalid_input("Invalid ciphertext: invalid ephemeral public key")?;
+
+    let shared_secret = SharedSecret::new(&ephemeral_public_key, &secret_key);
+    let cipher = derive_cipher(&shared_secret, &ephemeral_public_key);
+    cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: encrypted_payload,
+                aad: version,
+            },
+        )
+        .map_to_invalid_input("Failed to decrypt: wrong key or corrupted ciphertext")
+}
+
+// HKDF-SHA256 (RFC 5869) producing a single block of output key material.
+fn derive_cipher(
+    shared_secret: &SharedSecret,
+    ephemeral_public_key: &PublicKey,
+) -> XChaCha20Poly1305 {
+    let mut extract = HmacEngine::<sha256::Hash>::new(&ephemeral_public_key.serialize());
+    extract.input(&shared_secret.secret_bytes());
+    let prk = Hmac::<sha256::Hash>::from_engine(extract);
+
+    let mut expand = HmacEngine::<sha256::Hash>::new(&prk.into_inner());
+    expand.input(HKDF_INFO);
+    expand.input(&[1]);
+    let okm = Hmac::<sha256::Hash>::from_engine(expand);
+
+    XChaCha20Poly1305::new(Key::from_slice(&okm.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{derive_keys, generate_keypair, generate_mnemonic};
+    use bdk::bitcoin::Network;
+
+    const PAYLOAD: &[u8] = b"Payout instructions";
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let keypair = generate_keypair();
+
+        let ciphertext = encrypt_to_pubkey(PAYLOAD.to_vec(), keypair.public_key.clone()).unwrap();
+        assert_eq!(ciphertext[0], VERSION_1);
+        assert_eq!(ciphertext.len(), HEADER_LEN + PAYLOAD.len() + 16);
+        // A fresh ephemeral key is used for every encryption.
+        assert_ne!(
+            ciphertext,
+            encrypt_to_pubkey(PAYLOAD.to_vec(), keypair.public_key).unwrap()
+        );
+
+        let plaintext =
+            decrypt_with_secret_key(ciphertext.clone(), keypair.secret_key.clone()).unwrap();
+        assert_eq!(plaintext, PAYLOAD.to_vec());
+
+        let other_keypair = generate_keypair();
+        let result = decrypt_with_secret_key(ciphertext.clone(), other_keypair.secret_key.clone());
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Failed to decrypt: wrong key or corrupted ciphertext"));
+
+        let mut tampered_ciphertext = ciphertext;
+        *tampered_ciphertext.last_mut().unwrap() ^= 1;
+        assert!(decrypt_with_secret_key(tampered_ciphertext, keypair.secret_key).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_to_wallet_keypair() {
+        let keys = derive_keys(Network::Testnet, generate_mnemonic().unwrap()).unwrap();
+
+        // The wallet's auth public key is serialized uncompressed.
+        let ciphertext =
+            encrypt_to_pubkey(PAYLOAD.to_vec(), keys.wallet_keypair.public_key).unwrap();
+        let plaintext =
+            decrypt_with_secret_key(ciphertext, keys.wallet_keypair.secret_key).unwrap();
+
+        assert_eq!(plaintext, PAYLOAD.to_vec());
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let keypair = generate_keypair();
+
+        assert!(encrypt_to_pubkey(PAYLOAD.to_vec(), "04e2ad".to_string()).is_err());
+
+        let result = decrypt_with_secret_key(vec![VERSION_1; 10], keypair.secret_key.clone());
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Invalid ciphertext: too short"));
+
+        let mut ciphertext = encrypt_to_pubkey(PAYLOAD.to_vec(), keypair.public_key).unwrap();
+        ciphertext[0] = 2;
+        let result = decrypt_with_secret_key(ciphertext, keypair.secret_key);
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Invalid ciphertext: unsupported version 2"));
+    }
+}
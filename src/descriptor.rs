@@ -0,0 +1,352 @@
+use crate::errors::{Error, Result};
+
+use bdk::bitcoin::hashes::hex::ToHex;
+use bdk::bitcoin::hashes::{sha256, Hash};
+use bdk::bitcoin::util::bip32::{ChildNumber, DerivationPath};
+use bdk::bitcoin::Network;
+use bdk::descriptor::calc_checksum;
+use bdk::miniscript::descriptor::{DescriptorPublicKey, DescriptorSecretKey, Wildcard};
+use bdk::miniscript::{Descriptor, ForEachKey, Translator};
+use bdk::KeychainKind;
+use perro::{invalid_input, MapToError};
+use std::str::FromStr;
+
+const MULTIPATH_STEP: &str = "/<0;1>/";
+
+/// Derives the descriptor for receiving addresses from a watch or spend descriptor.
+///
+/// Accepts descriptors with or without checksum and with either a receive path ("/0/*") or a
+/// multipath ("/<0;1>/*") on every ranged key.
+pub(crate) fn get_receive_descriptor(descriptor: &str) -> Result<String> {
+    derive_keychain_descriptor(descriptor, KeychainKind::External)
+}
+
+/// Derives the descriptor for change addresses from a watch or spend descriptor.
+///
+/// Accepts descriptors with or without checksum and with either a receive path ("/0/*") or a
+/// multipath ("/<0;1>/*") on every ranged key.
+pub(crate) fn get_change_descriptor(descriptor: &str) -> Result<String> {
+    derive_keychain_descriptor(descriptor, KeychainKind::Internal)
+}
+
+/// Appends the BIP-380 checksum to a descriptor that doesn't have one yet.
+pub(crate) fn append_checksum(descriptor: &str) -> Result<String> {
+    let checksum = calc_checksum(descriptor)
+        .map_to_permanent_failure("Failed to compute descriptor checksum")?;
+    Ok(format!("{descriptor}#{checksum}"))
+}
+
+/// Computes a stable identifier for the wallet of a watch or spend descriptor: the hex encoded
+/// SHA-256 hash of its receive descriptor without checksum.
+///
+/// Equivalent notations of the same descriptor (checksum, multipath, "h" or "'" for hardened
+/// steps, surrounding whitespace) result in the same identifier.
+pub(crate) fn get_descriptor_id(descriptor: &str) -> Result<String> {
+    let receive_descriptor = get_receive_descriptor(descriptor)?;
+    Ok(sha256::Hash::hash(receive_descriptor.as_bytes()).to_hex())
+}
+
+/// Returns the networks of the extended keys of a watch or spend descriptor. Extended keys of all
+/// test networks have the network `Network::Testnet`. Single keys don't belong to a network.
+pub(crate) fn get_descriptor_networks(descriptor: &str) -> Result<Vec<Network>> {
+    let receive_descriptor = get_receive_descriptor(descriptor)?;
+    let parsed = Descriptor::<String>::from_str(&receive_descriptor)
+        .map_to_invalid_input("Invalid descriptor: Failed to parse descriptor")?;
+
+    let mut networks = Vec::new();
+    parsed.for_each_key(|key| {
+        if let Ok(DescriptorPublicKey::XPub(xkey)) = DescriptorPublicKey::from_str(key) {
+            networks.push(xkey.xkey.network);
+        } else if let Ok(DescriptorSecretKey::XPrv(xkey)) = DescriptorSecretKey::from_str(key) {
+            networks.push(xkey.xkey.network);
+        }
+        true
+    });
+    Ok(networks)
+}
+
+fn derive_keychain_descriptor(descriptor: &str, keychain: KeychainKind) -> Result<String> {
+    // Parsing with string keys keeps private keys intact, so the same code works for watch and
+    // spend descriptors. A checksum suffix is optional, but gets verified if present.
+    let parsed = Descriptor::<String>::from_str(descriptor.trim())
+        .map_to_invalid_input("Invalid descriptor: Failed to parse descriptor")?;
+
+    let mut translator = KeychainTranslator {
+        keychain_index: match keychain {
+            KeychainKind::External => 0,
+            KeychainKind::Internal => 1,
+        },
+        ranged_keys: 0,
+    };
+    let derived = parsed.translate_pk(&mut translator)?;
+
+    if translator.ranged_keys == 0 {
+        return Err(invalid_input(
+            "Invalid descriptor: Descriptor doesn't contain any ranged key",
+        ));
+    }
+
+    // Display appends a checksum, strip it to return the plain descriptor.
+    let derived = derived.to_string();
+    let derived = derived.split('#').next().unwrap_or_default();
+    Ok(derived.to_string())
+}
+
+/// Points every ranged key of a descriptor to the chain of the given keychain.
+struct KeychainTranslator {
+    keychain_index: u32,
+    ranged_keys: usize,
+}
+
+impl KeychainTranslator {
+    fn derive_path(
+        &mut self,
+        derivation_path: &mut DerivationPath,
+        wildcard: Wildcard,
+    ) -> Result<()> {
+        if matches!(wildcard, Wildcard::None) {
+            return Ok(());
+        }
+
+        let mut path: Vec<ChildNumber> = derivation_path.clone().into();
+        match path.last_mut() {
+            Some(step @ ChildNumber::Normal { index: 0 }) => {
+                *step = ChildNumber::Normal {
+                    index: self.keychain_index,
+                };
+            }
+            _ => {
+                return Err(invalid_input(
+                    "Invalid descriptor: Ranged key doesn't derive from \"0/*\". Could it already be a change descriptor?",
+                ))
+            }
+        }
+        *derivation_path = path.into();
+        self.ranged_keys += 1;
+        Ok(())
+    }
+}
+
+impl Translator<String, String, Error> for KeychainTranslator {
+    fn pk(&mut self, pk: &String) -> Result<String> {
+        // Multipath keys aren't supported by the miniscript version we depend on, so the step is
+        // resolved before the key gets parsed.
+        if pk.contains(MULTIPATH_STEP) {
+            let key = pk.replacen(MULTIPATH_STEP, &format!("/{}/", self.keychain_index), 1);
+            if DescriptorPublicKey::from_str(&key).is_err()
+                && DescriptorSecretKey::from_str(&key).is_err()
+            {
+                return Err(invalid_input(format!(
+                    "Invalid descriptor: Invalid key {pk}"
+                )));
+            }
+            self.ranged_keys += 1;
+            return Ok(key);
+        }
+
+        if let Ok(key) = DescriptorPublicKey::from_str(pk) {
+            return match key {
+                DescriptorPublicKey::Single(_) => Ok(pk.clone()),
+                DescriptorPublicKey::XPub(mut xkey) => {
+                    self.derive_path(&mut xkey.derivation_path, xkey.wildcard)?;
+                    Ok(DescriptorPublicKey::XPub(xkey).to_string())
+                }
+            };
+        }
+
+        let key = DescriptorSecretKey::from_str(pk)
+            .map_to_invalid_input("Invalid descriptor: Invalid key")?;
+        match key {
+            DescriptorSecretKey::Single(_) => Ok(pk.clone()),
+            DescriptorSecretKey::XPrv(mut xkey) => {
+                self.derive_path(&mut xkey.derivation_path, xkey.wildcard)?;
+                Ok(DescriptorSecretKey::XPrv(xkey).to_string())
+            }
+        }
+    }
+
+    fn sha256(&mut self, sha256: &String) -> Result<String> {
+        Ok(sha256.clone())
+    }
+
+    fn hash256(&mut self, hash256: &String) -> Result<String> {
+        Ok(hash256.clone())
+    }
+
+    fn ripemd160(&mut self, ripemd160: &String) -> Result<String> {
+        Ok(ripemd160.clone())
+    }
+
+    fn hash160(&mut self, hash160: &String) -> Result<String> {
+        Ok(hash160.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAINNET_WATCH_DESCRIPTOR: &str = "wpkh([ddd71d79/84'/0'/0']xpub6Cg6Y9ynKKSjZ1EwscvwerJMU1PPPcdhjr2tQ783zE31NUfAF1EMY4qiEBfKkExF3eBruUiSpGZLeCaFiJZSeh3HzAjNANx3TT8QxdN8GUd/0/*)";
+    const MAINNET_WATCH_DESCRIPTOR_CHANGE: &str = "wpkh([ddd71d79/84'/0'/0']xpub6Cg6Y9ynKKSjZ1EwscvwerJMU1PPPcdhjr2tQ783zE31NUfAF1EMY4qiEBfKkExF3eBruUiSpGZLeCaFiJZSeh3HzAjNANx3TT8QxdN8GUd/1/*)";
+
+    const TESTNET_WATCH_DESCRIPTOR: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/0/*)";
+    const TESTNET_WATCH_DESCRIPTOR_CHANGE: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/1/*)";
+    const TESTNET_WATCH_DESCRIPTOR_MULTIPATH: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/<0;1>/*)";
+
+    const TESTNET_TAPROOT_WATCH_DESCRIPTOR: &str = "tr([aed2a027/86'/1'/0']tpubDDt26we3uvM1pEW3ztPbRYEzerwF9LLgaghX1BaUXuKsqvPFBrDisaQBvsEAbNmPgi33H2UdFVjiuFmze4uFZgQQx8X5wYF7bhK7SisDn6Q/0/*)";
+    const TESTNET_TAPROOT_WATCH_DESCRIPTOR_CHANGE: &str = "tr([aed2a027/86'/1'/0']tpubDDt26we3uvM1pEW3ztPbRYEzerwF9LLgaghX1BaUXuKsqvPFBrDisaQBvsEAbNmPgi33H2UdFVjiuFmze4uFZgQQx8X5wYF7bhK7SisDn6Q/1/*)";
+
+    const TESTNET_SPEND_DESCRIPTOR: &str = "wpkh([aed2a027]tprv8ZgxMBicQKsPeT4bcpTNiHtBXqHRRPh4qMkWP4PahRJCGLd5A32RYUif9PJ8GMChWPB6yFFNGybZRGBFcsb9v9YifukeysfDAHDTzxRrtbi/84'/1'/0'/0/*)";
+    const TESTNET_SPEND_DESCRIPTOR_CHANGE: &str = "wpkh([aed2a027]tprv8ZgxMBicQKsPeT4bcpTNiHtBXqHRRPh4qMkWP4PahRJCGLd5A32RYUif9PJ8GMChWPB6yFFNGybZRGBFcsb9v9YifukeysfDAHDTzxRrtbi/84'/1'/0'/1/*)";
+
+    const MULTISIG_WATCH_DESCRIPTOR: &str = "wsh(sortedmulti(2,[aed2a027/48'/1'/0'/2']tpubDFiSiEGzQSwPukWTpLaqsBgW49bg2ehZkjbodvSmGvsxvn1K6bbPKesfMLyrbVaz3C79V4uAsuS6kypWKuuB36giWXffqjZiiPtH9kNtxad/0/*,[73c5da0a/48'/1'/0'/2']tpubDFH9dgzveyD8zTbPUFuLrGmCydNvxehyNdUXKJAQN8x4aZ4j6UZqGfnqFrD4NqyaTVGKbvEW54tsvPTK2UoSbCC1PJY8iCNiwTL3RWZEheQ/0/*,[b8688df1/48'/1'/0'/2']tpubDEfobrrtptRTbKf4gysDhoabneABDTAcdj3Vbn4XwPsLE2pmqpizSPRG6zHsbAMuiSgWmWPsYCLHTKTPpyrGJ5rAoTpKoQNZcxodiPf2tSJ/0/*))#ykh0uvlu";
+    const MULTISIG_WATCH_DESCRIPTOR_CHANGE: &str = "wsh(sortedmulti(2,[aed2a027/48'/1'/0'/2']tpubDFiSiEGzQSwPukWTpLaqsBgW49bg2ehZkjbodvSmGvsxvn1K6bbPKesfMLyrbVaz3C79V4uAsuS6kypWKuuB36giWXffqjZiiPtH9kNtxad/1/*,[73c5da0a/48'/1'/0'/2']tpubDFH9dgzveyD8zTbPUFuLrGmCydNvxehyNdUXKJAQN8x4aZ4j6UZqGfnqFrD4NqyaTVGKbvEW54tsvPTK2UoSbCC1PJY8iCNiwTL3RWZEheQ/1/*,[b8688df1/48'/1'/0'/2']tpubDEfobrrtptRTbKf4gysDhoabneABDTAcdj3Vbn4XwPsLE2pmqpizSPRG6zHsbAMuiSgWmWPsYCLHTKTPpyrGJ5rAoTpKoQNZcxodiPf2tSJ/1/*))";
+
+    const INVALID_WATCH_DESCRIPTOR: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH/0/*)K924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/0/*)";
+
+    #[test]
+    fn test_get_change_descriptor() {
+        assert_eq!(
+            MAINNET_WATCH_DESCRIPTOR_CHANGE,
+            get_change_descriptor(MAINNET_WATCH_DESCRIPTOR).unwrap()
+        );
+
+        assert_eq!(
+            TESTNET_WATCH_DESCRIPTOR_CHANGE,
+            get_change_descriptor(TESTNET_WATCH_DESCRIPTOR).unwrap()
+        );
+
+        assert_eq!(
+            TESTNET_TAPROOT_WATCH_DESCRIPTOR_CHANGE,
+            get_change_descriptor(TESTNET_TAPROOT_WATCH_DESCRIPTOR).unwrap()
+        );
+
+        assert_eq!(
+            TESTNET_SPEND_DESCRIPTOR_CHANGE,
+            get_change_descriptor(TESTNET_SPEND_DESCRIPTOR).unwrap()
+        );
+
+        assert_eq!(
+            MULTISIG_WATCH_DESCRIPTOR_CHANGE,
+            get_change_descriptor(MULTISIG_WATCH_DESCRIPTOR).unwrap()
+        );
+
+        let result = get_change_descriptor(MAINNET_WATCH_DESCRIPTOR_CHANGE);
+        assert!(result.is_err());
+        assert!(result.err().unwrap().to_string().contains(
+            "Invalid descriptor: Ranged key doesn't derive from \"0/*\". Could it already be a change descriptor?"
+        ));
+
+        let result = get_change_descriptor(INVALID_WATCH_DESCRIPTOR);
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Invalid descriptor"));
+    }
+
+    #[test]
+    fn test_get_descriptors_with_checksum() {
+        let descriptor_with_checksum =
+            Descriptor::<DescriptorPublicKey>::from_str(TESTNET_WATCH_DESCRIPTOR)
+                .unwrap()
+                .to_string();
+        assert!(descriptor_with_checksum.contains('#'));
+
+        assert_eq!(
+            TESTNET_WATCH_DESCRIPTOR,
+            get_receive_descriptor(&descriptor_with_checksum).unwrap()
+        );
+        assert_eq!(
+            TESTNET_WATCH_DESCRIPTOR_CHANGE,
+            get_change_descriptor(&descriptor_with_checksum).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_descriptors_with_invalid_checksum() {
+        let descriptor = format!("{TESTNET_WATCH_DESCRIPTOR}#aaaaaaaa");
+
+        let result = get_change_descriptor(&descriptor);
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Invalid descriptor: Failed to parse descriptor"));
+    }
+
+    #[test]
+    fn test_get_descriptors_ignores_surrounding_whitespace() {
+        let descriptor = format!(" {TESTNET_WATCH_DESCRIPTOR}\n");
+
+        assert_eq!(
+            TESTNET_WATCH_DESCRIPTOR,
+            get_receive_descriptor(&descriptor).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_append_checksum() {
+        assert_eq!(
+            append_checksum(TESTNET_WATCH_DESCRIPTOR).unwrap(),
+            format!("{TESTNET_WATCH_DESCRIPTOR}#kfnxpz7u")
+        );
+    }
+
+    #[test]
+    fn test_get_descriptors_from_multipath_descriptor() {
+        assert_eq!(
+            TESTNET_WATCH_DESCRIPTOR,
+            get_receive_descriptor(TESTNET_WATCH_DESCRIPTOR_MULTIPATH).unwrap()
+        );
+        assert_eq!(
+            TESTNET_WATCH_DESCRIPTOR_CHANGE,
+            get_change_descriptor(TESTNET_WATCH_DESCRIPTOR_MULTIPATH).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_descriptor_id() {
+        // The identifier is persisted by apps, so it must never change.
+        let id = get_descriptor_id(TESTNET_WATCH_DESCRIPTOR).unwrap();
+        assert_eq!(
+            id,
+            "d8a0f06ed9e5b03a4c3e4205b49dd2ef834da8efab65bde98a321cdc3cf71fe8"
+        );
+
+        let equivalent_descriptors = [
+            append_checksum(TESTNET_WATCH_DESCRIPTOR).unwrap(),
+            TESTNET_WATCH_DESCRIPTOR_MULTIPATH.to_string(),
+            TESTNET_WATCH_DESCRIPTOR.replace('\'', "h"),
+            format!(" {TESTNET_WATCH_DESCRIPTOR}\n"),
+        ];
+        for descriptor in equivalent_descriptors {
+            assert_eq!(get_descriptor_id(&descriptor).unwrap(), id);
+        }
+
+        assert_ne!(
+            get_descriptor_id(TESTNET_TAPROOT_WATCH_DESCRIPTOR).unwrap(),
+            id
+        );
+        assert!(get_descriptor_id(INVALID_WATCH_DESCRIPTOR).is_err());
+    }
+
+    #[test]
+    fn test_get_descriptor_networks() {
+        assert_eq!(
+            get_descriptor_networks(MAINNET_WATCH_DESCRIPTOR).unwrap(),
+            vec![Network::Bitcoin]
+        );
+        assert_eq!(
+            get_descriptor_networks(TESTNET_SPEND_DESCRIPTOR).unwrap(),
+            vec![Network::Testnet]
+        );
+        assert_eq!(
+            get_descriptor_networks(MULTISIG_WATCH_DESCRIPTOR).unwrap(),
+            vec![Network::Testnet; 3]
+        );
+        assert!(get_descriptor_networks(INVALID_WATCH_DESCRIPTOR).is_err());
+    }
+}
@@ -1,17 +1,78 @@
 use std::fmt::{Display, Formatter};
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct FundsShortfall {
+    pub required_sat: u64,
+    pub available_sat: u64,
+    pub fee_sat: u64,
+    pub missing_sat: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpendingLimit {
+    PerTx,
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SpendingPolicyViolation {
+    pub limit: SpendingLimit,
+    pub limit_sat: u64,
+    pub spent_sat: u64,
+    pub tx_sat: u64,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum WalletRuntimeErrorCode {
     ElectrumServiceUnavailable,
-    NotEnoughFunds,
+    ElectrumConnectionRefused,
+    ElectrumTimeout,
+    ElectrumTlsFailure,
+    ElectrumProtocolMismatch,
+    ElectrumServerBusy,
+    TxFeeTooLow {
+        min_fee_rate_sat_per_vbyte: Option<u64>,
+    },
+    TxDustOutput,
+    TxInputsMissingOrSpent,
+    TxRejected {
+        reason: String,
+    },
+    NotEnoughFunds {
+        shortfall: Option<FundsShortfall>,
+    },
     RemoteServiceUnavailable,
     SendToOurselves,
+    DatabaseLocked,
+    SyncAborted,
+    InvalidConfirmationTarget {
+        min_confirm_in_blocks: u32,
+        max_confirm_in_blocks: u32,
+    },
+    Unauthorized,
+    PolicyViolation {
+        violation: SpendingPolicyViolation,
+    },
+    DuplicatePayment {
+        txid: String,
+    },
     GenericError,
 }
 
 impl Display for WalletRuntimeErrorCode {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            WalletRuntimeErrorCode::NotEnoughFunds { .. } => write!(f, "NotEnoughFunds"),
+            WalletRuntimeErrorCode::TxFeeTooLow { .. } => write!(f, "TxFeeTooLow"),
+            WalletRuntimeErrorCode::TxRejected { .. } => write!(f, "TxRejected"),
+            WalletRuntimeErrorCode::InvalidConfirmationTarget { .. } => {
+                write!(f, "InvalidConfirmationTarget")
+            }
+            WalletRuntimeErrorCode::PolicyViolation { .. } => write!(f, "PolicyViolation"),
+            WalletRuntimeErrorCode::DuplicatePayment { .. } => write!(f, "DuplicatePayment"),
+            _ => write!(f, "{self:?}"),
+        }
     }
 }
 
@@ -0,0 +1,223 @@
+use crate::{Balance, TxStatus};
+
+use bdk::bitcoin::Txid;
+use std::collections::HashMap;
+
+pub trait WalletEventListener: Send + Sync {
+    fn balance_changed(&self, balance: Balance);
+
+    fn tx_confirmed(&self, txid: String, confirmations: u32);
+
+    fn new_incoming_tx(&self, txid: String);
+
+    fn tx_reorged(&self, txid: String);
+}
+
+pub(crate) struct TxSnapshot {
+    pub is_incoming: bool,
+    pub status: TxStatus,
+}
+
+pub(crate) struct WalletSnapshot {
+    pub balance: Balance,
+    pub txs: HashMap<Txid, TxSnapshot>,
+}
+
+/// Compares two snapshots of the local wallet and notifies the listener about the differences.
+pub(crate) fn notify_changes(
+    previous: &WalletSnapshot,
+    current: &WalletSnapshot,
+    listener: &dyn WalletEventListener,
+) {
+    for (txid, tx) in &current.txs {
+        let previous_tx = previous.txs.get(txid);
+
+        if previous_tx.is_none() && tx.is_incoming {
+            listener.new_incoming_tx(txid.to_string());
+        }
+
+        let previous_block_height = match previous_tx {
+            Some(TxSnapshot {
+                status: TxStatus::Confirmed { block_height, .. },
+                ..
+            }) => Some(*block_height),
+            _ => None,
+        };
+        // A tx is reorged if the block it was confirmed in isn't part of the chain anymore. It's
+        // either back to unconfirmed or confirmed in a different block.
+        let is_reorged = match (previous_block_height, &tx.status) {
+            (None, _) => false,
+            (Some(previous_block_height), TxStatus::Confirmed { block_height, .. }) => {
+                previous_block_height != *block_height
+            }
+            (Some(_), _) => true,
+        };
+        if is_reorged {
+            listener.tx_reorged(txid.to_string());
+        }
+
+        if let TxStatus::Confirmed {
+            number_of_blocks, ..
+        } = tx.status
+        {
+            if previous_block_height.is_none() || is_reorged {
+                listener.tx_confirmed(txid.to_string(), number_of_blocks);
+            }
+        }
+    }
+
+    // A confirmed tx can disappear if it got reorged out and conflicts with the new chain.
+    for (txid, tx) in &previous.txs {
+        if matches!(tx.status, TxStatus::Confirmed { .. }) && !current.txs.contains_key(txid) {
+            listener.tx_reorged(txid.to_string());
+        }
+    }
+
+    if previous.balance != current.balance {
+        listener.balance_changed(current.balance.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    const TXID: &str = "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a";
+
+    #[derive(Default)]
+    struct RecordingListener {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl WalletEventListener for RecordingListener {
+        fn balance_changed(&self, balance: Balance) {
+            let event = format!("balance_changed {}", balance.confirmed.sat);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn tx_confirmed(&self, txid: String, confirmations: u32) {
+            let event = format!("tx_confirmed {txid} {confirmations}");
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn new_incoming_tx(&self, txid: String) {
+            let event = format!("new_incoming_tx {txid}");
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn tx_reorged(&self, txid: String) {
+            let event = format!("tx_reorged {txid}");
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    fn snapshot(confirmed: u64, tx: Option<(bool, TxStatus)>) -> WalletSnapshot {
+        let mut txs = HashMap::new();
+        if let Some((is_incoming, status)) = tx {
+            txs.insert(
+                Txid::from_str(TXID).unwrap(),
+                TxSnapshot {
+                    is_incoming,
+                    status,
+                },
+            );
+        }
+        WalletSnapshot {
+            balance: bdk::Balance {
+                immature: 0,
+                trusted_pending: 0,
+                untrusted_pending: 0,
+                confirmed,
+            }
+            .into(),
+            txs,
+        }
+    }
+
+    fn confirmed(number_of_blocks: u32) -> TxStatus {
+        confirmed_in_block(number_of_blocks, 100)
+    }
+
+    fn confirmed_in_block(number_of_blocks: u32, block_height: u32) -> TxStatus {
+        TxStatus::Confirmed {
+            number_of_blocks,
+            block_height,
+            confirmed_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_no_changes() {
+        let listener = RecordingListener::default();
+        let previous = snapshot(1000, Some((true, confirmed(1))));
+        let current = snapshot(1000, Some((true, confirmed(2))));
+
+        notify_changes(&previous, &current, &listener);
+
+        assert!(listener.events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_incoming_tx_lifecycle() {
+        let listener = RecordingListener::default();
+        let empty = snapshot(0, None);
+        let in_mempool = snapshot(0, Some((true, TxStatus::InMempool)));
+        let confirmed = snapshot(1000, Some((true, confirmed(1))));
+
+        notify_changes(&empty, &in_mempool, &listener);
+        notify_changes(&in_mempool, &confirmed, &listener);
+
+        assert_eq!(
+            *listener.events.lock().unwrap(),
+            vec![
+                format!("new_incoming_tx {TXID}"),
+                format!("tx_confirmed {TXID} 1"),
+                "balance_changed 1000".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outgoing_tx_is_not_reported_as_incoming() {
+        let listener = RecordingListener::default();
+        let previous = snapshot(1000, None);
+        let current = snapshot(0, Some((false, TxStatus::InMempool)));
+
+        notify_changes(&previous, &current, &listener);
+
+        assert_eq!(
+            *listener.events.lock().unwrap(),
+            vec!["balance_changed 0".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_reorg() {
+        let listener = RecordingListener::default();
+        let confirmed = snapshot(1000, Some((true, confirmed(2))));
+        let unconfirmed = snapshot(0, Some((true, TxStatus::InMempool)));
+        let reconfirmed = snapshot(1000, Some((true, confirmed_in_block(1, 101))));
+
+        notify_changes(&confirmed, &unconfirmed, &listener);
+        notify_changes(&unconfirmed, &reconfirmed, &listener);
+        notify_changes(&confirmed, &reconfirmed, &listener);
+        notify_changes(&confirmed, &snapshot(0, None), &listener);
+
+        assert_eq!(
+            *listener.events.lock().unwrap(),
+            vec![
+                format!("tx_reorged {TXID}"),
+                "balance_changed 0".to_string(),
+                format!("tx_confirmed {TXID} 1"),
+                "balance_changed 1000".to_string(),
+                format!("tx_reorged {TXID}"),
+                format!("tx_confirmed {TXID} 1"),
+                format!("tx_reorged {TXID}"),
+                "balance_changed 0".to_string(),
+            ]
+        );
+    }
+}
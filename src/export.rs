@@ -0,0 +1,195 @@
+use serde_json::{json, Value};
+use std::time::SystemTime;
+
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Incoming => "incoming",
+            Direction::Outgoing => "outgoing",
+        }
+    }
+}
+
+pub(crate) struct TxHistoryEntry {
+    pub txid: String,
+    pub direction: Direction,
+    pub amount_sat: u64,
+    pub fee_sat: Option<u64>,
+    pub confirmed_at: Option<SystemTime>,
+    pub address: Option<String>,
+}
+
+const CSV_HEADER: &str = "txid,direction,amount_sat,fee_sat,confirmed_at,address";
+
+pub(crate) fn format_tx_history(entries: &[TxHistoryEntry], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Csv => to_csv(entries),
+        ExportFormat::Json => to_json(entries),
+    }
+}
+
+// None of the fields can contain commas, quotes or line breaks, so no escaping is needed.
+fn to_csv(entries: &[TxHistoryEntry]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+    for entry in entries {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            entry.txid,
+            entry.direction.as_str(),
+            entry.amount_sat,
+            entry.fee_sat.map(|fee| fee.to_string()).unwrap_or_default(),
+            entry.confirmed_at.map(to_iso_8601).unwrap_or_default(),
+            entry.address.clone().unwrap_or_default(),
+        ));
+    }
+    csv
+}
+
+fn to_json(entries: &[TxHistoryEntry]) -> String {
+    let entries: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "txid": entry.txid,
+                "direction": entry.direction.as_str(),
+                "amount_sat": entry.amount_sat,
+                "fee_sat": entry.fee_sat,
+                "confirmed_at": entry.confirmed_at.map(to_iso_8601),
+                "address": entry.address,
+            })
+        })
+        .collect();
+    Value::Array(entries).to_string()
+}
+
+// Formats a time as an ISO 8601 UTC timestamp with second precision, e.g. 2023-08-01T12:30:00Z.
+fn to_iso_8601(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+// Converts the number of days since 1970-01-01 into a (year, month, day) date of the proleptic
+// Gregorian calendar. See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const TXID: &str = "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a";
+    const ADDRESS: &str = "tb1q4rmfylm7rt5sjsgcqpmclmtqn5ynhrt0yqnxnw";
+
+    fn entries() -> Vec<TxHistoryEntry> {
+        vec![
+            TxHistoryEntry {
+                txid: TXID.to_string(),
+                direction: Direction::Outgoing,
+                amount_sat: 50_000,
+                fee_sat: Some(141),
+                confirmed_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_690_893_000)),
+                address: Some(ADDRESS.to_string()),
+            },
+            TxHistoryEntry {
+                txid: TXID.to_string(),
+                direction: Direction::Incoming,
+                amount_sat: 100_000,
+                fee_sat: None,
+                confirmed_at: None,
+                address: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_to_iso_8601() {
+        let at = |secs| to_iso_8601(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_690_893_000), "2023-08-01T12:30:00Z");
+        assert_eq!(at(4_107_542_399), "2100-02-28T23:59:59Z");
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            format_tx_history(&entries(), ExportFormat::Csv),
+            format!(
+                "{CSV_HEADER}\n\
+                 {TXID},outgoing,50000,141,2023-08-01T12:30:00Z,{ADDRESS}\n\
+                 {TXID},incoming,100000,,,\n"
+            )
+        );
+        assert_eq!(
+            format_tx_history(&[], ExportFormat::Csv),
+            format!("{CSV_HEADER}\n")
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let json: Value =
+            serde_json::from_str(&format_tx_history(&entries(), ExportFormat::Json)).unwrap();
+
+        assert_eq!(
+            json,
+            json!([
+                {
+                    "txid": TXID,
+                    "direction": "outgoing",
+                    "amount_sat": 50_000,
+                    "fee_sat": 141,
+                    "confirmed_at": "2023-08-01T12:30:00Z",
+                    "address": ADDRESS,
+                },
+                {
+                    "txid": TXID,
+                    "direction": "incoming",
+                    "amount_sat": 100_000,
+                    "fee_sat": null,
+                    "confirmed_at": null,
+                    "address": null,
+                },
+            ])
+        );
+    }
+}
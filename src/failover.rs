@@ -0,0 +1,233 @@
+use bdk::electrum_client;
+use bdk::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+pub(crate) type Connect<B> = Box<dyn Fn(&str) -> Result<B, Error> + Send + Sync>;
+
+// Connections to a list of equivalent servers. Calls go to the server that succeeded last. If it
+// fails with a connection error, the remaining servers are tried in order. A server that failed
+// is disconnected and only tried after healthy servers until its backoff period, doubling with
+// every consecutive failure, has passed. It's reconnected the next time it's tried. Servers are only
+// connected to once the first call needs them, so creating the blockchain works offline.
+pub(crate) struct FailoverBlockchain<B> {
+    servers: Vec<Server<B>>,
+    connect: Connect<B>,
+    current: Mutex<usize>,
+}
+
+struct Server<B> {
+    url: String,
+    state: Mutex<ServerState<B>>,
+}
+
+struct ServerState<B> {
+    connection: Option<Arc<B>>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl<B> FailoverBlockchain<B> {
+    pub fn new(urls: Vec<String>, connect: Connect<B>) -> Self {
+        Self {
+            servers: urls
+                .into_iter()
+                .map(|url| Server {
+                    url,
+                    state: Mutex::new(ServerState {
+                        connection: None,
+                        failures: 0,
+                        retry_at: None,
+                    }),
+                })
+                .collect(),
+            connect,
+            current: Mutex::new(0),
+        }
+    }
+
+    pub fn call<T>(&self, operation: impl Fn(&B) -> Result<T, Error>) -> Result<T, Error> {
+        let mut last_error = Error::Generic("No server configured".to_string());
+        for index in self.get_order() {
+            let connection = match self.get_connection(index) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    last_error = e;
+                    continue;
+                }
+            };
+            match operation(&connection) {
+                Err(e) if is_connection_error(&e) => {
+                    self.mark_failed(index);
+                    last_error = e;
+                }
+                result => {
+                    *self.current.lock().unwrap() = index;
+                    return result;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    // Healthy servers first, starting with the current one, followed by the ones in backoff.
+    fn get_order(&self) -> Vec<usize> {
+        let current = *self.current.lock().unwrap();
+        let now = Instant::now();
+        let (mut healthy, in_backoff): (Vec<usize>, Vec<usize>) = (0..self.servers.len())
+            .map(|offset| (current + offset) % self.servers.len())
+            .partition(|index| {
+                let state = self.servers[*index].state.lock().unwrap();
+                !matches!(state.retry_at, Some(retry_at) if retry_at > now)
+            });
+        healthy.extend(in_backoff);
+        healthy
+    }
+
+    fn get_connection(&self, index: usize) -> Result<Arc<B>, Error> {
+        let server = &self.servers[index];
+        let mut state = server.state.lock().unwrap();
+        if let Some(connection) = &state.connection {
+            return Ok(Arc::clone(connection));
+        }
+        match (self.connect)(&server.url) {
+            Ok(connection) => {
+                let connection = Arc::new(connection);
+                state.connection = Some(Arc::clone(&connection));
+                state.failures = 0;
+                state.retry_at = None;
+                Ok(connection)
+            }
+            Err(e) => {
+                drop(state);
+                self.mark_failed(index);
+                Err(e)
+            }
+        }
+    }
+
+    fn mark_failed(&self, index: usize) {
+        let mut state = self.servers[index].state.lock().unwrap();
+        state.connection = None;
+        state.failures += 1;
+        let backoff = INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(state.failures - 1))
+            .min(MAX_BACKOFF);
+        state.retry_at = Some(Instant::now() + backoff);
+    }
+}
+
+// Errors returned by the server itself (e.g. a rejected tx) would be returned by any other server
+// as well, so they don't trigger a failover.
+fn is_connection_error(error: &Error) -> bool {
+    match error {
+        Error::Electrum(electrum_client::Error::Protocol(_)) => false,
+        Error::Electrum(_) | Error::Esplora(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    const URL_1: &str = "ssl://electrum-1.example.com:50002";
+    const URL_2: &str = "ssl://electrum-2.example.com:50002";
+
+    struct FakeServer {
+        url: String,
+    }
+
+    fn connection_error() -> Error {
+        Error::Electrum(electrum_client::Error::Message(
+            "Connection reset".to_string(),
+        ))
+    }
+
+    fn create_blockchain(
+        down: &Arc<Mutex<HashSet<&'static str>>>,
+    ) -> FailoverBlockchain<FakeServer> {
+        let down = Arc::clone(down);
+        FailoverBlockchain::new(
+            vec![URL_1.to_string(), URL_2.to_string()],
+            Box::new(move |url| {
+                if down.lock().unwrap().contains(url) {
+                    Err(connection_error())
+                } else {
+                    Ok(FakeServer {
+                        url: url.to_string(),
+                    })
+                }
+            }),
+        )
+    }
+
+    #[test]
+    fn test_failover() {
+        let down = Arc::new(Mutex::new(HashSet::new()));
+        let blockchain = create_blockchain(&down);
+        let call = || {
+            blockchain.call(|server| {
+                if down.lock().unwrap().contains(server.url.as_str()) {
+                    Err(connection_error())
+                } else {
+                    Ok(server.url.clone())
+                }
+            })
+        };
+        assert_eq!(call().unwrap(), URL_1);
+
+        down.lock().unwrap().insert(URL_1);
+        assert_eq!(call().unwrap(), URL_2);
+
+        // The failed server is in backoff, so the healthy one keeps being used after it's back up.
+        down.lock().unwrap().remove(URL_1);
+        assert_eq!(call().unwrap(), URL_2);
+
+        // A server in backoff is still tried if no healthy server is left.
+        down.lock().unwrap().insert(URL_2);
+        assert_eq!(call().unwrap(), URL_1);
+
+        down.lock().unwrap().insert(URL_1);
+        assert!(matches!(call(), Err(Error::Electrum(_))));
+    }
+
+    #[test]
+    fn test_no_failover_on_server_errors() {
+        let down = Arc::new(Mutex::new(HashSet::new()));
+        let blockchain = create_blockchain(&down);
+
+        let result: Result<(), Error> = blockchain.call(|_| {
+            Err(Error::Electrum(electrum_client::Error::Protocol(
+                json!({"code": 1, "message": "the transaction was rejected by network rules"}),
+            )))
+        });
+        assert!(result.is_err());
+        assert_eq!(
+            blockchain.call(|server| Ok(server.url.clone())).unwrap(),
+            URL_1
+        );
+    }
+
+    #[test]
+    fn test_all_servers_down() {
+        let down = Arc::new(Mutex::new(HashSet::from([URL_1, URL_2])));
+        // No server is connected to before the first call.
+        let blockchain = create_blockchain(&down);
+        assert!(matches!(
+            blockchain.call(|server| Ok(server.url.clone())),
+            Err(Error::Electrum(_))
+        ));
+
+        down.lock().unwrap().remove(URL_2);
+        assert_eq!(
+            blockchain.call(|server| Ok(server.url.clone())).unwrap(),
+            URL_2
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use crate::TxStatus;
+
+use std::time::SystemTime;
+
+const SATS_PER_BTC: f64 = 100_000_000.0;
+
+pub trait ExchangeRateProvider: Send + Sync {
+    fn get_current_rate(&self, currency_code: String) -> Option<f64>;
+
+    fn get_historical_rate(&self, currency_code: String, at: SystemTime) -> Option<f64>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FiatValue {
+    pub currency_code: String,
+    pub amount: f64,
+    pub rate: f64,
+}
+
+pub(crate) struct FiatValuation {
+    provider: Box<dyn ExchangeRateProvider>,
+    currency_code: String,
+}
+
+impl FiatValuation {
+    pub fn new(provider: Box<dyn ExchangeRateProvider>, currency_code: String) -> Self {
+        Self {
+            provider,
+            currency_code,
+        }
+    }
+
+    pub fn get_current_value(&self, sat: u64) -> Option<FiatValue> {
+        let rate = self.provider.get_current_rate(self.currency_code.clone())?;
+        Some(self.to_fiat_value(sat, rate))
+    }
+
+    // Confirmed txs are valued at the rate at confirmation time, all others at the current rate.
+    // The current rate is queried at most once, however many txs are provided.
+    pub fn get_tx_values(&self, txs: &[(u64, &TxStatus)]) -> Vec<Option<FiatValue>> {
+        let mut current_rate = None;
+        txs.iter()
+            .map(|(sat, status)| {
+                let rate = match status {
+                    TxStatus::Confirmed { confirmed_at, .. } => self
+                        .provider
+                        .get_historical_rate(self.currency_code.clone(), *confirmed_at),
+                    TxStatus::InMempool | TxStatus::NotInMempool => *current_rate
+                        .get_or_insert_with(|| {
+                            self.provider.get_current_rate(self.currency_code.clone())
+                        }),
+                };
+                rate.map(|rate| self.to_fiat_value(*sat, rate))
+            })
+            .collect()
+    }
+
+    fn to_fiat_value(&self, sat: u64, rate: f64) -> FiatValue {
+        FiatValue {
+            currency_code: self.currency_code.clone(),
+            amount: sat as f64 * rate / SATS_PER_BTC,
+            rate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct FixedRates {
+        current_rate_queries: Arc<AtomicU32>,
+    }
+
+    impl ExchangeRateProvider for FixedRates {
+        fn get_current_rate(&self, currency_code: String) -> Option<f64> {
+            self.current_rate_queries.fetch_add(1, Ordering::SeqCst);
+            match currency_code.as_str() {
+                "EUR" => Some(25_000.0),
+                _ => None,
+            }
+        }
+
+        fn get_historical_rate(&self, currency_code: String, at: SystemTime) -> Option<f64> {
+            match currency_code.as_str() {
+                "EUR" if at < SystemTime::UNIX_EPOCH + Duration::from_secs(1_000) => Some(20_000.0),
+                _ => None,
+            }
+        }
+    }
+
+    fn create_valuation(currency_code: &str) -> (FiatValuation, Arc<AtomicU32>) {
+        let current_rate_queries = Arc::new(AtomicU32::new(0));
+        let provider = FixedRates {
+            current_rate_queries: Arc::clone(&current_rate_queries),
+        };
+        (
+            FiatValuation::new(Box::new(provider), currency_code.to_string()),
+            current_rate_queries,
+        )
+    }
+
+    fn confirmed_at(secs: u64) -> TxStatus {
+        TxStatus::Confirmed {
+            number_of_blocks: 1,
+            block_height: 100,
+            confirmed_at: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn test_get_current_value() {
+        let (valuation, _) = create_valuation("EUR");
+        assert_eq!(
+            valuation.get_current_value(200_000),
+            Some(FiatValue {
+                currency_code: "EUR".to_string(),
+                amount: 50.0,
+                rate: 25_000.0,
+            })
+        );
+
+        let (valuation, _) = create_valuation("CHF");
+        assert_eq!(valuation.get_current_value(200_000), None);
+    }
+
+    #[test]
+    fn test_get_tx_values() {
+        let (valuation, current_rate_queries) = create_valuation("EUR");
+        let old_tx = confirmed_at(10);
+        let recent_tx = confirmed_at(2_000);
+
+        let values = valuation.get_tx_values(&[
+            (100_000, &old_tx),
+            (100_000, &TxStatus::InMempool),
+            (200_000, &TxStatus::NotInMempool),
+            (100_000, &recent_tx),
+        ]);
+
+        let amounts: Vec<Option<f64>> = values
+            .iter()
+            .map(|v| v.as_ref().map(|v| v.amount))
+            .collect();
+        assert_eq!(amounts, vec![Some(20.0), Some(25.0), Some(50.0), None]);
+        assert_eq!(current_rate_queries.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::errors::Result;
+use bdk::bitcoin::{Address, Network, OutPoint, Txid};
+use bdk::rusqlite::{params, Connection};
+use bdk::sled::{Db, Tree};
+use perro::{invalid_input, permanent_failure, MapToError};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+const LABELS_TREE: &str = "labels";
+
+// The record types of BIP-329.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelType {
+    Tx,
+    Addr,
+    Pubkey,
+    Input,
+    Output,
+    Xpub,
+}
+
+impl LabelType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LabelType::Tx => "tx",
+            LabelType::Addr => "addr",
+            LabelType::Pubkey => "pubkey",
+            LabelType::Input => "input",
+            LabelType::Output => "output",
+            LabelType::Xpub => "xpub",
+        }
+    }
+
+    fn parse(label_type: &str) -> Option<Self> {
+        match label_type {
+            "tx" => Some(LabelType::Tx),
+            "addr" => Some(LabelType::Addr),
+            "pubkey" => Some(LabelType::Pubkey),
+            "input" => Some(LabelType::Input),
+            "output" => Some(LabelType::Output),
+            "xpub" => Some(LabelType::Xpub),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub label_type: LabelType,
+    pub reference: String,
+    pub label: String,
+}
+
+// Labels attached to txs, addresses, keys and outpoints, stored next to the BDK wallet databases.
+// There is at most one label per reference.
+pub(crate) struct LabelStore {
+    storage: LabelStorage,
+}
+
+enum LabelStorage {
+    // Keys are the label type followed by ":" and the reference. Label types don't contain ":", so
+    // the key is split at the first one.
+    Sled { tree: Tree },
+    Sqlite { connection: Mutex<Connection> },
+}
+
+impl LabelStore {
+    pub fn open_sled(db: &Db) -> Result<Self> {
+        let tree = db
+            .open_tree(LABELS_TREE)
+            .map_to_permanent_failure("Failed to open labels tree")?;
+        Ok(Self {
+            storage: LabelStorage::Sled { tree },
+        })
+    }
+
+    pub fn open_sqlite(path: &Path) -> Result<Self> {
+        let connection =
+            Connection::open(path).map_to_permanent_failure("Failed to open labels database")?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS labels (
+                    type TEXT NOT NULL,
+                    ref TEXT NOT NULL,
+                    label TEXT NOT NULL,
+                    PRIMARY KEY (type, ref)
+                );",
+            )
+            .map_to_permanent_failure("Failed to create labels table")?;
+        Ok(Self {
+            storage: LabelStorage::Sqlite {
+                connection: Mutex::new(connection),
+            },
+        })
+    }
+
+    // An empty label removes the label of the reference.
+    pub fn set(&self, label: &Label) -> Result<()> {
+        match &self.storage {
+            LabelStorage::Sled { tree } => {
+                let key = format!("{}:{}", label.label_type.as_str(), label.reference);
+                if label.label.is_empty() {
+                    tree.remove(key.as_bytes())
+                        .map_to_permanent_failure("Failed to remove from labels tree")?;
+                } else {
+                    tree.insert(key.as_bytes(), label.label.as_bytes())
+                        .map_to_permanent_failure("Failed to insert into labels tree")?;
+                }
+                tree.flush()
+                    .map_to_permanent_failure("Failed to flush labels tree")?;
+            }
+            LabelStorage::Sqlite { connection } => {
+                let connection = connection.lock().unwrap();
+                if label.label.is_empty() {
+                    connection
+                        .execute(
+                            "DELETE FROM labels WHERE type = ?1 AND ref = ?2",
+                            params![label.label_type.as_str(), label.reference],
+                        )
+                        .map_to_permanent_failure("Failed to delete from labels table")?;
+                } else {
+                    connection
+                        .execute(
+                            "INSERT OR REPLACE INTO labels (type, ref, label) VALUES (?1, ?2, ?3)",
+                            params![label.label_type.as_str(), label.reference, label.label],
+                        )
+                        .map_to_permanent_failure("Failed to insert into labels table")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<Label>> {
+        match &self.storage {
+            LabelStorage::Sled { tree } => {
+                let mut labels = Vec::new();
+                for entry in tree.iter() {
+                    let (key, value) =
+                        entry.map_to_permanent_failure("Failed to read labels tree")?;
+                    let key = String::from_utf8(key.to_vec())
+                        .map_to_permanent_failure("Invalid labels key")?;
+                    let (label_type, reference) = key
+                        .split_once(':')
+                        .ok_or_else(|| permanent_failure("Invalid labels key"))?;
+                    labels.push(Label {
+                        label_type: LabelType::parse(label_type)
+                            .ok_or_else(|| permanent_failure("Invalid labels key: unknown type"))?,
+                        reference: reference.to_string(),
+                        label: String::from_utf8(value.to_vec())
+                            .map_to_permanent_failure("Invalid label")?,
+                    });
+                }
+                Ok(labels)
+            }
+            LabelStorage::Sqlite { connection } => {
+                let connection = connection.lock().unwrap();
+                let mut statement = connection
+                    .prepare("SELECT type, ref, label FROM labels ORDER BY type, ref")
+                    .map_to_permanent_failure("Failed to prepare labels query")?;
+                let rows = statement
+                    .query_map([], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                        ))
+                    })
+                    .map_to_permanent_failure("Failed to query labels table")?;
+
+                let mut labels = Vec::new();
+                for row in rows {
+                    let (label_type, reference, label) =
+                        row.map_to_permanent_failure("Failed to read labels table")?;
+                    labels.push(Label {
+                        label_type: LabelType::parse(&label_type).ok_or_else(|| {
+                            permanent_failure("Invalid labels entry: unknown type")
+                        })?,
+                        reference,
+                        label,
+                    });
+                }
+                Ok(labels)
+            }
+        }
+    }
+}
+
+// Only checks references that can be checked without knowing the wallet's keys.
+pub(crate) fn validate_reference(
+    label_type: LabelType,
+    reference: &str,
+    network: Network,
+) -> Result<()> {
+    let is_valid = match label_type {
+        LabelType::Tx => Txid::from_str(reference).is_ok(),
+        LabelType::Addr => matches!(
+            Address::from_str(reference),
+            Ok(address) if address.is_valid_for_network(network)
+        ),
+        LabelType::Input | LabelType::Output => OutPoint::from_str(reference).is_ok(),
+        LabelType::Pubkey | LabelType::Xpub => !reference.is_empty(),
+    };
+    if !is_valid {
+        return Err(invalid_input(format!(
+            "Invalid reference for label type {}: {reference}",
+            label_type.as_str()
+        )));
+    }
+    Ok(())
+}
+
+// Exports the labels in the BIP-329 format: one JSON object per line.
+pub(crate) fn to_bip329(labels: &[Label]) -> String {
+    labels
+        .iter()
+        .map(|label| {
+            format!(
+                "{}\n",
+                json!({
+                    "type": label.label_type.as_str(),
+                    "ref": label.reference,
+                    "label": label.label,
+                })
+            )
+        })
+        .collect()
+}
+
+// Records of unknown types and records without a label (e.g. output records that only set
+// "spendable") are skipped, as BIP-329 allows. Other invalid records fail the whole import, so
+// that no partial import happens.
+pub(crate) fn parse_bip329(jsonl: &str, network: Network) -> Result<Vec<Label>> {
+    let mut labels = Vec::new();
+    for (index, line) in jsonl.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid_record =
+            || invalid_input(format!("Invalid BIP-329 record on line {}", index + 1));
+        let record: Value = serde_json::from_str(line).map_err(|_| invalid_record())?;
+        let label_type = record["type"].as_str().ok_or_else(invalid_record)?;
+        let label_type = match LabelType::parse(label_type) {
+            Some(label_type) => label_type,
+            None => continue,
+        };
+        let reference = record["ref"].as_str().ok_or_else(invalid_record)?;
+        let label = match record["label"].as_str() {
+            Some(label) if !label.is_empty() => label,
+            _ => continue,
+        };
+        validate_reference(label_type, reference, network).map_err(|_| invalid_record())?;
+        labels.push(Label {
+            label_type,
+            reference: reference.to_string(),
+            label: label.to_string(),
+        });
+    }
+    Ok(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a";
+    const ADDRESS: &str = "tb1q4rmfylm7rt5sjsgcqpmclmtqn5ynhrt0yqnxnw";
+
+    fn label(label_type: LabelType, reference: &str, label: &str) -> Label {
+        Label {
+            label_type,
+            reference: reference.to_string(),
+            label: label.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_set_and_list_sled() {
+        let db = bdk::sled::Config::new().temporary(true).open().unwrap();
+        test_set_and_list(LabelStore::open_sled(&db).unwrap());
+    }
+
+    #[test]
+    fn test_set_and_list_sqlite() {
+        test_set_and_list(LabelStore::open_sqlite(Path::new(":memory:")).unwrap());
+    }
+
+    fn test_set_and_list(store: LabelStore) {
+        let outpoint = format!("{TXID}:1");
+        store.set(&label(LabelType::Tx, TXID, "Rent")).unwrap();
+        store
+            .set(&label(LabelType::Output, &outpoint, "Change"))
+            .unwrap();
+        store.set(&label(LabelType::Tx, TXID, "Rent July")).unwrap();
+
+        let labels = store.list().unwrap();
+        assert_eq!(labels.len(), 2);
+        assert!(labels.contains(&label(LabelType::Tx, TXID, "Rent July")));
+        assert!(labels.contains(&label(LabelType::Output, &outpoint, "Change")));
+
+        store.set(&label(LabelType::Tx, TXID, "")).unwrap();
+        assert_eq!(
+            store.list().unwrap(),
+            vec![label(LabelType::Output, &outpoint, "Change")]
+        );
+    }
+
+    #[test]
+    fn test_bip329_round_trip() {
+        let labels = vec![
+            label(LabelType::Tx, TXID, "Rent \"July\""),
+            label(LabelType::Addr, ADDRESS, "Shop"),
+        ];
+
+        let jsonl = to_bip329(&labels);
+        assert_eq!(jsonl.lines().count(), 2);
+        assert_eq!(parse_bip329(&jsonl, Network::Testnet).unwrap(), labels);
+    }
+
+    #[test]
+    fn test_parse_bip329() {
+        let jsonl = format!(
+            "{{\"type\": \"tx\", \"ref\": \"{TXID}\", \"label\": \"Rent\", \"origin\": \"wpkh([d34db33f/84'/0'/0'])\"}}\n\
+             \n\
+             {{\"type\": \"output\", \"ref\": \"{TXID}:0\", \"spendable\": false}}\n\
+             {{\"type\": \"unknown\", \"ref\": \"abc\", \"label\": \"Ignored\"}}\n"
+        );
+        assert_eq!(
+            parse_bip329(&jsonl, Network::Testnet).unwrap(),
+            vec![label(LabelType::Tx, TXID, "Rent")]
+        );
+
+        let invalid_records = [
+            ("not json".to_string(), Network::Testnet),
+            (
+                "{\"ref\": \"abc\", \"label\": \"No type\"}".to_string(),
+                Network::Testnet,
+            ),
+            (
+                "{\"type\": \"tx\", \"ref\": \"abc\", \"label\": \"Invalid txid\"}".to_string(),
+                Network::Testnet,
+            ),
+            (
+                format!("{{\"type\": \"addr\", \"ref\": \"{ADDRESS}\", \"label\": \"Shop\"}}"),
+                Network::Bitcoin,
+            ),
+        ];
+        for (record, network) in invalid_records {
+            let jsonl = format!(
+                "{{\"type\": \"tx\", \"ref\": \"{TXID}\", \"label\": \"Rent\"}}\n{record}\n"
+            );
+            let error = parse_bip329(&jsonl, network).unwrap_err();
+            assert!(error.to_string().contains("line 2"));
+        }
+    }
+}
@@ -1,29 +1,116 @@
+// Without the "native" feature, only the modules that compile to wasm32-unknown-unknown are
+// built: addresses, amounts, secrets and signing. The wallet, auth and the bindings need network
+// access or a database.
+#![cfg_attr(not(feature = "native"), allow(dead_code))]
+
 mod address;
+mod amount;
+#[cfg(feature = "native")]
+mod audit;
+#[cfg(feature = "native")]
 mod auth;
+#[cfg(feature = "native")]
+mod blockchain_errors;
+#[cfg(feature = "native")]
+mod config;
+mod crypto;
+mod descriptor;
 mod errors;
+#[cfg(feature = "native")]
+mod events;
+#[cfg(feature = "native")]
+mod export;
+#[cfg(feature = "native")]
+mod failover;
+#[cfg(feature = "native")]
+mod fiat;
+#[cfg(feature = "native")]
+mod jwt;
+#[cfg(feature = "native")]
+mod labels;
+#[cfg(feature = "native")]
+mod manager;
+#[cfg(feature = "native")]
 mod native_logger;
+#[cfg(feature = "native")]
+mod policy;
+#[cfg(feature = "native")]
+mod proposals;
+#[cfg(feature = "native")]
+mod relay;
+#[cfg(feature = "native")]
+mod scrub;
 mod secrets;
 mod signing;
+#[cfg(feature = "native")]
+mod snapshot;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "native")]
 mod wallet;
 
-pub use crate::address::AddressParsingError;
-pub use crate::auth::Auth;
-pub use crate::errors::{Error as WalletError, WalletRuntimeErrorCode};
+#[cfg(feature = "fuzzing")]
+pub use crate::address::fuzz_parse;
+pub use crate::address::{AddressParsingError, PaymentUri};
+pub use crate::amount::{amount_from_btc, amount_from_msat, amount_from_sat, Amount};
+#[cfg(feature = "native")]
+pub use crate::audit::{AuditEvent, AuditEventKind};
+#[cfg(feature = "native")]
+pub use crate::auth::{
+    parse_backend_errors, Auth, AuthBackendError, AuthConfig, AuthRegistry, RetryPolicy, TokenInfo,
+};
+#[cfg(feature = "native")]
+pub use crate::config::{validate_config, ConfigProblem};
+pub use crate::crypto::{decrypt_with_secret_key, encrypt_to_pubkey};
+pub use crate::errors::{
+    Error as WalletError, FundsShortfall, SpendingLimit, SpendingPolicyViolation,
+    WalletRuntimeErrorCode,
+};
+#[cfg(feature = "native")]
+pub use crate::events::WalletEventListener;
+#[cfg(feature = "native")]
+pub use crate::export::ExportFormat;
+#[cfg(feature = "native")]
+pub use crate::fiat::{ExchangeRateProvider, FiatValue};
+#[cfg(feature = "native")]
+pub use crate::labels::{Label, LabelType};
+#[cfg(feature = "native")]
+pub use crate::manager::{WalletManager, WalletManagerConfig};
+#[cfg(feature = "native")]
 pub use crate::native_logger::init_native_logger_once;
+#[cfg(feature = "native")]
+pub use crate::policy::SpendingPolicy;
+#[cfg(feature = "native")]
+pub use crate::proposals::TxProposal;
 pub use crate::secrets::{
-    derive_keys, generate_keypair, generate_mnemonic, words_by_prefix, Descriptors, KeyPair,
-    WalletKeys,
+    build_multisig_descriptors, decrypt_mnemonic, derive_keys, derive_keys_for_account,
+    derive_keys_v2, derive_keys_with_script, derive_ldk_seed, derive_silent_payment_keys,
+    derive_watch_descriptor_for_account, encrypt_mnemonic, generate_keypair, generate_mnemonic,
+    generate_mnemonic_with_length, get_multisig_xpub, words_by_prefix,
+    words_by_prefix_with_language, Descriptors, KeyPair, MnemonicLanguage, MnemonicLength,
+    ScriptType, SilentPaymentKeys, WalletKeys, WalletKeysV2,
+};
+pub use crate::signing::{sign, sign_message_recoverable, verify, verify_message};
+#[cfg(feature = "native")]
+pub use crate::wallet::{
+    AddressInfo, Balance, BlockchainBackend, BroadcastChannel, BroadcastResult, CachedBalance,
+    Config, DatabaseBackend, DecodedTx, DecodedTxInput, DecodedTxOutput, DetailedBalance,
+    FeeEstimate, FeeHistogramEntry, IncomingTx, Keychain, PaymentRequest, Recipient, SignedTx,
+    SyncCallback, SyncPhase, SyncProgressCallback, Tx, TxDetails, TxStatus, UtxoDetails, Wallet,
+    WalletDiagnostics, ZeroConfRisk,
 };
-pub use crate::signing::sign;
-pub use crate::wallet::{Config, Tx, TxDetails, TxStatus, Wallet};
 
+#[cfg(feature = "native")]
 pub use honey_badger::graphql::errors::{
     Error as AuthError, GraphQlRuntimeErrorCode as AuthRuntimeErrorCode,
 };
+#[cfg(feature = "native")]
 pub use honey_badger::AuthLevel;
 
+#[cfg(feature = "native")]
 use bdk::bitcoin::Network;
-use bdk::Balance;
+#[cfg(feature = "native")]
 use log::Level as LogLevel;
 
+#[cfg(feature = "native")]
 include!(concat!(env!("OUT_DIR"), "/lipabusinesslib.uniffi.rs"));
@@ -0,0 +1,213 @@
+use crate::errors::Result;
+use crate::failover::FailoverBlockchain;
+use crate::{Balance, BlockchainBackend, Config, DatabaseBackend, Wallet};
+
+use bdk::bitcoin::Network;
+use bdk::blockchain::AnyBlockchain;
+use log::warn;
+use perro::invalid_input;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub struct WalletManagerConfig {
+    pub blockchain_backend: BlockchainBackend,
+    pub wallets_db_path: String,
+    pub network: Network,
+    pub stop_gap: Option<u32>,
+    pub database: DatabaseBackend,
+    pub socks5_proxy: Option<String>,
+    pub timeout_secs: Option<u8>,
+    pub electrum_retries: Option<u8>,
+    pub broadcast_fallback_url: Option<String>,
+    pub min_confirm_in_blocks: Option<u32>,
+    pub max_confirm_in_blocks: Option<u32>,
+}
+
+// Manages several watch-only wallets sharing a single connection to the blockchain backend. Each
+// wallet is registered under a name and stored in its own database below `wallets_db_path`. Only
+// the scans of syncs use a connection of their own, so that an abort can close it.
+pub struct WalletManager {
+    config: WalletManagerConfig,
+    blockchain: Arc<FailoverBlockchain<AnyBlockchain>>,
+    wallets: Mutex<BTreeMap<String, Arc<Wallet>>>,
+}
+
+impl WalletManager {
+    pub fn new(config: WalletManagerConfig) -> Result<Self> {
+        let blockchain = Wallet::create_blockchain(
+            &config.blockchain_backend,
+            config.stop_gap,
+            config.socks5_proxy.clone(),
+            config.timeout_secs,
+            config.electrum_retries,
+        )?;
+        Ok(Self {
+            config,
+            blockchain: Arc::new(blockchain),
+            wallets: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    // Registering a name that was used before reopens the existing database of the wallet. Wallets
+    // aren't persisted by the manager, so they need to be registered again after restarting the app.
+    pub fn register_wallet(&self, name: String, watch_descriptor: String) -> Result<Arc<Wallet>> {
+        validate_name(&name)?;
+        let mut wallets = self.wallets.lock().unwrap();
+        if wallets.contains_key(&name) {
+            return Err(invalid_input(format!(
+                "A wallet named {name} is already registered"
+            )));
+        }
+
+        let config = Config {
+            blockchain_backend: self.config.blockchain_backend.clone(),
+            wallet_db_path: Path::new(&self.config.wallets_db_path)
+                .join(&name)
+                .to_string_lossy()
+                .to_string(),
+            network: self.config.network,
+            watch_descriptor,
+            stop_gap: self.config.stop_gap,
+            database: self.config.database,
+            socks5_proxy: self.config.socks5_proxy.clone(),
+            timeout_secs: self.config.timeout_secs,
+            electrum_retries: self.config.electrum_retries,
+            broadcast_fallback_url: self.config.broadcast_fallback_url.clone(),
+            min_confirm_in_blocks: self.config.min_confirm_in_blocks,
+            max_confirm_in_blocks: self.config.max_confirm_in_blocks,
+        };
+        let wallet = Arc::new(Wallet::with_blockchain(
+            config,
+            Arc::clone(&self.blockchain),
+        )?);
+        wallets.insert(name, Arc::clone(&wallet));
+        Ok(wallet)
+    }
+
+    // The database of the wallet is kept, so it can be registered again later.
+    pub fn unregister_wallet(&self, name: String) {
+        self.wallets.lock().unwrap().remove(&name);
+    }
+
+    pub fn get_wallet(&self, name: String) -> Option<Arc<Wallet>> {
+        self.wallets.lock().unwrap().get(&name).cloned()
+    }
+
+    // Sorted alphabetically.
+    pub fn list_wallet_names(&self) -> Vec<String> {
+        self.wallets.lock().unwrap().keys().cloned().collect()
+    }
+
+    // A failing sync doesn't prevent the remaining wallets from being synced. The first error is
+    // returned once all wallets have been synced.
+    pub fn sync_all(&self) -> Result<()> {
+        let mut first_error = None;
+        for (name, wallet) in self.get_wallets() {
+            if let Err(e) = wallet.sync() {
+                warn!("Failed to sync wallet {name}: {e}");
+                first_error.get_or_insert(e);
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_aggregated_balance(&self) -> Result<Balance> {
+        let mut total = bdk::Balance {
+            immature: 0,
+            trusted_pending: 0,
+            untrusted_pending: 0,
+            confirmed: 0,
+        };
+        for (_, wallet) in self.get_wallets() {
+            let balance = wallet.get_bdk_balance()?;
+            total.immature += balance.immature;
+            total.trusted_pending += balance.trusted_pending;
+            total.untrusted_pending += balance.untrusted_pending;
+            total.confirmed += balance.confirmed;
+        }
+        Ok(total.into())
+    }
+
+    // The wallets are synced without holding the lock, so that wallets can be registered meanwhile.
+    fn get_wallets(&self) -> Vec<(String, Arc<Wallet>)> {
+        self.wallets
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, wallet)| (name.clone(), Arc::clone(wallet)))
+            .collect()
+    }
+}
+
+// The name is used as directory name, so only characters that are safe on all platforms are
+// allowed.
+fn validate_name(name: &str) -> Result<()> {
+    let is_valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !is_valid {
+        return Err(invalid_input(
+            "Invalid wallet name. Only ASCII letters, digits, '-' and '_' are allowed",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::remove_dir_all;
+
+    const TESTNET_WATCH_DESCRIPTOR: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/0/*)";
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("store-1_zurich").is_ok());
+        for invalid in ["", "../store", "store 1", "store/1", "zürich"] {
+            assert!(validate_name(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_register_wallets() {
+        let _ = remove_dir_all(".bdk-database-wallet-manager");
+
+        let manager = WalletManager::new(WalletManagerConfig {
+            blockchain_backend: BlockchainBackend::Electrum {
+                url: "ssl://electrum.blockstream.info:60002".to_string(),
+            },
+            wallets_db_path: ".bdk-database-wallet-manager".to_string(),
+            network: Network::Testnet,
+            stop_gap: None,
+            database: DatabaseBackend::Sled,
+            socks5_proxy: None,
+            timeout_secs: None,
+            electrum_retries: None,
+            broadcast_fallback_url: None,
+            min_confirm_in_blocks: None,
+            max_confirm_in_blocks: None,
+        })
+        .unwrap();
+
+        manager
+            .register_wallet("store-b".to_string(), TESTNET_WATCH_DESCRIPTOR.to_string())
+            .unwrap();
+        manager
+            .register_wallet("store-a".to_string(), TESTNET_WATCH_DESCRIPTOR.to_string())
+            .unwrap();
+        assert!(manager
+            .register_wallet("store-a".to_string(), TESTNET_WATCH_DESCRIPTOR.to_string())
+            .is_err());
+        assert_eq!(manager.list_wallet_names(), vec!["store-a", "store-b"]);
+        assert!(manager.get_wallet("store-a".to_string()).is_some());
+
+        manager.unregister_wallet("store-b".to_string());
+        assert!(manager.get_wallet("store-b".to_string()).is_none());
+        assert_eq!(manager.get_aggregated_balance().unwrap().confirmed.sat, 0);
+    }
+}
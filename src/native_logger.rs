@@ -1,19 +1,23 @@
+use crate::scrub::ScrubbingLogger;
 #[cfg(target_os = "android")]
-use android_logger::Config;
-use log::Level;
+use android_logger::{AndroidLogger, Config};
+use log::{Level, Log};
 #[cfg(target_os = "ios")]
 use oslog::OsLogger;
 use std::sync::Once;
 
 fn init_native_logger(min_level: Level) {
     #[cfg(target_os = "android")]
-    android_logger::init_once(Config::default().with_max_level(min_level.to_level_filter()));
+    install_scrubbing_logger(
+        AndroidLogger::new(Config::default().with_max_level(min_level.to_level_filter())),
+        min_level,
+    );
 
     #[cfg(target_os = "ios")]
-    OsLogger::new("com.getlipa.lipalightninglib")
-        .level_filter(min_level.to_level_filter())
-        .init()
-        .unwrap();
+    install_scrubbing_logger(
+        OsLogger::new("com.getlipa.lipalightninglib").level_filter(min_level.to_level_filter()),
+        min_level,
+    );
 
     #[cfg(all(not(target_os = "android"), not(target_os = "ios")))]
     {
@@ -22,6 +26,15 @@ fn init_native_logger(min_level: Level) {
     }
 }
 
+// Every log message is scrubbed of secrets (e.g. access tokens) before it reaches the native logger.
+#[allow(dead_code)]
+fn install_scrubbing_logger(logger: impl Log + 'static, min_level: Level) {
+    let logger = Box::leak(Box::new(ScrubbingLogger::new(logger)));
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(min_level.to_level_filter());
+    }
+}
+
 static INIT_LOGGER_ONCE: Once = Once::new();
 
 /// Call the function once before instantiating the library to get logs.
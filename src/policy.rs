@@ -0,0 +1,248 @@
+use crate::auth::Auth;
+use crate::errors::{Result, SpendingLimit, SpendingPolicyViolation};
+use crate::WalletRuntimeErrorCode;
+use honey_badger::AuthLevel;
+use log::warn;
+use perro::{permanent_failure, runtime_error};
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+const WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+// Limits of the funds leaving the wallet (amounts sent plus fees), so that mistakes of employees
+// and compromised terminals can only do limited damage. Daily and weekly limits apply to the last
+// 24 hours and 7 days, not to calendar days and weeks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpendingPolicy {
+    pub max_tx_sat: Option<u64>,
+    pub daily_limit_sat: Option<u64>,
+    pub weekly_limit_sat: Option<u64>,
+}
+
+// Funds that left the wallet in a previous tx.
+pub(crate) struct Spend {
+    pub amount_sat: u64,
+    pub spent_at: SystemTime,
+}
+
+// Employees may draft payouts as tx proposals, but only owners may spend funds by signing them.
+// Without an Auth there is no session to take the level from, so signing proposals is refused.
+pub(crate) fn ensure_may_sign_proposal(auth: Option<&Auth>) -> Result<()> {
+    let auth = auth.ok_or_else(|| {
+        runtime_error(
+            WalletRuntimeErrorCode::Unauthorized,
+            "Signing a tx proposal requires an owner session, but no Auth has been set",
+        )
+    })?;
+    ensure_owner(&get_auth_level(auth)?)
+}
+
+// Owner sessions may override the spending policy, e.g. to pay a large supplier invoice.
+pub(crate) fn enforce_spending_policy(
+    violation: Option<SpendingPolicyViolation>,
+    auth: Option<&Auth>,
+) -> Result<()> {
+    let violation = match violation {
+        Some(violation) => violation,
+        None => return Ok(()),
+    };
+    if let Some(auth) = auth {
+        match get_auth_level(auth) {
+            Ok(AuthLevel::Owner) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to check if the session may override the policy: {e}"),
+        }
+    }
+    let msg = format!(
+        "The tx of {} sats exceeds the {:?} spending limit of {} sats ({} sats already spent)",
+        violation.tx_sat, violation.limit, violation.limit_sat, violation.spent_sat
+    );
+    Err(runtime_error(
+        WalletRuntimeErrorCode::PolicyViolation { violation },
+        msg,
+    ))
+}
+
+// Returns the first limit that would be exceeded, checking the per-tx limit first. Txs that don't
+// spend funds of the wallet never violate the policy, even if overridden txs exceeded a limit.
+pub(crate) fn find_policy_violation(
+    policy: &SpendingPolicy,
+    tx_sat: u64,
+    previous_spends: &[Spend],
+    now: SystemTime,
+) -> Option<SpendingPolicyViolation> {
+    if tx_sat == 0 {
+        return None;
+    }
+    let spent_within = |period: Duration| -> u64 {
+        previous_spends
+            .iter()
+            .filter(|spend| now.duration_since(spend.spent_at).unwrap_or_default() < period)
+            .map(|spend| spend.amount_sat)
+            .sum()
+    };
+    let limits = [
+        (SpendingLimit::PerTx, policy.max_tx_sat, None),
+        (SpendingLimit::Daily, policy.daily_limit_sat, Some(DAY)),
+        (SpendingLimit::Weekly, policy.weekly_limit_sat, Some(WEEK)),
+    ];
+    for (limit, limit_sat, period) in limits {
+        let limit_sat = match limit_sat {
+            Some(limit_sat) => limit_sat,
+            None => continue,
+        };
+        let spent_sat = period.map(spent_within).unwrap_or(0);
+        if spent_sat.saturating_add(tx_sat) > limit_sat {
+            return Some(SpendingPolicyViolation {
+                limit,
+                limit_sat,
+                spent_sat,
+                tx_sat,
+            });
+        }
+    }
+    None
+}
+
+fn get_auth_level(auth: &Auth) -> Result<AuthLevel> {
+    let token_info = auth.get_token_info().map_err(|e| match e {
+        perro::Error::RuntimeError { msg, .. } => runtime_error(
+            WalletRuntimeErrorCode::RemoteServiceUnavailable,
+            format!("Failed to get the auth level of the session: {msg}"),
+        ),
+        e => permanent_failure(format!("Failed to get the auth level of the session: {e}")),
+    })?;
+    Ok(token_info.auth_level)
+}
+
+fn ensure_owner(auth_level: &AuthLevel) -> Result<()> {
+    match auth_level {
+        AuthLevel::Owner => Ok(()),
+        AuthLevel::Employee | AuthLevel::Pseudonymous => Err(runtime_error(
+            WalletRuntimeErrorCode::Unauthorized,
+            "Only owners can sign tx proposals",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_unauthorized(result: Result<()>) -> bool {
+        matches!(
+            result,
+            Err(perro::Error::RuntimeError {
+                code: WalletRuntimeErrorCode::Unauthorized,
+                ..
+            })
+        )
+    }
+
+    #[test]
+    fn test_ensure_may_sign_proposal() {
+        assert!(ensure_owner(&AuthLevel::Owner).is_ok());
+        assert!(is_unauthorized(ensure_owner(&AuthLevel::Employee)));
+        assert!(is_unauthorized(ensure_owner(&AuthLevel::Pseudonymous)));
+        assert!(is_unauthorized(ensure_may_sign_proposal(None)));
+    }
+
+    #[test]
+    fn test_find_policy_violation() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let hours_ago = |hours: u64| now - Duration::from_secs(hours * 60 * 60);
+        let previous_spends = [
+            Spend {
+                amount_sat: 30_000,
+                spent_at: hours_ago(2),
+            },
+            Spend {
+                amount_sat: 50_000,
+                spent_at: hours_ago(48),
+            },
+            Spend {
+                amount_sat: 1_000_000,
+                spent_at: hours_ago(24 * 8),
+            },
+        ];
+        let policy = SpendingPolicy {
+            max_tx_sat: Some(100_000),
+            daily_limit_sat: Some(120_000),
+            weekly_limit_sat: Some(150_000),
+        };
+
+        assert_eq!(
+            find_policy_violation(&SpendingPolicy::default(), u64::MAX, &previous_spends, now),
+            None
+        );
+        assert_eq!(
+            find_policy_violation(&policy, 70_000, &previous_spends, now),
+            None
+        );
+        let overridden_spends = [Spend {
+            amount_sat: 500_000,
+            spent_at: hours_ago(1),
+        }];
+        assert_eq!(
+            find_policy_violation(&policy, 0, &overridden_spends, now),
+            None
+        );
+        assert_eq!(
+            find_policy_violation(&policy, 100_001, &previous_spends, now),
+            Some(SpendingPolicyViolation {
+                limit: SpendingLimit::PerTx,
+                limit_sat: 100_000,
+                spent_sat: 0,
+                tx_sat: 100_001,
+            })
+        );
+        assert_eq!(
+            find_policy_violation(&policy, 90_001, &previous_spends, now),
+            Some(SpendingPolicyViolation {
+                limit: SpendingLimit::Daily,
+                limit_sat: 120_000,
+                spent_sat: 30_000,
+                tx_sat: 90_001,
+            })
+        );
+
+        let policy = SpendingPolicy {
+            daily_limit_sat: None,
+            ..policy
+        };
+        assert_eq!(
+            find_policy_violation(&policy, 90_000, &previous_spends, now),
+            Some(SpendingPolicyViolation {
+                limit: SpendingLimit::Weekly,
+                limit_sat: 150_000,
+                spent_sat: 80_000,
+                tx_sat: 90_000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_enforce_spending_policy() {
+        assert!(enforce_spending_policy(None, None).is_ok());
+
+        let violation = SpendingPolicyViolation {
+            limit: SpendingLimit::Daily,
+            limit_sat: 120_000,
+            spent_sat: 30_000,
+            tx_sat: 90_001,
+        };
+        let result = enforce_spending_policy(Some(violation), None);
+        assert!(matches!(
+            result,
+            Err(perro::Error::RuntimeError {
+                code: WalletRuntimeErrorCode::PolicyViolation {
+                    violation: SpendingPolicyViolation {
+                        limit: SpendingLimit::Daily,
+                        ..
+                    }
+                },
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+use crate::amount::Amount;
+use crate::errors::Result;
+use crate::wallet::Tx;
+use bdk::bitcoin::consensus::deserialize;
+use bdk::bitcoin::hashes::hex::{FromHex, ToHex};
+use bdk::bitcoin::psbt::Psbt;
+use bdk::bitcoin::{OutPoint, Transaction};
+use bdk::rusqlite::{params, Connection};
+use bdk::sled::{Db, Tree};
+use perro::{permanent_failure, MapToError};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+const TX_PROPOSALS_TREE: &str = "tx-proposals";
+
+// A prepared tx that waits to be signed, e.g. a payout prepared by an employee for an owner.
+pub struct TxProposal {
+    pub tx: Tx,
+    pub memo: String,
+    pub created_at: SystemTime,
+}
+
+// Tx proposals stored next to the BDK wallet databases, keyed by the id of the tx.
+pub(crate) struct TxProposalStore {
+    storage: TxProposalStorage,
+}
+
+enum TxProposalStorage {
+    // Values are JSON objects with the fields of the proposal, the blob is hex encoded.
+    Sled { tree: Tree },
+    Sqlite { connection: Mutex<Connection> },
+}
+
+impl TxProposalStore {
+    pub fn open_sled(db: &Db) -> Result<Self> {
+        let tree = db
+            .open_tree(TX_PROPOSALS_TREE)
+            .map_to_permanent_failure("Failed to open tx proposals tree")?;
+        Ok(Self {
+            storage: TxProposalStorage::Sled { tree },
+        })
+    }
+
+    pub fn open_sqlite(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .map_to_permanent_failure("Failed to open tx proposals database")?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS tx_proposals (
+                    id TEXT PRIMARY KEY,
+                    blob BLOB NOT NULL,
+                    on_chain_fee_sat INTEGER NOT NULL,
+                    output_sat INTEGER NOT NULL,
+                    memo TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                );",
+            )
+            .map_to_permanent_failure("Failed to create tx proposals table")?;
+        Ok(Self {
+            storage: TxProposalStorage::Sqlite {
+                connection: Mutex::new(connection),
+            },
+        })
+    }
+
+    // A proposal with the same tx id is replaced.
+    pub fn save(&self, proposal: &TxProposal) -> Result<()> {
+        let tx = &proposal.tx;
+        match &self.storage {
+            TxProposalStorage::Sled { tree } => {
+                let value = json!({
+                    "blob": tx.blob.to_hex(),
+                    "on_chain_fee_sat": tx.on_chain_fee.sat,
+                    "output_sat": tx.output.sat,
+                    "memo": proposal.memo,
+                    "created_at": to_millis(proposal.created_at),
+                });
+                tree.insert(tx.id.as_bytes(), value.to_string().as_bytes())
+                    .map_to_permanent_failure("Failed to insert into tx proposals tree")?;
+                tree.flush()
+                    .map_to_permanent_failure("Failed to flush tx proposals tree")?;
+            }
+            TxProposalStorage::Sqlite { connection } => {
+                connection
+                    .lock()
+                    .unwrap()
+                    .execute(
+                        "INSERT OR REPLACE INTO tx_proposals
+                        (id, blob, on_chain_fee_sat, output_sat, memo, created_at)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![
+                            tx.id,
+                            tx.blob,
+                            tx.on_chain_fee.sat as i64,
+                            tx.output.sat as i64,
+                            proposal.memo,
+                            to_millis(proposal.created_at) as i64,
+                        ],
+                    )
+                    .map_to_permanent_failure("Failed to insert into tx proposals table")?;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the proposals from the oldest to the newest.
+    pub fn list(&self) -> Result<Vec<TxProposal>> {
+        let mut proposals = match &self.storage {
+            TxProposalStorage::Sled { tree } => {
+                let mut proposals = Vec::new();
+                for entry in tree.iter() {
+                    let (key, value) =
+                        entry.map_to_permanent_failure("Failed to read tx proposals tree")?;
+                    let id = String::from_utf8(key.to_vec())
+                        .map_to_permanent_failure("Invalid tx proposals key")?;
+                    proposals.push(parse_proposal(id, &value)?);
+                }
+                proposals
+            }
+            TxProposalStorage::Sqlite { connection } => {
+                let connection = connection.lock().unwrap();
+                let mut statement = connection
+                    .prepare(
+                        "SELECT id, blob, on_chain_fee_sat, output_sat, memo, created_at
+                        FROM tx_proposals",
+                    )
+                    .map_to_permanent_failure("Failed to prepare tx proposals query")?;
+                let rows = statement
+                    .query_map([], |row| {
+                        Ok(TxProposal {
+                            tx: Tx {
+                                id: row.get(0)?,
+                                blob: row.get(1)?,
+                                on_chain_fee: Amount::from_sat(row.get::<_, i64>(2)? as u64),
+                                output: Amount::from_sat(row.get::<_, i64>(3)? as u64),
+                            },
+                            memo: row.get(4)?,
+                            created_at: from_millis(row.get::<_, i64>(5)? as u64),
+                        })
+                    })
+                    .map_to_permanent_failure("Failed to query tx proposals table")?;
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_to_permanent_failure("Failed to read tx proposals table")?
+            }
+        };
+        proposals.sort_by(|a, b| (a.created_at, &a.tx.id).cmp(&(b.created_at, &b.tx.id)));
+        Ok(proposals)
+    }
+
+    // Returns whether the tx spends an outpoint that is also spent by a proposal. A tx that only
+    // differs from a proposal in e.g. the sequence numbers of its inputs has a different id, but
+    // spends the same funds.
+    pub fn spends_reserved_outpoint(&self, tx: &Transaction) -> Result<bool> {
+        let outpoints: HashSet<OutPoint> =
+            tx.input.iter().map(|input| input.previous_output).collect();
+        for proposal in self.list()? {
+            let psbt = deserialize::<Psbt>(&proposal.tx.blob)
+                .map_to_permanent_failure("Invalid tx proposal blob")?;
+            if psbt
+                .unsigned_tx
+                .input
+                .iter()
+                .any(|input| outpoints.contains(&input.previous_output))
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Returns whether a proposal with the tx id existed.
+    pub fn delete(&self, id: &str) -> Result<bool> {
+        match &self.storage {
+            TxProposalStorage::Sled { tree } => {
+                let removed = tree
+                    .remove(id.as_bytes())
+                    .map_to_permanent_failure("Failed to remove from tx proposals tree")?;
+                tree.flush()
+                    .map_to_permanent_failure("Failed to flush tx proposals tree")?;
+                Ok(removed.is_some())
+            }
+            TxProposalStorage::Sqlite { connection } => {
+                let deleted = connection
+                    .lock()
+                    .unwrap()
+                    .execute("DELETE FROM tx_proposals WHERE id = ?1", params![id])
+                    .map_to_permanent_failure("Failed to delete from tx proposals table")?;
+                Ok(deleted > 0)
+            }
+        }
+    }
+}
+
+fn parse_proposal(id: String, value: &[u8]) -> Result<TxProposal> {
+    let value: Value =
+        serde_json::from_slice(value).map_to_permanent_failure("Invalid tx proposals entry")?;
+    let invalid_entry = || permanent_failure("Invalid tx proposals entry");
+    let blob = value["blob"].as_str().ok_or_else(invalid_entry)?;
+    Ok(TxProposal {
+        tx: Tx {
+            id,
+            blob: Vec::<u8>::from_hex(blob).map_err(|_| invalid_entry())?,
+            on_chain_fee: Amount::from_sat(
+                value["on_chain_fee_sat"]
+                    .as_u64()
+                    .ok_or_else(invalid_entry)?,
+            ),
+            output: Amount::from_sat(value["output_sat"].as_u64().ok_or_else(invalid_entry)?),
+        },
+        memo: value["memo"].as_str().unwrap_or_default().to_string(),
+        created_at: from_millis(value["created_at"].as_u64().ok_or_else(invalid_entry)?),
+    })
+}
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn from_millis(millis: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::consensus::serialize;
+    use bdk::bitcoin::{PackedLockTime, Script, Sequence, TxIn, TxOut, Txid, Witness};
+    use std::str::FromStr;
+
+    const TXID_1: &str = "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a";
+    const TXID_2: &str = "7c2b8e4f5a1d9c3b6e0f2a8d4c7b1e5f9a3d6c0b8e2f4a7d1c5b9e3f6a0d2c8b";
+
+    fn proposal(id: &str, memo: &str, created_at_secs: u64) -> TxProposal {
+        TxProposal {
+            tx: Tx {
+                id: id.to_string(),
+                blob: vec![0x70, 0x73, 0x62, 0x74, 0xff],
+                on_chain_fee: Amount::from_sat(141),
+                output: Amount::from_sat(50_000),
+            },
+            memo: memo.to_string(),
+            created_at: SystemTime::UNIX_EPOCH + Duration::from_secs(created_at_secs),
+        }
+    }
+
+    fn summarize(proposals: &[TxProposal]) -> Vec<(String, String)> {
+        proposals
+            .iter()
+            .map(|proposal| (proposal.tx.id.clone(), proposal.memo.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_save_list_and_delete_sled() {
+        let db = bdk::sled::Config::new().temporary(true).open().unwrap();
+        test_save_list_and_delete(TxProposalStore::open_sled(&db).unwrap());
+    }
+
+    #[test]
+    fn test_save_list_and_delete_sqlite() {
+        test_save_list_and_delete(TxProposalStore::open_sqlite(Path::new(":memory:")).unwrap());
+    }
+
+    fn test_save_list_and_delete(store: TxProposalStore) {
+        store.save(&proposal(TXID_1, "Salary June", 2_000)).unwrap();
+        store.save(&proposal(TXID_2, "Supplier", 1_000)).unwrap();
+        store.save(&proposal(TXID_1, "Salary July", 2_000)).unwrap();
+
+        let proposals = store.list().unwrap();
+        assert_eq!(
+            summarize(&proposals),
+            vec![
+                (TXID_2.to_string(), "Supplier".to_string()),
+                (TXID_1.to_string(), "Salary July".to_string()),
+            ]
+        );
+        let saved = proposal(TXID_2, "Supplier", 1_000);
+        assert_eq!(proposals[0].tx.blob, saved.tx.blob);
+        assert_eq!(proposals[0].tx.on_chain_fee, saved.tx.on_chain_fee);
+        assert_eq!(proposals[0].tx.output, saved.tx.output);
+        assert_eq!(proposals[0].created_at, saved.created_at);
+
+        assert!(store.delete(TXID_2).unwrap());
+        assert!(!store.delete(TXID_2).unwrap());
+        assert_eq!(
+            summarize(&store.list().unwrap()),
+            vec![(TXID_1.to_string(), "Salary July".to_string())]
+        );
+    }
+
+    fn spending_tx(outpoints: &[(&str, u32)], sequence: u32) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: outpoints
+                .iter()
+                .map(|(txid, vout)| TxIn {
+                    previous_output: OutPoint::new(Txid::from_str(txid).unwrap(), *vout),
+                    script_sig: Script::new(),
+                    sequence: Sequence(sequence),
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: 50_000,
+                script_pubkey: Script::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_spends_reserved_outpoint() {
+        let store = TxProposalStore::open_sqlite(Path::new(":memory:")).unwrap();
+        let proposed = spending_tx(&[(TXID_1, 0), (TXID_1, 1)], 0xfffffffd);
+        let mut proposal = proposal(&proposed.txid().to_string(), "Salary", 1_000);
+        proposal.tx.blob = serialize(&Psbt::from_unsigned_tx(proposed.clone()).unwrap());
+        store.save(&proposal).unwrap();
+
+        assert!(store.spends_reserved_outpoint(&proposed).unwrap());
+        // Same inputs with another sequence number, so the tx id differs.
+        let replaced = spending_tx(&[(TXID_1, 0), (TXID_1, 1)], 0xfffffffe);
+        assert_ne!(replaced.txid(), proposed.txid());
+        assert!(store.spends_reserved_outpoint(&replaced).unwrap());
+        assert!(store
+            .spends_reserved_outpoint(&spending_tx(&[(TXID_2, 0), (TXID_1, 1)], 0))
+            .unwrap());
+        assert!(!store
+            .spends_reserved_outpoint(&spending_tx(&[(TXID_1, 2), (TXID_2, 0)], 0))
+            .unwrap());
+    }
+}
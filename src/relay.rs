@@ -0,0 +1,343 @@
+use log::warn;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const COMMAND_CONNECT: u8 = 1;
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+const REPLY_SUCCEEDED: u8 = 0;
+const REPLY_HOST_UNREACHABLE: u8 = 4;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+
+// A SOCKS5 proxy on localhost whose connections can all be closed at once. Neither the electrum
+// nor the esplora client can be interrupted while waiting for a response, but both fail as soon as
+// their connection gets closed. Connections are forwarded to the upstream proxy if there is one,
+// and to the requested host otherwise.
+//
+// The timeout applies to connecting to the upstream host and to waiting for its responses.
+pub(crate) struct InterruptibleRelay {
+    address: SocketAddr,
+    state: Arc<RelayState>,
+}
+
+struct RelayState {
+    interrupted: AtomicBool,
+    connections: Mutex<Vec<TcpStream>>,
+}
+
+impl InterruptibleRelay {
+    pub fn start(upstream_proxy: Option<String>, timeout: Option<Duration>) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(RelayState {
+            interrupted: AtomicBool::new(false),
+            connections: Mutex::new(Vec::new()),
+        });
+        let accepting_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for client in listener.incoming() {
+                let client = match client {
+                    Ok(client) => client,
+                    Err(e) => {
+                        warn!("Failed to accept a connection to the relay: {e}");
+                        continue;
+                    }
+                };
+                if accepting_state.register(&client).is_err() {
+                    break;
+                }
+                let state = Arc::clone(&accepting_state);
+                let upstream_proxy = upstream_proxy.clone();
+                thread::spawn(move || {
+                    let result = relay(client, upstream_proxy.as_deref(), timeout, &state);
+                    if let Err(e) = result {
+                        if !state.interrupted.load(Ordering::SeqCst) {
+                            warn!("Failed to relay a connection: {e}");
+                        }
+                    }
+                });
+            }
+        });
+        Ok(Self { address, state })
+    }
+
+    pub fn address(&self) -> String {
+        self.address.to_string()
+    }
+
+    // Closes all connections and rejects new ones.
+    pub fn interrupt(&self) {
+        self.state.interrupt();
+        // Wakes up the accept loop, so that it notices the interruption and stops listening.
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+impl Drop for InterruptibleRelay {
+    fn drop(&mut self) {
+        self.interrupt();
+    }
+}
+
+impl RelayState {
+    // Fails once the relay got interrupted, so that no connection outlives the interruption.
+    fn register(&self, stream: &TcpStream) -> io::Result<()> {
+        let mut connections = self.connections.lock().unwrap();
+        if self.interrupted.load(Ordering::SeqCst) {
+            let _ = stream.shutdown(Shutdown::Both);
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "The relay was interrupted",
+            ));
+        }
+        connections.push(stream.try_clone()?);
+        Ok(())
+    }
+
+    fn interrupt(&self) {
+        let connections = self.connections.lock().unwrap();
+        self.interrupted.store(true, Ordering::SeqCst);
+        for connection in connections.iter() {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn relay(
+    mut client: TcpStream,
+    upstream_proxy: Option<&str>,
+    timeout: Option<Duration>,
+    state: &RelayState,
+) -> io::Result<()> {
+    let target = accept_handshake(&mut client)?;
+    let upstream = match upstream_proxy {
+        Some(proxy) => connect_through_proxy(proxy, &target, timeout),
+        None => to_socket_addrs(&target).and_then(|addresses| connect(&addresses, timeout)),
+    };
+    let upstream = match upstream {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            let _ = send_reply(&mut client, REPLY_HOST_UNREACHABLE);
+            return Err(e);
+        }
+    };
+    upstream.set_read_timeout(timeout)?;
+    state.register(&upstream)?;
+    send_reply(&mut client, REPLY_SUCCEEDED)?;
+
+    let mut upstream_reader = upstream.try_clone()?;
+    let mut client_writer = client.try_clone()?;
+    let responses = thread::spawn(move || {
+        let _ = io::copy(&mut upstream_reader, &mut client_writer);
+        let _ = client_writer.shutdown(Shutdown::Both);
+    });
+    let mut upstream_writer = upstream;
+    let _ = io::copy(&mut client, &mut upstream_writer);
+    let _ = upstream_writer.shutdown(Shutdown::Both);
+    let _ = responses.join();
+    Ok(())
+}
+
+// Returns the requested target in the wire format of a SOCKS5 address: the address type, the
+// address and the port.
+fn accept_handshake(client: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut greeting = [0; 2];
+    client.read_exact(&mut greeting)?;
+    let mut methods = vec![0; greeting[1] as usize];
+    client.read_exact(&mut methods)?;
+    if greeting[0] != SOCKS_VERSION || !methods.contains(&NO_AUTHENTICATION) {
+        client.write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHODS])?;
+        return Err(invalid_data("Unsupported SOCKS5 authentication methods"));
+    }
+    client.write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])?;
+
+    let mut request = [0; 4];
+    client.read_exact(&mut request)?;
+    if request[1] != COMMAND_CONNECT {
+        send_reply(client, REPLY_COMMAND_NOT_SUPPORTED)?;
+        return Err(invalid_data("Unsupported SOCKS5 command"));
+    }
+    read_address(client, request[3])
+}
+
+fn read_address(stream: &mut TcpStream, address_type: u8) -> io::Result<Vec<u8>> {
+    let mut address = vec![address_type];
+    let length = match address_type {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        ADDRESS_DOMAIN => {
+            let mut length = [0; 1];
+            stream.read_exact(&mut length)?;
+            address.push(length[0]);
+            length[0] as usize
+        }
+        _ => return Err(invalid_data("Unsupported SOCKS5 address type")),
+    };
+    let mut address_and_port = vec![0; length + 2];
+    stream.read_exact(&mut address_and_port)?;
+    address.extend(address_and_port);
+    Ok(address)
+}
+
+fn to_socket_addrs(target: &[u8]) -> io::Result<Vec<SocketAddr>> {
+    let (address, port) = target.split_at(target.len() - 2);
+    let port = u16::from_be_bytes([port[0], port[1]]);
+    match address[0] {
+        ADDRESS_IPV4 => {
+            let ip: [u8; 4] = address[1..].try_into().map_err(|_| invalid_address())?;
+            Ok(vec![SocketAddr::from((ip, port))])
+        }
+        ADDRESS_IPV6 => {
+            let ip: [u8; 16] = address[1..].try_into().map_err(|_| invalid_address())?;
+            Ok(vec![SocketAddr::from((ip, port))])
+        }
+        _ => {
+            let host = std::str::from_utf8(&address[2..]).map_err(|_| invalid_address())?;
+            Ok((host, port).to_socket_addrs()?.collect())
+        }
+    }
+}
+
+fn connect(addresses: &[SocketAddr], timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "The host didn't resolve");
+    for address in addresses {
+        let result = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(address, timeout),
+            None => TcpStream::connect(address),
+        };
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+// The host names are resolved by the proxy, so that .onion urls can be used.
+fn connect_through_proxy(
+    proxy: &str,
+    target: &[u8],
+    timeout: Option<Duration>,
+) -> io::Result<TcpStream> {
+    let addresses: Vec<SocketAddr> = proxy.to_socket_addrs()?.collect();
+    let mut stream = connect(&addresses, timeout)?;
+    stream.set_read_timeout(timeout)?;
+    stream.write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION])?;
+    let mut method = [0; 2];
+    stream.read_exact(&mut method)?;
+    if method != [SOCKS_VERSION, NO_AUTHENTICATION] {
+        return Err(invalid_data("The proxy requires authentication"));
+    }
+    let mut request = vec![SOCKS_VERSION, COMMAND_CONNECT, 0];
+    request.extend_from_slice(target);
+    stream.write_all(&request)?;
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != REPLY_SUCCEEDED {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("The proxy failed to connect with reply code {}", reply[1]),
+        ));
+    }
+    // The address the proxy bound to isn't needed.
+    read_address(&mut stream, reply[3])?;
+    Ok(stream)
+}
+
+fn send_reply(client: &mut TcpStream, reply: u8) -> io::Result<()> {
+    client.write_all(&[SOCKS_VERSION, reply, 0, ADDRESS_IPV4, 0, 0, 0, 0, 0, 0])
+}
+
+fn invalid_address() -> io::Error {
+    invalid_data("Invalid SOCKS5 address")
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    let mut reader = stream.try_clone().unwrap();
+                    let _ = io::copy(&mut reader, &mut stream);
+                });
+            }
+        });
+        address
+    }
+
+    fn connect_through_relay(relay: &InterruptibleRelay, target: SocketAddr) -> TcpStream {
+        let mut stream = TcpStream::connect(relay.address()).unwrap();
+        stream
+            .write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION])
+            .unwrap();
+        let mut method = [0; 2];
+        stream.read_exact(&mut method).unwrap();
+        assert_eq!(method, [SOCKS_VERSION, NO_AUTHENTICATION]);
+
+        let target = match target {
+            SocketAddr::V4(target) => target,
+            SocketAddr::V6(_) => panic!("The echo server listens on IPv4"),
+        };
+        let mut request = vec![SOCKS_VERSION, COMMAND_CONNECT, 0, ADDRESS_IPV4];
+        request.extend_from_slice(&target.ip().octets());
+        request.extend_from_slice(&target.port().to_be_bytes());
+        stream.write_all(&request).unwrap();
+        let mut reply = [0; 10];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[1], REPLY_SUCCEEDED);
+        stream
+    }
+
+    #[test]
+    fn test_relay() {
+        let echo_server = start_echo_server();
+        let relay = InterruptibleRelay::start(None, None).unwrap();
+        let mut stream = connect_through_relay(&relay, echo_server);
+
+        stream.write_all(b"ping").unwrap();
+        let mut response = [0; 4];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(&response, b"ping");
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let echo_server = start_echo_server();
+        let relay = InterruptibleRelay::start(None, None).unwrap();
+        let mut stream = connect_through_relay(&relay, echo_server);
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        relay.interrupt();
+
+        let mut response = [0; 4];
+        let closed = matches!(stream.read(&mut response), Ok(0) | Err(_));
+        assert!(closed);
+        let reconnected = TcpStream::connect(relay.address())
+            .and_then(|mut stream| {
+                stream.write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION])?;
+                stream.read_exact(&mut [0; 2])
+            })
+            .is_ok();
+        assert!(!reconnected);
+    }
+}
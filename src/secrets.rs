@@ -1,29 +1,85 @@
+use crate::descriptor::append_checksum;
 use crate::errors::Result;
 use bdk::bitcoin::hashes::hex::ToHex;
 use bdk::bitcoin::secp256k1::PublicKey;
-use bdk::bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey, KeySource};
+use bdk::bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey, ExtendedPubKey, KeySource};
 use bdk::bitcoin::Network;
 use bdk::descriptor::Segwitv0;
 use bdk::keys::bip39::{Language, Mnemonic};
 use bdk::keys::DescriptorKey::Secret;
 use bdk::keys::{DerivableKey, DescriptorKey, ExtendedKey};
+use bdk::miniscript::descriptor::{DescriptorPublicKey, Wildcard};
 use bdk::miniscript::ToPublicKey;
-use perro::{permanent_failure, MapToError};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use perro::{invalid_input, permanent_failure, MapToError};
 use rand::rngs::OsRng;
 use rand::RngCore;
+use scrypt::{scrypt, Params as ScryptParams};
 use secp256k1::SECP256K1;
 use std::str::FromStr;
 
-// In the near future we want to migrate to the following keys for backend auth
-//const BACKEND_AUTH_DERIVATION_PATH: &str = "m/76738065'/0'/0";
-// For now, we use the master key pair
-const BACKEND_AUTH_DERIVATION_PATH: &str = "m";
-const ACCOUNT_DERIVATION_PATH_MAINNET: &str = "m/84'/0'/0'";
-const ACCOUNT_DERIVATION_PATH_TESTNET: &str = "m/84'/1'/0'";
+// Backend auth is being migrated from the master key pair to a dedicated key pair. derive_keys()
+// keeps returning the master key pair, so that existing sessions don't break.
+const LEGACY_BACKEND_AUTH_DERIVATION_PATH: &str = "m";
+const BACKEND_AUTH_DERIVATION_PATH: &str = "m/76738065'/0'/0";
+// Account paths are m/purpose'/coin_type'/account' (BIP-84 for Segwit, BIP-86 for Taproot).
+const SEGWIT_PURPOSE: u32 = 84;
+const TAPROOT_PURPOSE: u32 = 86;
+const COIN_TYPE_MAINNET: u32 = 0;
+const COIN_TYPE_TESTNET: u32 = 1;
+const DEFAULT_ACCOUNT_INDEX: u32 = 0;
+// Account indices are hardened derivation steps.
+const MAX_ACCOUNT_INDEX: u32 = (1 << 31) - 1;
+const MULTISIG_ACCOUNT_DERIVATION_PATH_MAINNET: &str = "m/48'/0'/0'/2'";
+const MULTISIG_ACCOUNT_DERIVATION_PATH_TESTNET: &str = "m/48'/1'/0'/2'";
+// BIP-352 silent payment keys
+const SILENT_PAYMENT_SCAN_DERIVATION_PATH_MAINNET: &str = "m/352'/0'/0'/1'/0";
+const SILENT_PAYMENT_SCAN_DERIVATION_PATH_TESTNET: &str = "m/352'/1'/0'/1'/0";
+const SILENT_PAYMENT_SPEND_DERIVATION_PATH_MAINNET: &str = "m/352'/0'/0'/0'/0";
+const SILENT_PAYMENT_SPEND_DERIVATION_PATH_TESTNET: &str = "m/352'/1'/0'/0'/0";
+// Seed of the Lightning node run by the companion library. Below the lipa purpose like the backend
+// auth key, so that it can't collide with keys of the on-chain wallet.
+const LIGHTNING_SEED_DERIVATION_PATH_MAINNET: &str = "m/76738065'/1'/0'";
+const LIGHTNING_SEED_DERIVATION_PATH_TESTNET: &str = "m/76738065'/1'/1'";
+// Limit of keys in a CHECKMULTISIG script
+const MAX_MULTISIG_KEYS: usize = 20;
+
+// Mnemonic backup format v1:
+// version (1 byte) | scrypt salt (16 bytes) | XChaCha20-Poly1305 nonce (24 bytes) | ciphertext
+// The version byte is authenticated as associated data.
+const BACKUP_VERSION_1: u8 = 1;
+const BACKUP_SALT_LEN: usize = 16;
+const BACKUP_NONCE_LEN: usize = 24;
+const BACKUP_HEADER_LEN: usize = 1 + BACKUP_SALT_LEN + BACKUP_NONCE_LEN;
+// scrypt parameters of v1 backups (N = 2^15, r = 8, p = 1), requiring 32 MiB of memory
+const BACKUP_V1_SCRYPT_LOG_N: u8 = 15;
+const BACKUP_V1_SCRYPT_R: u32 = 8;
+const BACKUP_V1_SCRYPT_P: u32 = 1;
+
+pub enum MnemonicLength {
+    Words12,
+    Words18,
+    Words24,
+}
+
+impl MnemonicLength {
+    fn entropy_len(&self) -> usize {
+        match self {
+            MnemonicLength::Words12 => 16,
+            MnemonicLength::Words18 => 24,
+            MnemonicLength::Words24 => 32,
+        }
+    }
+}
 
 pub fn generate_mnemonic() -> Result<Vec<String>> {
-    let entropy = generate_random_bytes()?;
-    let mnemonic = Mnemonic::from_entropy(&entropy)
+    generate_mnemonic_with_length(MnemonicLength::Words24)
+}
+
+pub fn generate_mnemonic_with_length(word_count: MnemonicLength) -> Result<Vec<String>> {
+    let entropy = generate_random_bytes::<32>()?;
+    let mnemonic = Mnemonic::from_entropy(&entropy[..word_count.entropy_len()])
         .map_to_permanent_failure("Failed to get mnemonic from entropy")?;
 
     let mnemonic: Vec<String> = mnemonic.word_iter().map(|s| s.to_string()).collect();
@@ -31,22 +87,105 @@ pub fn generate_mnemonic() -> Result<Vec<String>> {
     Ok(mnemonic)
 }
 
-fn generate_random_bytes() -> Result<[u8; 32]> {
-    let mut bytes = [0u8; 32];
+fn generate_random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
     OsRng
         .try_fill_bytes(&mut bytes)
         .map_to_permanent_failure("Failed to generate random bytes using OsRng")?;
     Ok(bytes)
 }
 
+/// Encrypts a mnemonic with a password into a versioned blob that can be stored as a backup.
+pub fn encrypt_mnemonic(mnemonic_string: Vec<String>, password: String) -> Result<Vec<u8>> {
+    let mnemonic = Mnemonic::from_str(mnemonic_string.join(" ").as_str())
+        .map_to_invalid_input("Invalid mnemonic string")?;
+
+    let salt = generate_random_bytes::<BACKUP_SALT_LEN>()?;
+    let nonce = generate_random_bytes::<BACKUP_NONCE_LEN>()?;
+    let cipher = derive_backup_cipher(&password, &salt)?;
+
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: mnemonic.to_string().as_bytes(),
+                aad: &[BACKUP_VERSION_1],
+            },
+        )
+        .map_to_permanent_failure("Failed to encrypt mnemonic")?;
+
+    let mut blob = Vec::with_capacity(BACKUP_HEADER_LEN + ciphertext.len());
+    blob.push(BACKUP_VERSION_1);
+    blob.extend_from_slice(&salt);
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&ciphertext);
+    Ok(blob)
+}
+
+/// Decrypts a blob created by [`encrypt_mnemonic`].
+pub fn decrypt_mnemonic(blob: Vec<u8>, password: String) -> Result<Vec<String>> {
+    if blob.len() < BACKUP_HEADER_LEN {
+        return Err(invalid_input("Invalid backup: blob is too short"));
+    }
+    let (version, rest) = blob.split_at(1);
+    if version[0] != BACKUP_VERSION_1 {
+        return Err(invalid_input(format!(
+            "Invalid backup: unsupported version {}",
+            version[0]
+        )));
+    }
+    let (salt, rest) = rest.split_at(BACKUP_SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(BACKUP_NONCE_LEN);
+
+    let cipher = derive_backup_cipher(&password, salt)?;
+    let plaintext = cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: version,
+            },
+        )
+        .map_to_invalid_input("Failed to decrypt backup: wrong password or corrupted backup")?;
+
+    let mnemonic = String::from_utf8(plaintext)
+        .map_to_invalid_input("Invalid backup: mnemonic isn't valid UTF-8")?;
+    let mnemonic =
+        Mnemonic::from_str(&mnemonic).map_to_invalid_input("Invalid backup: invalid mnemonic")?;
+
+    Ok(mnemonic.word_iter().map(|s| s.to_string()).collect())
+}
+
+fn derive_backup_cipher(password: &str, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+    let params = ScryptParams::new(
+        BACKUP_V1_SCRYPT_LOG_N,
+        BACKUP_V1_SCRYPT_R,
+        BACKUP_V1_SCRYPT_P,
+        ScryptParams::RECOMMENDED_LEN,
+    )
+    .map_to_permanent_failure("Invalid scrypt parameters")?;
+
+    let mut key = [0u8; 32];
+    scrypt(password.as_bytes(), salt, &params, &mut key)
+        .map_to_permanent_failure("Failed to derive backup key")?;
+
+    Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+#[derive(Clone)]
 pub struct KeyPair {
     pub secret_key: String,
     pub public_key: String,
 }
 
+// The account fields describe the key derived from the mnemonic, which is also embedded in the
+// descriptors, so that integrations don't have to parse the descriptors to get them.
 pub struct Descriptors {
     pub spend_descriptor: String,
     pub watch_descriptor: String,
+    pub account_xpub: String,
+    pub master_fingerprint: String,
+    pub account_derivation_path: String,
 }
 
 pub struct WalletKeys {
@@ -54,27 +193,260 @@ pub struct WalletKeys {
     pub wallet_descriptors: Descriptors,
 }
 
+pub struct WalletKeysV2 {
+    pub wallet_keypair: KeyPair,
+    pub legacy_wallet_keypair: KeyPair,
+    pub wallet_descriptors: Descriptors,
+}
+
+pub struct SilentPaymentKeys {
+    pub scan_keypair: KeyPair,
+    pub spend_keypair: KeyPair,
+}
+
+#[derive(Clone, Copy)]
+pub enum ScriptType {
+    Segwit,
+    Taproot,
+}
+
 pub fn derive_keys(network: Network, mnemonic_string: Vec<String>) -> Result<WalletKeys> {
+    derive_keys_with_script(network, mnemonic_string, ScriptType::Segwit)
+}
+
+pub fn derive_keys_with_script(
+    network: Network,
+    mnemonic_string: Vec<String>,
+    script_type: ScriptType,
+) -> Result<WalletKeys> {
+    derive_keys_for_account(network, mnemonic_string, script_type, DEFAULT_ACCOUNT_INDEX)
+}
+
+/// Derives the wallet keys with the descriptors of the account with the given index, so that one
+/// mnemonic can back several wallets (e.g. operating funds and savings). The wallet key pair
+/// identifies the user towards the backend, so it's the same for all accounts.
+pub fn derive_keys_for_account(
+    network: Network,
+    mnemonic_string: Vec<String>,
+    script_type: ScriptType,
+    account_index: u32,
+) -> Result<WalletKeys> {
+    validate_account_index(account_index)?;
     let mnemonic = Mnemonic::from_str(mnemonic_string.join(" ").as_str())
         .map_to_invalid_input("Invalid mnemonic string")?;
 
     let master_xpriv = get_master_xpriv(network, mnemonic)?;
 
-    let auth_keypair = derive_auth_keypair(master_xpriv)?;
-    let spend_descriptor = build_spend_descriptor(network, master_xpriv)?;
-    let watch_descriptor = build_watch_descriptor(network, master_xpriv)?;
+    let auth_keypair = derive_auth_keypair(master_xpriv, LEGACY_BACKEND_AUTH_DERIVATION_PATH)?;
 
     Ok(WalletKeys {
         wallet_keypair: auth_keypair,
-        wallet_descriptors: Descriptors {
-            spend_descriptor,
-            watch_descriptor,
+        wallet_descriptors: build_descriptors(network, script_type, account_index, master_xpriv)?,
+    })
+}
+
+/// Derives the wallet keys with the wallet key pair for backend auth at `m/76738065'/0'/0`
+/// instead of the master key pair. The master key pair used by [`derive_keys`] is returned as the
+/// legacy wallet key pair, so that the wallet can keep authenticating with it while migrating.
+pub fn derive_keys_v2(network: Network, mnemonic_string: Vec<String>) -> Result<WalletKeysV2> {
+    let mnemonic = Mnemonic::from_str(mnemonic_string.join(" ").as_str())
+        .map_to_invalid_input("Invalid mnemonic string")?;
+
+    let master_xpriv = get_master_xpriv(network, mnemonic)?;
+
+    let auth_keypair = derive_auth_keypair(master_xpriv, BACKEND_AUTH_DERIVATION_PATH)?;
+    let legacy_auth_keypair =
+        derive_auth_keypair(master_xpriv, LEGACY_BACKEND_AUTH_DERIVATION_PATH)?;
+
+    Ok(WalletKeysV2 {
+        wallet_keypair: auth_keypair,
+        legacy_wallet_keypair: legacy_auth_keypair,
+        wallet_descriptors: build_descriptors(
+            network,
+            ScriptType::Segwit,
+            DEFAULT_ACCOUNT_INDEX,
+            master_xpriv,
+        )?,
+    })
+}
+
+/// Derives the Segwit watch descriptor of the account with the given index, e.g. to give a store's
+/// accountant read-only access to the funds of that store's account. Account 0 is the account of
+/// the descriptors returned by [`derive_keys`].
+pub fn derive_watch_descriptor_for_account(
+    network: Network,
+    mnemonic_string: Vec<String>,
+    account_index: u32,
+) -> Result<String> {
+    validate_account_index(account_index)?;
+    let mnemonic = Mnemonic::from_str(mnemonic_string.join(" ").as_str())
+        .map_to_invalid_input("Invalid mnemonic string")?;
+
+    let master_xpriv = get_master_xpriv(network, mnemonic)?;
+
+    build_watch_descriptor(network, ScriptType::Segwit, account_index, master_xpriv)
+}
+
+/// Derives the BIP-352 scan and spend key pairs. The silent payment address isn't returned, as a
+/// `Wallet` can't detect payments to it yet.
+pub fn derive_silent_payment_keys(
+    network: Network,
+    mnemonic_string: Vec<String>,
+) -> Result<SilentPaymentKeys> {
+    let mnemonic = Mnemonic::from_str(mnemonic_string.join(" ").as_str())
+        .map_to_invalid_input("Invalid mnemonic string")?;
+
+    let master_xpriv = get_master_xpriv(network, mnemonic)?;
+
+    let (scan_path, spend_path) = get_silent_payment_derivation_paths(network);
+    let derive_key = |path: &str| {
+        let derivation_path = DerivationPath::from_str(path)
+            .map_to_permanent_failure("Failed to build derivation path")?;
+        let xpriv = master_xpriv
+            .derive_priv(SECP256K1, &derivation_path)
+            .map_to_permanent_failure("Failed to derive keys")?;
+        Ok((
+            xpriv.private_key,
+            PublicKey::from_secret_key(SECP256K1, &xpriv.private_key),
+        ))
+    };
+    let (scan_secret_key, scan_public_key) = derive_key(scan_path)?;
+    let (spend_secret_key, spend_public_key) = derive_key(spend_path)?;
+
+    Ok(SilentPaymentKeys {
+        scan_keypair: KeyPair {
+            secret_key: scan_secret_key.secret_bytes().to_hex(),
+            public_key: scan_public_key.serialize().to_hex(),
+        },
+        spend_keypair: KeyPair {
+            secret_key: spend_secret_key.secret_bytes().to_hex(),
+            public_key: spend_public_key.serialize().to_hex(),
         },
     })
 }
 
-fn derive_auth_keypair(master_xpriv: ExtendedPrivKey) -> Result<KeyPair> {
-    let lipa_purpose_path = DerivationPath::from_str(BACKEND_AUTH_DERIVATION_PATH)
+/// Derives the 32 byte seed of a Lightning node (e.g. LDK's `KeysManager`) from the mnemonic of the
+/// wallet, so that a single backup covers both the on-chain wallet and the Lightning node. The seed
+/// is the private key at `m/76738065'/1'/0'` (`m/76738065'/1'/1'` for testnets).
+pub fn derive_ldk_seed(network: Network, mnemonic_string: Vec<String>) -> Result<Vec<u8>> {
+    let mnemonic = Mnemonic::from_str(mnemonic_string.join(" ").as_str())
+        .map_to_invalid_input("Invalid mnemonic string")?;
+
+    let master_xpriv = get_master_xpriv(network, mnemonic)?;
+
+    let derivation_path = DerivationPath::from_str(get_lightning_seed_derivation_path(network))
+        .map_to_permanent_failure("Failed to build derivation path")?;
+    let xpriv = master_xpriv
+        .derive_priv(SECP256K1, &derivation_path)
+        .map_to_permanent_failure("Failed to derive keys")?;
+    Ok(xpriv.private_key.secret_bytes().to_vec())
+}
+
+/// Returns the BIP-48 account xpub (including its key origin) to be shared with the cosigners of
+/// a multisig wallet.
+pub fn get_multisig_xpub(network: Network, mnemonic_string: Vec<String>) -> Result<String> {
+    let mnemonic = Mnemonic::from_str(mnemonic_string.join(" ").as_str())
+        .map_to_invalid_input("Invalid mnemonic string")?;
+
+    let master_xpriv = get_master_xpriv(network, mnemonic)?;
+
+    let account_path = get_multisig_account_derivation_path(network);
+    let account_xpub = derive_account_xpub(master_xpriv, account_path)?;
+
+    Ok(format!(
+        "[{}{}]{account_xpub}",
+        master_xpriv.fingerprint(SECP256K1),
+        account_path.trim_start_matches('m')
+    ))
+}
+
+/// Builds the descriptors of a `threshold`-of-n multisig wallet where the local key derived from
+/// the mnemonic is one of the n keys and the remaining ones are the provided cosigner xpubs as
+/// returned by [`get_multisig_xpub`].
+pub fn build_multisig_descriptors(
+    network: Network,
+    mnemonic_string: Vec<String>,
+    threshold: u32,
+    cosigner_xpubs: Vec<String>,
+) -> Result<Descriptors> {
+    let number_of_keys = cosigner_xpubs.len() + 1;
+    if number_of_keys > MAX_MULTISIG_KEYS {
+        return Err(invalid_input(format!(
+            "Invalid cosigners: at most {MAX_MULTISIG_KEYS} keys are supported"
+        )));
+    }
+    if threshold == 0 || threshold as usize > number_of_keys {
+        return Err(invalid_input(format!(
+            "Invalid threshold: must be between 1 and {number_of_keys}"
+        )));
+    }
+
+    let cosigner_keys = cosigner_xpubs
+        .iter()
+        .map(|xpub| validate_cosigner_xpub(network, xpub).map(|_| format!("{xpub}/0/*")))
+        .collect::<Result<Vec<_>>>()?
+        .join(",");
+
+    let mnemonic = Mnemonic::from_str(mnemonic_string.join(" ").as_str())
+        .map_to_invalid_input("Invalid mnemonic string")?;
+    let master_xpriv = get_master_xpriv(network, mnemonic)?;
+
+    let account_path = get_multisig_account_derivation_path(network);
+    let spend_key = build_descriptor_key(
+        master_xpriv,
+        "m",
+        &format!("{account_path}/0"),
+        DescriptorKind::Private,
+    )?;
+    let watch_key =
+        build_descriptor_key(master_xpriv, account_path, "m/0", DescriptorKind::Public)?;
+
+    let to_descriptor = |key: String| {
+        append_checksum(&format!(
+            "wsh(sortedmulti({threshold},{key},{cosigner_keys}))"
+        ))
+    };
+
+    Ok(Descriptors {
+        spend_descriptor: to_descriptor(spend_key)?,
+        watch_descriptor: to_descriptor(watch_key)?,
+        account_xpub: derive_account_xpub(master_xpriv, account_path)?.to_string(),
+        master_fingerprint: master_xpriv.fingerprint(SECP256K1).to_string(),
+        account_derivation_path: account_path.to_string(),
+    })
+}
+
+fn validate_cosigner_xpub(network: Network, xpub: &str) -> Result<()> {
+    let xkey = match DescriptorPublicKey::from_str(xpub) {
+        Ok(DescriptorPublicKey::XPub(xkey)) => xkey,
+        _ => {
+            return Err(invalid_input(format!(
+                "Invalid cosigner xpub {xpub}: expected an extended public key"
+            )))
+        }
+    };
+
+    if !xkey.derivation_path.is_master() || !matches!(xkey.wildcard, Wildcard::None) {
+        return Err(invalid_input(format!(
+            "Invalid cosigner xpub {xpub}: expected an account-level xpub without derivation path"
+        )));
+    }
+
+    let expected_network = match network {
+        Network::Bitcoin => Network::Bitcoin,
+        Network::Testnet | Network::Signet | Network::Regtest => Network::Testnet,
+    };
+    if xkey.xkey.network != expected_network {
+        return Err(invalid_input(format!(
+            "Invalid cosigner xpub {xpub}: xpub is for a different network"
+        )));
+    }
+
+    Ok(())
+}
+
+fn derive_auth_keypair(master_xpriv: ExtendedPrivKey, derivation_path: &str) -> Result<KeyPair> {
+    let lipa_purpose_path = DerivationPath::from_str(derivation_path)
         .map_to_permanent_failure("Failed to build derivation path")?;
 
     let auth_xpriv = master_xpriv
@@ -104,31 +476,99 @@ fn get_master_xpriv(network: Network, mnemonic: Mnemonic) -> Result<ExtendedPriv
     Ok(master_xpriv)
 }
 
-fn build_spend_descriptor(network: Network, master_xpriv: ExtendedPrivKey) -> Result<String> {
+fn validate_account_index(account_index: u32) -> Result<()> {
+    if account_index > MAX_ACCOUNT_INDEX {
+        return Err(invalid_input(format!(
+            "Invalid account index. Please use an index in the range [0; {MAX_ACCOUNT_INDEX}]"
+        )));
+    }
+    Ok(())
+}
+
+fn build_descriptors(
+    network: Network,
+    script_type: ScriptType,
+    account_index: u32,
+    master_xpriv: ExtendedPrivKey,
+) -> Result<Descriptors> {
+    let account_path = get_account_derivation_path(network, script_type, account_index);
+    Ok(Descriptors {
+        spend_descriptor: build_spend_descriptor(
+            network,
+            script_type,
+            account_index,
+            master_xpriv,
+        )?,
+        watch_descriptor: build_watch_descriptor(
+            network,
+            script_type,
+            account_index,
+            master_xpriv,
+        )?,
+        account_xpub: derive_account_xpub(master_xpriv, &account_path)?.to_string(),
+        master_fingerprint: master_xpriv.fingerprint(SECP256K1).to_string(),
+        account_derivation_path: account_path,
+    })
+}
+
+fn derive_account_xpub(
+    master_xpriv: ExtendedPrivKey,
+    account_path: &str,
+) -> Result<ExtendedPubKey> {
+    let account_derivation_path = DerivationPath::from_str(account_path)
+        .map_to_permanent_failure("Failed to build derivation path")?;
+    let account_xpriv = master_xpriv
+        .derive_priv(SECP256K1, &account_derivation_path)
+        .map_to_permanent_failure("Failed to derive keys")?;
+    Ok(ExtendedPubKey::from_priv(SECP256K1, &account_xpriv))
+}
+
+fn build_spend_descriptor(
+    network: Network,
+    script_type: ScriptType,
+    account_index: u32,
+    master_xpriv: ExtendedPrivKey,
+) -> Result<String> {
     // Directly embed the master extended key in the descriptor
     let origin_path = "m";
 
-    // Provide a BIP84 derivation path for the descriptor. It's built from the
-    // account derivation path concatenated with the "change" path ("/0")
-    let key_path = format!("{}{}", get_account_derivation_path(network), "/0");
+    // Provide a BIP84 (or BIP86 for Taproot) derivation path for the descriptor. It's built from
+    // the account derivation path concatenated with the "change" path ("/0")
+    let key_path = format!(
+        "{}{}",
+        get_account_derivation_path(network, script_type, account_index),
+        "/0"
+    );
 
     build_descriptor(
         master_xpriv,
         origin_path,
         key_path.as_str(),
+        script_type,
         DescriptorKind::Private,
     )
 }
 
-fn build_watch_descriptor(network: Network, master_xpriv: ExtendedPrivKey) -> Result<String> {
+fn build_watch_descriptor(
+    network: Network,
+    script_type: ScriptType,
+    account_index: u32,
+    master_xpriv: ExtendedPrivKey,
+) -> Result<String> {
     // Embed the account level extended key in the descriptor
-    let origin_path = get_account_derivation_path(network);
+    let origin_path = get_account_derivation_path(network, script_type, account_index);
 
     // The extended key in the descriptor is already the account-level one so we just need to set
     // the remaining part of the path
     let key_path = "m/0";
 
-    build_descriptor(master_xpriv, origin_path, key_path, DescriptorKind::Public)
+    build_descriptor(
+        master_xpriv,
+        &origin_path,
+        key_path,
+        script_type,
+        DescriptorKind::Public,
+    )
 }
 
 enum DescriptorKind {
@@ -144,9 +584,27 @@ enum DescriptorKind {
 /// from the master xpriv using this path
 /// - `key_derivation_path`: this is the derivation path that is applied to the embedded xkey when
 /// using the built descriptor
+/// - `script_type`: the type of output script the descriptor describes
 /// - `kind`: enum defining whether the xkey embedded in the returned descriptor should be an xpub
 /// or an xpriv
 fn build_descriptor(
+    master_xpriv: ExtendedPrivKey,
+    origin_derivation_path: &str,
+    key_derivation_path: &str,
+    script_type: ScriptType,
+    kind: DescriptorKind,
+) -> Result<String> {
+    let desc_key = build_descriptor_key(
+        master_xpriv,
+        origin_derivation_path,
+        key_derivation_path,
+        kind,
+    )?;
+    append_checksum(&key_to_descriptor(script_type, &desc_key))
+}
+
+/// Builds a ranged descriptor key (`[origin]xkey/path/*`) from the master key.
+fn build_descriptor_key(
     master_xpriv: ExtendedPrivKey,
     origin_derivation_path: &str,
     key_derivation_path: &str,
@@ -166,6 +624,8 @@ fn build_descriptor(
         extended_key_derivation_path,
     );
 
+    // The script context only restricts which keys are valid. Extended keys are valid in every
+    // context, so the Segwit v0 context can be used for Taproot descriptors too.
     let derived_xpriv_desc_key: DescriptorKey<Segwitv0> = derived_xpriv
         .into_descriptor_key(Some(origin), descriptor_derivation_path)
         .map_to_permanent_failure("Failed to get descriptor key from xpriv")?;
@@ -180,23 +640,64 @@ fn build_descriptor(
             }
             DescriptorKind::Private => desc_seckey.to_string(),
         };
-        Ok(key_to_wpkh_descriptor(&desc_key))
+        Ok(desc_key)
     } else {
         Err(permanent_failure("Failed to get descriptor from xpriv"))
     }
 }
 
-fn get_account_derivation_path(network: Network) -> &'static str {
+fn get_silent_payment_derivation_paths(network: Network) -> (&'static str, &'static str) {
+    match network {
+        Network::Bitcoin => (
+            SILENT_PAYMENT_SCAN_DERIVATION_PATH_MAINNET,
+            SILENT_PAYMENT_SPEND_DERIVATION_PATH_MAINNET,
+        ),
+        Network::Testnet | Network::Signet | Network::Regtest => (
+            SILENT_PAYMENT_SCAN_DERIVATION_PATH_TESTNET,
+            SILENT_PAYMENT_SPEND_DERIVATION_PATH_TESTNET,
+        ),
+    }
+}
+
+fn get_lightning_seed_derivation_path(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => LIGHTNING_SEED_DERIVATION_PATH_MAINNET,
+        Network::Testnet | Network::Signet | Network::Regtest => {
+            LIGHTNING_SEED_DERIVATION_PATH_TESTNET
+        }
+    }
+}
+
+fn get_multisig_account_derivation_path(network: Network) -> &'static str {
     match network {
-        Network::Bitcoin => ACCOUNT_DERIVATION_PATH_MAINNET,
-        Network::Testnet => ACCOUNT_DERIVATION_PATH_TESTNET,
-        Network::Signet => ACCOUNT_DERIVATION_PATH_TESTNET,
-        Network::Regtest => ACCOUNT_DERIVATION_PATH_TESTNET,
+        Network::Bitcoin => MULTISIG_ACCOUNT_DERIVATION_PATH_MAINNET,
+        Network::Testnet => MULTISIG_ACCOUNT_DERIVATION_PATH_TESTNET,
+        Network::Signet => MULTISIG_ACCOUNT_DERIVATION_PATH_TESTNET,
+        Network::Regtest => MULTISIG_ACCOUNT_DERIVATION_PATH_TESTNET,
     }
 }
 
-fn key_to_wpkh_descriptor(key: &str) -> String {
-    format!("wpkh({key})")
+fn get_account_derivation_path(
+    network: Network,
+    script_type: ScriptType,
+    account_index: u32,
+) -> String {
+    let purpose = match script_type {
+        ScriptType::Segwit => SEGWIT_PURPOSE,
+        ScriptType::Taproot => TAPROOT_PURPOSE,
+    };
+    let coin_type = match network {
+        Network::Bitcoin => COIN_TYPE_MAINNET,
+        Network::Testnet | Network::Signet | Network::Regtest => COIN_TYPE_TESTNET,
+    };
+    format!("m/{purpose}'/{coin_type}'/{account_index}'")
+}
+
+fn key_to_descriptor(script_type: ScriptType, key: &str) -> String {
+    match script_type {
+        ScriptType::Segwit => format!("wpkh({key})"),
+        ScriptType::Taproot => format!("tr({key})"),
+    }
 }
 
 pub fn generate_keypair() -> KeyPair {
@@ -210,10 +711,51 @@ pub fn generate_keypair() -> KeyPair {
     }
 }
 
+pub enum MnemonicLanguage {
+    English,
+    SimplifiedChinese,
+    TraditionalChinese,
+    Czech,
+    French,
+    Italian,
+    Japanese,
+    Korean,
+    Spanish,
+}
+
+impl From<MnemonicLanguage> for Language {
+    fn from(language: MnemonicLanguage) -> Self {
+        match language {
+            MnemonicLanguage::English => Language::English,
+            MnemonicLanguage::SimplifiedChinese => Language::SimplifiedChinese,
+            MnemonicLanguage::TraditionalChinese => Language::TraditionalChinese,
+            MnemonicLanguage::Czech => Language::Czech,
+            MnemonicLanguage::French => Language::French,
+            MnemonicLanguage::Italian => Language::Italian,
+            MnemonicLanguage::Japanese => Language::Japanese,
+            MnemonicLanguage::Korean => Language::Korean,
+            MnemonicLanguage::Spanish => Language::Spanish,
+        }
+    }
+}
+
 pub fn words_by_prefix(prefix: String) -> Vec<String> {
-    Language::English
+    words_by_prefix_with_language(prefix, MnemonicLanguage::English, 0, None)
+}
+
+pub fn words_by_prefix_with_language(
+    prefix: String,
+    language: MnemonicLanguage,
+    offset: u32,
+    limit: Option<u32>,
+) -> Vec<String> {
+    let limit = limit.map_or(usize::MAX, |limit| limit as usize);
+
+    Language::from(language)
         .words_by_prefix(&prefix)
         .iter()
+        .skip(offset as usize)
+        .take(limit)
         .map(|w| w.to_string())
         .collect()
 }
@@ -228,12 +770,26 @@ mod tests {
     // Values used for testing were obtained from https://iancoleman.io/bip39
     const NETWORK: Network = Network::Testnet;
     const MNEMONIC_STR: &str = "between angry ketchup hill admit attitude echo wisdom still barrel coral obscure home museum trick grow magic eagle school tilt loop actress equal law";
-    const SPEND_DESCRIPTOR: &str = "wpkh([aed2a027]tprv8ZgxMBicQKsPeT4bcpTNiHtBXqHRRPh4qMkWP4PahRJCGLd5A32RYUif9PJ8GMChWPB6yFFNGybZRGBFcsb9v9YifukeysfDAHDTzxRrtbi/84'/1'/0'/0/*)";
-    const WATCH_DESCRIPTOR: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/0/*)";
+    const SPEND_DESCRIPTOR: &str = "wpkh([aed2a027]tprv8ZgxMBicQKsPeT4bcpTNiHtBXqHRRPh4qMkWP4PahRJCGLd5A32RYUif9PJ8GMChWPB6yFFNGybZRGBFcsb9v9YifukeysfDAHDTzxRrtbi/84'/1'/0'/0/*)#lvf6tw8e";
+    const WATCH_DESCRIPTOR: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/0/*)#kfnxpz7u";
+
+    const TAPROOT_SPEND_DESCRIPTOR: &str = "tr([aed2a027]tprv8ZgxMBicQKsPeT4bcpTNiHtBXqHRRPh4qMkWP4PahRJCGLd5A32RYUif9PJ8GMChWPB6yFFNGybZRGBFcsb9v9YifukeysfDAHDTzxRrtbi/86'/1'/0'/0/*)#08wwz9cg";
+    const TAPROOT_WATCH_DESCRIPTOR: &str = "tr([aed2a027/86'/1'/0']tpubDDt26we3uvM1pEW3ztPbRYEzerwF9LLgaghX1BaUXuKsqvPFBrDisaQBvsEAbNmPgi33H2UdFVjiuFmze4uFZgQQx8X5wYF7bhK7SisDn6Q/0/*)#tshqr5tm";
+
+    // Test vector from BIP-86
+    const BIP86_MNEMONIC_STR: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const BIP86_WATCH_DESCRIPTOR: &str = "tr([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*)#rg247h69";
+
+    const MULTISIG_XPUB: &str = "[aed2a027/48'/1'/0'/2']tpubDFiSiEGzQSwPukWTpLaqsBgW49bg2ehZkjbodvSmGvsxvn1K6bbPKesfMLyrbVaz3C79V4uAsuS6kypWKuuB36giWXffqjZiiPtH9kNtxad";
+    const COSIGNER_1_XPUB: &str = "[73c5da0a/48'/1'/0'/2']tpubDFH9dgzveyD8zTbPUFuLrGmCydNvxehyNdUXKJAQN8x4aZ4j6UZqGfnqFrD4NqyaTVGKbvEW54tsvPTK2UoSbCC1PJY8iCNiwTL3RWZEheQ";
+    const COSIGNER_2_XPUB: &str = "[b8688df1/48'/1'/0'/2']tpubDEfobrrtptRTbKf4gysDhoabneABDTAcdj3Vbn4XwPsLE2pmqpizSPRG6zHsbAMuiSgWmWPsYCLHTKTPpyrGJ5rAoTpKoQNZcxodiPf2tSJ";
+    const MULTISIG_SPEND_DESCRIPTOR: &str = "wsh(sortedmulti(2,[aed2a027]tprv8ZgxMBicQKsPeT4bcpTNiHtBXqHRRPh4qMkWP4PahRJCGLd5A32RYUif9PJ8GMChWPB6yFFNGybZRGBFcsb9v9YifukeysfDAHDTzxRrtbi/48'/1'/0'/2'/0/*,[73c5da0a/48'/1'/0'/2']tpubDFH9dgzveyD8zTbPUFuLrGmCydNvxehyNdUXKJAQN8x4aZ4j6UZqGfnqFrD4NqyaTVGKbvEW54tsvPTK2UoSbCC1PJY8iCNiwTL3RWZEheQ/0/*,[b8688df1/48'/1'/0'/2']tpubDEfobrrtptRTbKf4gysDhoabneABDTAcdj3Vbn4XwPsLE2pmqpizSPRG6zHsbAMuiSgWmWPsYCLHTKTPpyrGJ5rAoTpKoQNZcxodiPf2tSJ/0/*))#nfvqg64k";
+    const MULTISIG_WATCH_DESCRIPTOR: &str = "wsh(sortedmulti(2,[aed2a027/48'/1'/0'/2']tpubDFiSiEGzQSwPukWTpLaqsBgW49bg2ehZkjbodvSmGvsxvn1K6bbPKesfMLyrbVaz3C79V4uAsuS6kypWKuuB36giWXffqjZiiPtH9kNtxad/0/*,[73c5da0a/48'/1'/0'/2']tpubDFH9dgzveyD8zTbPUFuLrGmCydNvxehyNdUXKJAQN8x4aZ4j6UZqGfnqFrD4NqyaTVGKbvEW54tsvPTK2UoSbCC1PJY8iCNiwTL3RWZEheQ/0/*,[b8688df1/48'/1'/0'/2']tpubDEfobrrtptRTbKf4gysDhoabneABDTAcdj3Vbn4XwPsLE2pmqpizSPRG6zHsbAMuiSgWmWPsYCLHTKTPpyrGJ5rAoTpKoQNZcxodiPf2tSJ/0/*))#ykh0uvlu";
 
     // The following corresponds to path "m/76738065'/0'/0"
-    //const AUTH_PUB_KEY: &str = "02549b15801b155d32ca3931665361b1d2997ee531859b2d48cebbc2ccf21aac96";
-    // For now we'll use the master key pair
+    const AUTH_PUB_KEY_V2: &str =
+        "02549b15801b155d32ca3931665361b1d2997ee531859b2d48cebbc2ccf21aac96";
+    // The master key pair
     const AUTH_PUB_KEY: &str = "0365704b042bdf2a8bf19714902242f9275ce7b0e2438a35dbb25133c49d1c8ef2";
 
     fn mnemonic_str_to_vec(mnemonic_str: &str) -> Vec<String> {
@@ -268,11 +824,233 @@ mod tests {
             WATCH_DESCRIPTOR.to_string()
         );
         assert_eq!(keys.wallet_keypair.public_key, AUTH_PUB_KEY.to_string());
+        assert_eq!(keys.wallet_descriptors.master_fingerprint, "aed2a027");
+        assert_eq!(
+            keys.wallet_descriptors.account_derivation_path,
+            "m/84'/1'/0'"
+        );
+        assert!(WATCH_DESCRIPTOR.contains(&format!(
+            "[aed2a027/84'/1'/0']{}/0/*",
+            keys.wallet_descriptors.account_xpub
+        )));
 
         // No need to check that the auth secret_key is correct because here we check the auth
         // public key and in `test_auth_keys_match()` we check that the keys match.
     }
 
+    #[test]
+    fn test_derive_keys_v2() {
+        let mnemonic_string = mnemonic_str_to_vec(MNEMONIC_STR);
+
+        let keys = derive_keys_v2(NETWORK, mnemonic_string).unwrap();
+
+        assert_eq!(keys.wallet_keypair.public_key, AUTH_PUB_KEY_V2.to_string());
+        assert_eq!(
+            keys.legacy_wallet_keypair.public_key,
+            AUTH_PUB_KEY.to_string()
+        );
+        assert_eq!(
+            keys.wallet_descriptors.watch_descriptor,
+            WATCH_DESCRIPTOR.to_string()
+        );
+        check_keys_match(keys.wallet_keypair);
+        check_keys_match(keys.legacy_wallet_keypair);
+    }
+
+    #[test]
+    fn test_derive_watch_descriptor_for_account() {
+        let descriptor =
+            derive_watch_descriptor_for_account(NETWORK, mnemonic_str_to_vec(MNEMONIC_STR), 0)
+                .unwrap();
+        assert_eq!(descriptor, WATCH_DESCRIPTOR);
+
+        let descriptor =
+            derive_watch_descriptor_for_account(NETWORK, mnemonic_str_to_vec(MNEMONIC_STR), 7)
+                .unwrap();
+        assert!(descriptor.starts_with("wpkh([aed2a027/84'/1'/7']tpub"));
+        assert!(descriptor.contains("/0/*)#"));
+        // Wallets derive the receive and change descriptors of any account.
+        assert!(crate::descriptor::get_change_descriptor(&descriptor)
+            .unwrap()
+            .contains("/1/*)"));
+
+        let result = derive_watch_descriptor_for_account(
+            NETWORK,
+            mnemonic_str_to_vec(MNEMONIC_STR),
+            MAX_ACCOUNT_INDEX + 1,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_derive_keys_for_account() {
+        let keys = derive_keys_for_account(
+            NETWORK,
+            mnemonic_str_to_vec(MNEMONIC_STR),
+            ScriptType::Segwit,
+            0,
+        )
+        .unwrap();
+        assert_eq!(keys.wallet_descriptors.spend_descriptor, SPEND_DESCRIPTOR);
+        assert_eq!(keys.wallet_descriptors.watch_descriptor, WATCH_DESCRIPTOR);
+
+        let keys = derive_keys_for_account(
+            NETWORK,
+            mnemonic_str_to_vec(MNEMONIC_STR),
+            ScriptType::Segwit,
+            1,
+        )
+        .unwrap();
+        assert!(keys
+            .wallet_descriptors
+            .spend_descriptor
+            .contains("/84'/1'/1'/0/*)"));
+        assert_eq!(
+            keys.wallet_descriptors.watch_descriptor,
+            derive_watch_descriptor_for_account(NETWORK, mnemonic_str_to_vec(MNEMONIC_STR), 1)
+                .unwrap()
+        );
+        assert_eq!(
+            keys.wallet_descriptors.account_derivation_path,
+            "m/84'/1'/1'"
+        );
+        // The wallet key pair doesn't depend on the account.
+        assert_eq!(keys.wallet_keypair.public_key, AUTH_PUB_KEY);
+    }
+
+    #[test]
+    fn test_derive_taproot_keys() {
+        let mnemonic_string = mnemonic_str_to_vec(MNEMONIC_STR);
+
+        let keys = derive_keys_with_script(NETWORK, mnemonic_string, ScriptType::Taproot).unwrap();
+
+        assert_eq!(
+            keys.wallet_descriptors.spend_descriptor,
+            TAPROOT_SPEND_DESCRIPTOR.to_string()
+        );
+        assert_eq!(
+            keys.wallet_descriptors.watch_descriptor,
+            TAPROOT_WATCH_DESCRIPTOR.to_string()
+        );
+        // The auth keys don't depend on the script type.
+        assert_eq!(keys.wallet_keypair.public_key, AUTH_PUB_KEY.to_string());
+
+        let mnemonic_string = mnemonic_str_to_vec(BIP86_MNEMONIC_STR);
+        let keys = derive_keys_with_script(Network::Bitcoin, mnemonic_string, ScriptType::Taproot)
+            .unwrap();
+        assert_eq!(
+            keys.wallet_descriptors.watch_descriptor,
+            BIP86_WATCH_DESCRIPTOR.to_string()
+        );
+    }
+
+    #[test]
+    fn test_get_multisig_xpub() {
+        let mnemonic_string = mnemonic_str_to_vec(MNEMONIC_STR);
+
+        let xpub = get_multisig_xpub(NETWORK, mnemonic_string).unwrap();
+
+        assert_eq!(xpub, MULTISIG_XPUB.to_string());
+    }
+
+    #[test]
+    fn test_build_multisig_descriptors() {
+        let cosigner_xpubs = vec![COSIGNER_1_XPUB.to_string(), COSIGNER_2_XPUB.to_string()];
+
+        let descriptors = build_multisig_descriptors(
+            NETWORK,
+            mnemonic_str_to_vec(MNEMONIC_STR),
+            2,
+            cosigner_xpubs.clone(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            descriptors.spend_descriptor,
+            MULTISIG_SPEND_DESCRIPTOR.to_string()
+        );
+        assert_eq!(
+            descriptors.watch_descriptor,
+            MULTISIG_WATCH_DESCRIPTOR.to_string()
+        );
+        assert_eq!(
+            format!(
+                "[{}{}]{}",
+                descriptors.master_fingerprint,
+                descriptors.account_derivation_path.trim_start_matches('m'),
+                descriptors.account_xpub
+            ),
+            MULTISIG_XPUB
+        );
+
+        let result = build_multisig_descriptors(
+            NETWORK,
+            mnemonic_str_to_vec(MNEMONIC_STR),
+            4,
+            cosigner_xpubs,
+        );
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Invalid threshold: must be between 1 and 3"));
+
+        let result = build_multisig_descriptors(
+            NETWORK,
+            mnemonic_str_to_vec(MNEMONIC_STR),
+            2,
+            vec![format!("{COSIGNER_1_XPUB}/0/*")],
+        );
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("expected an account-level xpub without derivation path"));
+
+        let result = build_multisig_descriptors(
+            Network::Bitcoin,
+            mnemonic_str_to_vec(MNEMONIC_STR),
+            2,
+            vec![COSIGNER_1_XPUB.to_string()],
+        );
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("xpub is for a different network"));
+    }
+
+    #[test]
+    fn test_derive_silent_payment_keys() {
+        let mnemonic_string: Vec<String> =
+            BIP86_MNEMONIC_STR.split(' ').map(String::from).collect();
+        let keys = derive_silent_payment_keys(Network::Bitcoin, mnemonic_string).unwrap();
+        assert_eq!(
+            keys.spend_keypair.public_key,
+            "02fa210b3c4a60b80dd1616f48ae53bbdf0db744b3f9083385108f81be0acb58c6"
+        );
+    }
+
+    #[test]
+    fn test_derive_ldk_seed() {
+        let seed = derive_ldk_seed(Network::Bitcoin, mnemonic_str_to_vec(MNEMONIC_STR)).unwrap();
+        assert_eq!(
+            seed.to_hex(),
+            "2723e36816f9974edef9c63ebfa335f46ec24df3997b681a50eceb6c3b120433"
+        );
+
+        let seed = derive_ldk_seed(NETWORK, mnemonic_str_to_vec(MNEMONIC_STR)).unwrap();
+        assert_eq!(
+            seed.to_hex(),
+            "1d62ea1d042ba15ff6f7b421174f15b4ea39736cf774300c8c0f07feea9e06ab"
+        );
+
+        assert!(derive_ldk_seed(NETWORK, mnemonic_str_to_vec("invalid mnemonic")).is_err());
+    }
+
     #[test]
     fn test_auth_keys_encode_decode() {
         let mnemonic_string = mnemonic_str_to_vec(MNEMONIC_STR);
@@ -324,11 +1102,64 @@ mod tests {
 
         let master_xpriv = get_master_xpriv(NETWORK, mnemonic).unwrap();
 
-        let keypair = derive_auth_keypair(master_xpriv).unwrap();
+        let keypair = derive_auth_keypair(master_xpriv, BACKEND_AUTH_DERIVATION_PATH).unwrap();
 
         check_keys_match(keypair);
     }
 
+    #[test]
+    fn test_generate_mnemonic_with_length() {
+        let mnemonic = generate_mnemonic_with_length(MnemonicLength::Words12).unwrap();
+        assert_eq!(mnemonic.len(), 12);
+        let mnemonic = generate_mnemonic_with_length(MnemonicLength::Words18).unwrap();
+        assert_eq!(mnemonic.len(), 18);
+        let mnemonic = generate_mnemonic_with_length(MnemonicLength::Words24).unwrap();
+        assert_eq!(mnemonic.len(), 24);
+
+        // Shorter mnemonics are just as usable for deriving keys
+        let mnemonic = generate_mnemonic_with_length(MnemonicLength::Words12).unwrap();
+        assert!(derive_keys(NETWORK, mnemonic).is_ok());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_mnemonic() {
+        let mnemonic_string = mnemonic_str_to_vec(MNEMONIC_STR);
+
+        let blob = encrypt_mnemonic(mnemonic_string.clone(), "password".to_string()).unwrap();
+        assert_eq!(blob[0], BACKUP_VERSION_1);
+        // The salt and nonce are random, encrypting twice yields different blobs.
+        let other_blob = encrypt_mnemonic(mnemonic_string.clone(), "password".to_string()).unwrap();
+        assert_ne!(blob, other_blob);
+
+        let decrypted = decrypt_mnemonic(blob.clone(), "password".to_string()).unwrap();
+        assert_eq!(decrypted, mnemonic_string);
+
+        let result = decrypt_mnemonic(blob.clone(), "wrong password".to_string());
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Failed to decrypt backup: wrong password or corrupted backup"));
+
+        let mut tampered_blob = blob.clone();
+        *tampered_blob.last_mut().unwrap() ^= 1;
+        assert!(decrypt_mnemonic(tampered_blob, "password".to_string()).is_err());
+
+        let mut other_version_blob = blob;
+        other_version_blob[0] = 2;
+        let result = decrypt_mnemonic(other_version_blob, "password".to_string());
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Invalid backup: unsupported version 2"));
+
+        let result = encrypt_mnemonic(vec!["invalid".to_string()], "password".to_string());
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_generate_keypair() {
         let keypair = generate_keypair();
@@ -346,4 +1177,28 @@ mod tests {
         assert_eq!(words_by_prefix("scheme".to_string()), vec!["scheme"]);
         assert_eq!(words_by_prefix("schemelol".to_string()).len(), 0);
     }
+
+    #[test]
+    fn test_words_by_prefix_with_language() {
+        let spanish = |prefix: &str, offset, limit| {
+            words_by_prefix_with_language(
+                prefix.to_string(),
+                MnemonicLanguage::Spanish,
+                offset,
+                limit,
+            )
+        };
+        assert_eq!(spanish("", 0, None).len(), 2048);
+        assert_eq!(spanish("", 0, None)[0], "ábaco");
+        assert_eq!(spanish("", 2040, None).len(), 8);
+        assert_eq!(spanish("", 0, Some(10)).len(), 10);
+        assert_eq!(spanish("", 10, Some(10)), spanish("", 0, Some(20))[10..]);
+        assert_eq!(spanish("abr", 0, None), vec!["abrazo", "abrir"]);
+        assert_eq!(spanish("abr", 1, Some(1)), vec!["abrir"]);
+        assert_eq!(spanish("abr", 2, None).len(), 0);
+
+        let french =
+            words_by_prefix_with_language("abe".to_string(), MnemonicLanguage::French, 0, None);
+        assert_eq!(french, vec!["abeille"]);
+    }
 }
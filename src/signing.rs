@@ -1,26 +1,88 @@
 use crate::errors::Result;
 use bdk::bitcoin::hashes::hex::FromHex;
 use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::secp256k1::ecdsa::Signature;
 use bdk::bitcoin::secp256k1::Message;
-use bdk::bitcoin::secp256k1::SecretKey;
+use bdk::bitcoin::secp256k1::{PublicKey, SecretKey};
+use bdk::bitcoin::util::misc::{signed_msg_hash, MessageSignature};
+use bdk::bitcoin::Address;
 use perro::MapToError;
 use secp256k1::SECP256K1;
+use std::str::FromStr;
 
 pub fn sign(message: String, private_key: String) -> Result<String> {
     let message = Message::from_hashed_data::<sha256::Hash>(message.as_bytes());
-    let secret_key_bytes =
-        Vec::from_hex(&private_key).map_to_invalid_input("Invalid private key string")?;
-    let secret_key = SecretKey::from_slice(secret_key_bytes.as_slice())
-        .map_to_invalid_input("Invalid private key string")?;
+    let secret_key = parse_secret_key(&private_key)?;
 
     let sig = SECP256K1.sign_ecdsa(&message, &secret_key);
 
     Ok(sig.serialize_der().to_string())
 }
 
+/// Verifies a hex encoded DER signature created by [`sign`] against a hex encoded public key.
+pub fn verify(message: String, signature: String, public_key: String) -> Result<bool> {
+    let message = Message::from_hashed_data::<sha256::Hash>(message.as_bytes());
+    let signature_bytes =
+        Vec::from_hex(&signature).map_to_invalid_input("Invalid signature string")?;
+    let signature = Signature::from_der(signature_bytes.as_slice())
+        .map_to_invalid_input("Invalid signature string")?;
+    let public_key_bytes =
+        Vec::from_hex(&public_key).map_to_invalid_input("Invalid public key string")?;
+    let public_key = PublicKey::from_slice(public_key_bytes.as_slice())
+        .map_to_invalid_input("Invalid public key string")?;
+
+    Ok(SECP256K1
+        .verify_ecdsa(&message, &signature, &public_key)
+        .is_ok())
+}
+
+/// Signs a message in the "Bitcoin Signed Message" format (as used by Bitcoin Core's
+/// `signmessage` and Electrum). The signature is returned base64 encoded.
+pub fn sign_message_recoverable(message: String, private_key: String) -> Result<String> {
+    let msg_hash = signed_msg_hash(&message);
+    let message = Message::from_slice(&msg_hash[..])
+        .map_to_permanent_failure("Failed to build message from hash")?;
+    let secret_key = parse_secret_key(&private_key)?;
+
+    let sig = SECP256K1.sign_ecdsa_recoverable(&message, &secret_key);
+
+    Ok(MessageSignature::new(sig, true).to_base64())
+}
+
+/// Verifies a base64 encoded "Bitcoin Signed Message" signature against an address.
+///
+/// Besides P2PKH addresses, signatures are also accepted for the P2WPKH and P2SH-P2WPKH
+/// addresses of the signing key (BIP-137).
+pub fn verify_message(message: String, signature: String, address: String) -> Result<bool> {
+    let signature = MessageSignature::from_base64(signature.trim())
+        .map_to_invalid_input("Invalid signature")?;
+    let address = Address::from_str(address.trim()).map_to_invalid_input("Invalid address")?;
+
+    let public_key = match signature.recover_pubkey(SECP256K1, signed_msg_hash(&message)) {
+        Ok(public_key) => public_key,
+        Err(_) => return Ok(false),
+    };
+
+    let network = address.network;
+    let signing_addresses = [
+        Some(Address::p2pkh(&public_key, network)),
+        Address::p2wpkh(&public_key, network).ok(),
+        Address::p2shwpkh(&public_key, network).ok(),
+    ];
+
+    Ok(signing_addresses.contains(&Some(address)))
+}
+
+pub(crate) fn parse_secret_key(private_key: &str) -> Result<SecretKey> {
+    let secret_key_bytes =
+        Vec::from_hex(private_key).map_to_invalid_input("Invalid private key string")?;
+    SecretKey::from_slice(secret_key_bytes.as_slice())
+        .map_to_invalid_input("Invalid private key string")
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::signing::sign;
+    use crate::signing::{sign, sign_message_recoverable, verify, verify_message};
     use crate::{derive_keys, generate_mnemonic};
     use bdk::bitcoin::hashes::hex::FromHex;
     use bdk::bitcoin::hashes::sha256;
@@ -46,6 +108,19 @@ mod tests {
         "eaec97b1abc09d70a582c9459972dfb28b1be1cdfc2dace4f853a47c54af1891";
     const AUTH_PUB_KEY_HEX: &str = "0498cc3defcb5facb3b6a9042f61c9a85593f803fee89338eeceabf351d6db380598e44320c65e1765ebcdf2a5a7eb5fdeffd262732e48ef04828574e486c62f03";
     const CHALLENGE_WITH_PREFIX: &str = "x18Bitcoin Signed Message:\neyJ0eXAiOiJKV1QiLCJhbGciOiJFUzI1NksifQ.eyJyZCI6IlZLZDExeEI1MXlkb2hiQTNibThMTmZNaFVOWXNEWXdFZi9ITkV6MDloZkcrVkV5MlV4THMvMlUwb3A5NkdGMkxmODZ2RkhpNWd2dGhsbG0zTTVXQjByZ1FjRmluOXljSTFoemFMRHNOdnhUaFoyY0FJL1B4cjNWS2J2YmpOR2dKVVJORytmSHVSWTk0K2RjWUxtemVRYzlRcy9POWVxQXdOSnoxQnFtbVFabDRaY1dwTE10MDc2eExtSXFiYTV3VXdpcmFnMzMyYlNMRW00ODAvdUp6OVpBeXBDS0dyY0NtUGlRQno4Y2lKeURpaUdKdGYzajZrTjN1N1cwS0l6bjZ0UFNzZndmaUNheG9FRFptZXpJYkJaSFRaUGtOUjFrZ2pvTG9nUzZGOEdDRlY5QXBHNWZERVA0bCtpb2piT05QcHEzakhjaHY4eTdCdFhXc3RBbTdGUT09IiwiZXhwIjoxNjQ5MTQzODM0fQ.MEQCIAURfxuhMcc0VtsfNCXLuTVC_l8HKocJuSNNn2n6t8n8AiB5yWezmYIsgsMa2aGSY-TjGOgQy7JP_8sTnQRsrp0IaA";
+    // Values obtained from the rust-bitcoin MessageSignature tests
+    const SIGNED_MESSAGE: &str = "rust-bitcoin MessageSignature test";
+    const SIGNED_MESSAGE_SIG: &str =
+        "IAM2qX24tYx/bdBTIgVLhD8QEAjrPlJpmjB4nZHdRYGIBa4DmVulAcwjPnWe6Q5iEwXH6F0pUCJP/ZeHPWS1h1o=";
+    const SIGNED_MESSAGE_ADDRESS: &str = "1DUm3t5rF5yMD6r1VbCmgbPDe6LAVYVGrQ";
+
+    const RECOVERABLE_SIG_GOLDEN: &str =
+        "H4OScdHAPAnfKgkIpA2XuDzDQqgHmlBOROZWRnDjeeLtAgwfQqRneRfRgWzmVXDJToHXSIViwMDrRaOijreOufs=";
+    // Addresses of the key EC_PRIVATE_KEY_HEX
+    const EC_P2PKH_ADDRESS: &str = "1FhnX5KaFjJbjDUSQAhNKwQMd8Mvm6qCLB";
+    const EC_P2WPKH_ADDRESS: &str = "bc1q59yx273hkexrhuga7r9nluz5xun8yh6yje5k9x";
+    const EC_P2SH_P2WPKH_ADDRESS: &str = "3Q2Xe8xfEoomoTXzmnAKGZeQMpEFEEqYEN";
+
     const SIGNED_CHALLENGE_GOLDEN: &str = "3045022100a08d08cb2a6afb5592e7d911ecbc0373bc601e4b5dd3515e3d45ed5d3c708b760220765e6477a88f25594d068a0782c47755830b84059c902879ba349dab4dc8b699";
 
     fn verify_sig(message: String, signature: String, public_key: String) -> Result<(), Error> {
@@ -90,4 +165,98 @@ mod tests {
         verify_sig(CHALLENGE_WITH_PREFIX.to_string(), sig.clone(), public_key).unwrap();
         assert_eq!(sig, SIGNED_CHALLENGE_GOLDEN.to_string());
     }
+
+    #[test]
+    fn test_sign_message_recoverable() {
+        let sig = sign_message_recoverable(MESSAGE_STR.to_string(), EC_PRIVATE_KEY_HEX.to_string())
+            .unwrap();
+        assert_eq!(sig, RECOVERABLE_SIG_GOLDEN.to_string());
+
+        for address in [EC_P2PKH_ADDRESS, EC_P2WPKH_ADDRESS, EC_P2SH_P2WPKH_ADDRESS] {
+            assert!(
+                verify_message(MESSAGE_STR.to_string(), sig.clone(), address.to_string()).unwrap()
+            );
+        }
+
+        assert!(!verify_message(
+            "Another message".to_string(),
+            sig.clone(),
+            EC_P2PKH_ADDRESS.to_string()
+        )
+        .unwrap());
+        assert!(!verify_message(
+            MESSAGE_STR.to_string(),
+            sig,
+            SIGNED_MESSAGE_ADDRESS.to_string()
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_verify_message_precomputed_value() {
+        assert!(verify_message(
+            SIGNED_MESSAGE.to_string(),
+            SIGNED_MESSAGE_SIG.to_string(),
+            SIGNED_MESSAGE_ADDRESS.to_string()
+        )
+        .unwrap());
+
+        assert!(verify_message(
+            SIGNED_MESSAGE.to_string(),
+            "invalid".to_string(),
+            SIGNED_MESSAGE_ADDRESS.to_string()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let public_key = EC_PUBLIC_KEY_HEX.to_string();
+
+        assert!(verify(
+            MESSAGE_STR.to_string(),
+            SIG_GOLDEN.to_string(),
+            public_key.clone()
+        )
+        .unwrap());
+        assert!(verify(
+            CHALLENGE_WITH_PREFIX.to_string(),
+            SIGNED_CHALLENGE_GOLDEN.to_string(),
+            AUTH_PUB_KEY_HEX.to_string()
+        )
+        .unwrap());
+
+        assert!(!verify(
+            "Another message".to_string(),
+            SIG_GOLDEN.to_string(),
+            public_key.clone()
+        )
+        .unwrap());
+        assert!(!verify(
+            MESSAGE_STR.to_string(),
+            SIG_GOLDEN.to_string(),
+            AUTH_PUB_KEY_HEX.to_string()
+        )
+        .unwrap());
+
+        let result = verify(MESSAGE_STR.to_string(), "30440220".to_string(), public_key);
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Invalid signature string"));
+
+        let result = verify(
+            MESSAGE_STR.to_string(),
+            SIG_GOLDEN.to_string(),
+            "04e2ad".to_string(),
+        );
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Invalid public key string"));
+    }
 }
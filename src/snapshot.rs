@@ -0,0 +1,264 @@
+use bdk::bitcoin::{OutPoint, Txid};
+use bdk::database::{BatchDatabase, BatchOperations, Database, MemoryDatabase};
+use bdk::{Error, KeychainKind};
+use std::collections::HashSet;
+
+// Copies the wallet data into an in-memory database, so that it can be synced without blocking
+// readers of the original database.
+pub(crate) fn take_snapshot<D: Database>(database: &D) -> Result<MemoryDatabase, Error> {
+    let mut snapshot = MemoryDatabase::new();
+    copy_into(database, &mut snapshot)?;
+    Ok(snapshot)
+}
+
+// Writes the data of a synced snapshot to the database in a single batch, so that a crash can't
+// leave it partially updated. UTXOs and txs that are no longer in the snapshot (e.g. spent UTXOs or
+// txs dropped from the mempool) are removed. Addresses revealed while the snapshot was synced are
+// kept.
+pub(crate) fn apply_snapshot<D: BatchDatabase>(
+    snapshot: &MemoryDatabase,
+    database: &mut D,
+) -> Result<(), Error> {
+    let mut batch = database.begin_batch();
+
+    let outpoints: HashSet<OutPoint> = snapshot
+        .iter_utxos()?
+        .into_iter()
+        .map(|utxo| utxo.outpoint)
+        .collect();
+    for utxo in database.iter_utxos()? {
+        if !outpoints.contains(&utxo.outpoint) {
+            batch.del_utxo(&utxo.outpoint)?;
+        }
+    }
+    let txids: HashSet<Txid> = snapshot
+        .iter_txs(false)?
+        .into_iter()
+        .map(|tx| tx.txid)
+        .collect();
+    for tx in database.iter_txs(false)? {
+        if !txids.contains(&tx.txid) {
+            batch.del_tx(&tx.txid, false)?;
+        }
+    }
+    let raw_txids: HashSet<Txid> = snapshot
+        .iter_raw_txs()?
+        .iter()
+        .map(|tx| tx.txid())
+        .collect();
+    for tx in database.iter_raw_txs()? {
+        if !raw_txids.contains(&tx.txid()) {
+            batch.del_raw_tx(&tx.txid())?;
+        }
+    }
+
+    copy_into(snapshot, &mut batch)?;
+    // Overwrites the last indices of the snapshot in the batch if more addresses were revealed.
+    for keychain in [KeychainKind::External, KeychainKind::Internal] {
+        if let Some(index) = database.get_last_index(keychain)? {
+            if snapshot.get_last_index(keychain)? < Some(index) {
+                batch.set_last_index(keychain, index)?;
+            }
+        }
+    }
+
+    database.commit_batch(batch)
+}
+
+// BDK only asks the backend for the confirmation of txs it considers unconfirmed, so a tx whose
+// block got reorged out would stay confirmed forever. Forgetting the confirmations of the last
+// `depth` blocks before syncing makes BDK fetch them again, so such txs go back to the mempool or
+// get the height of the block that confirms them on the new chain.
+pub(crate) fn forget_recent_confirmations(
+    snapshot: &mut MemoryDatabase,
+    depth: u32,
+) -> Result<(), Error> {
+    let tip_height = match snapshot.get_sync_time()? {
+        Some(sync_time) => sync_time.block_time.height,
+        None => return Ok(()),
+    };
+    for mut tx in snapshot.iter_txs(false)? {
+        let is_recent = match &tx.confirmation_time {
+            Some(block_time) => block_time.height.saturating_add(depth) > tip_height,
+            None => false,
+        };
+        if is_recent {
+            tx.confirmation_time = None;
+            snapshot.set_tx(&tx)?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_into<D: Database, B: BatchOperations>(from: &D, to: &mut B) -> Result<(), Error> {
+    for script_pubkey in from.iter_script_pubkeys(None)? {
+        if let Some((keychain, child)) = from.get_path_from_script_pubkey(&script_pubkey)? {
+            to.set_script_pubkey(&script_pubkey, keychain, child)?;
+        }
+    }
+    for utxo in from.iter_utxos()? {
+        to.set_utxo(&utxo)?;
+    }
+    for tx in from.iter_raw_txs()? {
+        to.set_raw_tx(&tx)?;
+    }
+    for tx in from.iter_txs(false)? {
+        to.set_tx(&tx)?;
+    }
+    for keychain in [KeychainKind::External, KeychainKind::Internal] {
+        if let Some(index) = from.get_last_index(keychain)? {
+            to.set_last_index(keychain, index)?;
+        }
+    }
+    if let Some(sync_time) = from.get_sync_time()? {
+        to.set_sync_time(sync_time)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::{PackedLockTime, Script, Transaction, TxOut};
+    use bdk::database::SyncTime;
+    use bdk::{BlockTime, LocalUtxo, TransactionDetails};
+
+    fn create_tx(value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: Vec::new(),
+            output: vec![TxOut {
+                value,
+                script_pubkey: Script::new(),
+            }],
+        }
+    }
+
+    fn store_tx(database: &mut MemoryDatabase, tx: &Transaction) {
+        database.set_raw_tx(tx).unwrap();
+        database
+            .set_tx(&TransactionDetails {
+                transaction: None,
+                txid: tx.txid(),
+                received: tx.output[0].value,
+                sent: 0,
+                fee: None,
+                confirmation_time: None,
+            })
+            .unwrap();
+        database
+            .set_utxo(&LocalUtxo {
+                outpoint: OutPoint::new(tx.txid(), 0),
+                txout: tx.output[0].clone(),
+                keychain: KeychainKind::External,
+                is_spent: false,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_forget_recent_confirmations() {
+        let old_tx = create_tx(1_000);
+        let recent_tx = create_tx(2_000);
+        let unconfirmed_tx = create_tx(3_000);
+
+        let mut snapshot = MemoryDatabase::new();
+        for (tx, height) in [
+            (&old_tx, Some(95)),
+            (&recent_tx, Some(96)),
+            (&unconfirmed_tx, None),
+        ] {
+            snapshot
+                .set_tx(&TransactionDetails {
+                    transaction: None,
+                    txid: tx.txid(),
+                    received: tx.output[0].value,
+                    sent: 0,
+                    fee: None,
+                    confirmation_time: height.map(|height| BlockTime {
+                        height,
+                        timestamp: 1_700_000_000,
+                    }),
+                })
+                .unwrap();
+        }
+        // Without a sync time the tip is unknown, so nothing is forgotten.
+        forget_recent_confirmations(&mut snapshot, 5).unwrap();
+        assert!(snapshot
+            .get_tx(&recent_tx.txid(), false)
+            .unwrap()
+            .unwrap()
+            .confirmation_time
+            .is_some());
+
+        snapshot
+            .set_sync_time(SyncTime {
+                block_time: BlockTime {
+                    height: 100,
+                    timestamp: 1_700_000_000,
+                },
+            })
+            .unwrap();
+        forget_recent_confirmations(&mut snapshot, 5).unwrap();
+
+        let get_height = |txid| {
+            snapshot
+                .get_tx(&txid, false)
+                .unwrap()
+                .unwrap()
+                .confirmation_time
+                .map(|block_time| block_time.height)
+        };
+        assert_eq!(get_height(old_tx.txid()), Some(95));
+        assert_eq!(get_height(recent_tx.txid()), None);
+        assert_eq!(get_height(unconfirmed_tx.txid()), None);
+    }
+
+    #[test]
+    fn test_apply_snapshot() {
+        let dropped_tx = create_tx(1_000);
+        let received_tx = create_tx(2_000);
+
+        let mut database = MemoryDatabase::new();
+        store_tx(&mut database, &dropped_tx);
+        database.set_last_index(KeychainKind::External, 5).unwrap();
+        database.set_last_index(KeychainKind::Internal, 1).unwrap();
+
+        let mut snapshot = take_snapshot(&database).unwrap();
+        assert_eq!(snapshot.iter_utxos().unwrap().len(), 1);
+        // The sync found out that the tx was dropped and found a new one, while an address was
+        // revealed in the database.
+        snapshot.del_tx(&dropped_tx.txid(), true).unwrap();
+        snapshot
+            .del_utxo(&OutPoint::new(dropped_tx.txid(), 0))
+            .unwrap();
+        store_tx(&mut snapshot, &received_tx);
+        snapshot.set_last_index(KeychainKind::Internal, 3).unwrap();
+        database.set_last_index(KeychainKind::External, 6).unwrap();
+
+        apply_snapshot(&snapshot, &mut database).unwrap();
+
+        let txids: Vec<Txid> = database
+            .iter_txs(false)
+            .unwrap()
+            .into_iter()
+            .map(|tx| tx.txid)
+            .collect();
+        assert_eq!(txids, vec![received_tx.txid()]);
+        assert_eq!(database.iter_raw_txs().unwrap(), vec![received_tx.clone()]);
+        assert_eq!(
+            database.iter_utxos().unwrap()[0].outpoint,
+            OutPoint::new(received_tx.txid(), 0)
+        );
+        assert_eq!(database.iter_utxos().unwrap().len(), 1);
+        assert_eq!(
+            database.get_last_index(KeychainKind::External).unwrap(),
+            Some(6)
+        );
+        assert_eq!(
+            database.get_last_index(KeychainKind::Internal).unwrap(),
+            Some(3)
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use crate::errors::Result;
+use bdk::bitcoin::{BlockHash, Txid};
+use bdk::electrum_client::{Client, ElectrumApi};
+use log::debug;
+use perro::{permanent_failure, MapToError};
+use std::process::{Command, Output};
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+
+const ELECTRUM_URL: &str = "tcp://localhost:50000";
+const MAX_WAITING_SECS: u8 = 15;
+
+// Controls the regtest network run by Nigiri (https://nigiri.vulpem.com), so that apps can write
+// integration tests against the lib without a public network. The `nigiri` CLI and Docker need to
+// be installed. All controllers share the same network, so tests using it must run sequentially.
+#[derive(Default)]
+pub struct RegtestController {}
+
+impl RegtestController {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    // The URL to use for `BlockchainBackend::Electrum` in the wallet config.
+    pub fn electrum_url(&self) -> String {
+        ELECTRUM_URL.to_string()
+    }
+
+    // Starts a blank network, deleting the state of the previous one.
+    pub fn start(&self) -> Result<()> {
+        self.stop()?;
+        self.resume()
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        debug!("NIGIRI stopping ...");
+        exec(&["nigiri", "stop", "--delete"])?;
+        Ok(())
+    }
+
+    // Stops the network without deleting its state, e.g. to test how the wallet handles an
+    // unreachable backend.
+    pub fn pause(&self) -> Result<()> {
+        debug!("NIGIRI pausing (stopping without resetting)...");
+        exec(&["nigiri", "stop"])?;
+        Ok(())
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        debug!("NIGIRI starting ...");
+        exec(&["nigiri", "start", "--ci"])?;
+        debug!("Waiting for Electrum ...");
+        wait_until("Electrum to start", || Ok(connect()?.ping().is_ok()))
+    }
+
+    pub fn get_block_height(&self) -> Result<u32> {
+        let stdout = exec(&["nigiri", "rpc", "getblockcount"])?;
+        stdout
+            .trim()
+            .parse()
+            .map_to_permanent_failure("Invalid block count")
+    }
+
+    // Returns once Electrum has seen the new blocks.
+    pub fn mine_blocks(&self, block_amount: u32) -> Result<()> {
+        let target_height = self.get_block_height()? + block_amount;
+        exec(&["nigiri", "rpc", "-generate", &block_amount.to_string()])?;
+        self.wait_for_electrum_height(target_height)
+    }
+
+    // Sends the amount from the faucet and confirms the tx in a new block.
+    pub fn fund_address(&self, amount_btc: f32, address: &str) -> Result<Txid> {
+        debug!("Funding {} btc onto {} ...", amount_btc, address);
+        let stdout = exec(&["nigiri", "faucet", address, &amount_btc.to_string()])?;
+        let (_, txid) = stdout
+            .split_once(' ')
+            .ok_or_else(|| permanent_failure(format!("Unexpected faucet output: {stdout}")))?;
+        let txid = parse_txid(txid)?;
+        self.wait_for_tx(&txid)?;
+        Ok(txid)
+    }
+
+    // Sends the amount from the faucet, leaving the tx in the mempool.
+    pub fn fund_address_without_conf(&self, amount_btc: f32, address: &str) -> Result<Txid> {
+        debug!(
+            "Funding {} btc onto {} without automatic confirmation...",
+            amount_btc, address
+        );
+        let stdout = exec(&[
+            "nigiri",
+            "rpc",
+            "sendtoaddress",
+            address,
+            &amount_btc.to_string(),
+        ])?;
+        let txid = parse_txid(&stdout)?;
+        self.wait_for_tx(&txid)?;
+        Ok(txid)
+    }
+
+    // Replaces the last `depth` blocks with `depth + 1` new ones, so that the new chain wins. Txs
+    // of the replaced blocks go back to the mempool and are confirmed again in the new blocks.
+    pub fn reorg(&self, depth: u32) -> Result<()> {
+        let height = self.get_block_height()?;
+        if depth == 0 || depth > height {
+            return Err(permanent_failure(format!(
+                "Cannot reorg {depth} blocks at height {height}"
+            )));
+        }
+        debug!("Reorging the last {} blocks ...", depth);
+        self.invalidate_block(&self.get_block_hash(height - depth + 1)?)?;
+        self.mine_blocks(depth + 1)
+    }
+
+    // Removes the block and its descendants from the chain, so that the tip goes back to its
+    // parent. Their txs go back to the mempool until new blocks are mined.
+    pub fn invalidate_block(&self, hash: &BlockHash) -> Result<()> {
+        debug!("Invalidating block {} ...", hash);
+        exec(&["nigiri", "rpc", "invalidateblock", &hash.to_string()])?;
+        self.wait_for_electrum_height(self.get_block_height()?)
+    }
+
+    pub fn get_block_hash(&self, height: u32) -> Result<BlockHash> {
+        let stdout = exec(&["nigiri", "rpc", "getblockhash", &height.to_string()])?;
+        BlockHash::from_str(stdout.trim()).map_to_permanent_failure("Invalid block hash")
+    }
+
+    pub fn wait_for_tx(&self, txid: &Txid) -> Result<()> {
+        debug!("Waiting for Electrum to see tx {} ...", txid);
+        let client = connect()?;
+        wait_until(&format!("Electrum to see tx {txid}"), || {
+            Ok(client.transaction_get(txid).is_ok())
+        })
+    }
+
+    fn wait_for_electrum_height(&self, height: u32) -> Result<()> {
+        debug!("Waiting for Electrum to reach height {} ...", height);
+        let client = connect()?;
+        wait_until(&format!("Electrum to reach height {height}"), || {
+            let tip = client
+                .block_headers_subscribe()
+                .map_to_permanent_failure("Failed to get the tip from Electrum")?;
+            Ok(tip.height == height as usize)
+        })
+    }
+}
+
+fn connect() -> Result<Client> {
+    Client::new(ELECTRUM_URL).map_to_permanent_failure("Failed to connect to Electrum")
+}
+
+fn wait_until<F: FnMut() -> Result<bool>>(what: &str, mut condition: F) -> Result<()> {
+    for _ in 0..MAX_WAITING_SECS {
+        if condition()? {
+            return Ok(());
+        }
+        sleep(Duration::from_secs(1));
+    }
+    Err(permanent_failure(format!(
+        "Timed out waiting for {what} after {MAX_WAITING_SECS} seconds"
+    )))
+}
+
+fn parse_txid(txid: &str) -> Result<Txid> {
+    Txid::from_str(txid.trim()).map_to_permanent_failure("Invalid txid")
+}
+
+// Returns the stdout of the command.
+fn exec(params: &[&str]) -> Result<String> {
+    let (command, args) = params
+        .split_first()
+        .ok_or_else(|| permanent_failure("At least one param is needed"))?;
+    let output = Command::new(command)
+        .args(args)
+        .output()
+        .map_err(|e| permanent_failure(format!("Failed to run `{}`: {e}", params.join(" "))))?;
+    if !output.status.success() {
+        return Err(permanent_failure(produce_cmd_err_msg(params, &output)));
+    }
+    String::from_utf8(output.stdout).map_to_permanent_failure("Invalid command output")
+}
+
+fn produce_cmd_err_msg(cmd: &[&str], output: &Output) -> String {
+    format!(
+        "Command `{}` failed.\nStderr: {}Stdout: {}",
+        cmd.join(" "),
+        String::from_utf8_lossy(&output.stderr),
+        String::from_utf8_lossy(&output.stdout),
+    )
+}
@@ -1,44 +1,286 @@
-use crate::address::{parse_address, AddressParsingError};
-use crate::errors::Result;
+use crate::address::{
+    build_bip21_uri, parse_address, parse_payment_uri, AddressParsingError, PaymentUri,
+};
+use crate::amount::Amount;
+use crate::audit::{to_json, AuditEvent, AuditEventKind, AuditLog};
+use crate::auth::Auth;
+use crate::blockchain_errors::{map_blockchain_error, map_broadcast_error};
+use crate::descriptor::{get_change_descriptor, get_descriptor_id, get_receive_descriptor};
+use crate::errors::{FundsShortfall, Result, SpendingPolicyViolation};
+use crate::events::{notify_changes, TxSnapshot, WalletEventListener, WalletSnapshot};
+use crate::export::{format_tx_history, Direction, ExportFormat, TxHistoryEntry};
+use crate::failover::{Connect, FailoverBlockchain};
+use crate::fiat::{ExchangeRateProvider, FiatValuation, FiatValue};
+use crate::labels::{parse_bip329, to_bip329, validate_reference, Label, LabelStore};
+use crate::policy::{
+    enforce_spending_policy, ensure_may_sign_proposal, find_policy_violation, Spend, SpendingPolicy,
+};
+use crate::proposals::{TxProposal, TxProposalStore};
+use crate::relay::InterruptibleRelay;
+use crate::snapshot::{apply_snapshot, forget_recent_confirmations, take_snapshot};
 use crate::WalletRuntimeErrorCode;
 
 use bdk::bitcoin::blockdata::script::Script;
 use bdk::bitcoin::blockdata::transaction::TxOut;
 use bdk::bitcoin::consensus::{deserialize, serialize};
-use bdk::bitcoin::psbt::Psbt;
-use bdk::bitcoin::{Address, Network, OutPoint, Txid};
-use bdk::blockchain::{Blockchain, ElectrumBlockchain};
-use bdk::database::{Database, MemoryDatabase};
-use bdk::electrum_client::Client;
-use bdk::sled::Tree;
+use bdk::bitcoin::hashes::hex::FromHex;
+use bdk::bitcoin::psbt::{Input as PsbtInput, Psbt};
+use bdk::bitcoin::{Address, Network, OutPoint, PrivateKey, Transaction, Txid, Witness};
+use bdk::blockchain::electrum::ElectrumBlockchainConfig;
+use bdk::blockchain::esplora::{EsploraBlockchain, EsploraBlockchainConfig};
+use bdk::blockchain::{
+    AnyBlockchain, Blockchain, ConfigurableBlockchain, ElectrumBlockchain, GetHeight,
+};
+use bdk::database::any::SqliteDbConfiguration;
+use bdk::database::{
+    AnyDatabase, BatchDatabase, ConfigurableDatabase, Database, MemoryDatabase, SqliteDatabase,
+};
+use bdk::electrum_client::ElectrumApi;
 use bdk::wallet::AddressIndex;
-use bdk::{Balance, Error, SignOptions, SyncOptions, TransactionDetails};
+use bdk::{Error, FeeRate, KeychainKind, LocalUtxo, SignOptions, SyncOptions, TransactionDetails};
+use log::warn;
 use perro::{invalid_input, permanent_failure, runtime_error, MapToError};
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Mutex;
-use std::time::{Duration, SystemTime};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// Same stop gap that BDK uses by default for the Electrum backend.
+const DEFAULT_STOP_GAP: u32 = 20;
+// A larger stop gap makes every sync slower, as that many unused addresses are queried after the
+// last used one of each keychain.
+pub(crate) const MAX_STOP_GAP: u32 = 1_000;
+// Same confirmation targets that were accepted before they could be configured.
+const DEFAULT_MIN_CONFIRM_IN_BLOCKS: u32 = 1;
+const DEFAULT_MAX_CONFIRM_IN_BLOCKS: u32 = 25;
+// Bitcoin Core, which Electrum servers forward fee estimations to, doesn't estimate fees for
+// higher confirmation targets.
+pub(crate) const MAX_CONFIRM_IN_BLOCKS: u32 = 1_008;
+// The dust limit of P2PKH outputs at the default dust relay fee of Bitcoin Core (3 sat/vbyte).
+// It's the highest dust limit of all standard output types, so amounts above it can be sent to any
+// address.
+const DUST_LIMIT_SAT: u64 = 546;
+// Consolidating pays off for wallets with many UTXOs while fees are low. The confirmation target
+// of the fee estimation is the lowest priority one.
+const CONSOLIDATION_MIN_UTXOS: usize = 20;
+const CONSOLIDATION_MAX_FEE_RATE_SAT_PER_VBYTE: f32 = 5.0;
+const CONSOLIDATION_CONFIRM_IN_BLOCKS: u32 = 25;
+// Larger OP_RETURN outputs aren't relayed by Bitcoin Core's default policy.
+const MAX_MEMO_LEN: usize = 80;
+// Same number of retries that the electrum client uses by default.
+const DEFAULT_ELECTRUM_RETRIES: u8 = 1;
+// Bitcoin Core's default minimum relay fee rate.
+const MIN_FEE_RATE_SAT_PER_VBYTE: f32 = 1.0;
+// Far above any fee rate seen so far, to catch typos in caller-supplied fee rates.
+const MAX_FEE_RATE_SAT_PER_VBYTE: f32 = 1_000.0;
+// A block has a weight limit of 4M weight units, i.e. 1M vbytes.
+const MAX_BLOCK_VSIZE: u64 = 1_000_000;
+// Confirmations in the last blocks are fetched again on every sync, so that txs of blocks that got
+// reorged out don't stay confirmed. Deeper reorgs aren't expected in practice.
+const MAX_REORG_DEPTH: u32 = 6;
+// Every poll syncs the whole wallet, so polling more often would mostly add load on the backend.
+const INCOMING_TX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+// How quickly a sync notices that it got aborted while scanning.
+const SYNC_ABORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// The fee rate of an unconfirmed tx is compared to the estimation for this confirmation target. A
+// tx paying less may stay unconfirmed long enough to be replaced.
+const ZERO_CONF_CONFIRM_IN_BLOCKS: usize = 3;
+const WALLET_DATABASE: &str = "bdk-wallet-database";
+// Previous versions kept two copies of the wallet database and swapped them after every sync.
+const LEGACY_WALLET_DATABASES: [&str; 2] = ["bdk-wallet-database-1", "bdk-wallet-database-2"];
+
+#[derive(Clone)]
+pub enum BlockchainBackend {
+    Electrum { url: String },
+    ElectrumPool { urls: Vec<String> },
+    Esplora { url: String },
+}
+
+#[derive(Clone, Copy)]
+pub enum DatabaseBackend {
+    Sled,
+    Sqlite,
+}
 
 pub struct Config {
-    pub electrum_url: String,
+    pub blockchain_backend: BlockchainBackend,
     pub wallet_db_path: String,
     pub network: Network,
     pub watch_descriptor: String,
+    pub stop_gap: Option<u32>,
+    pub database: DatabaseBackend,
+    pub socks5_proxy: Option<String>,
+    pub timeout_secs: Option<u8>,
+    pub electrum_retries: Option<u8>,
+    pub broadcast_fallback_url: Option<String>,
+    pub min_confirm_in_blocks: Option<u32>,
+    pub max_confirm_in_blocks: Option<u32>,
+}
+
+// The scan of a sync uses a connection of its own, so that it can be closed if the sync gets
+// aborted.
+struct ScanBackend {
+    backend: BlockchainBackend,
+    stop_gap: Option<u32>,
+    socks5_proxy: Option<String>,
+    timeout_secs: Option<u8>,
+    electrum_retries: Option<u8>,
 }
 
-type BdkWallet = bdk::Wallet<Tree>;
+type BdkWallet = bdk::Wallet<AnyDatabase>;
+type BdkMemoryWallet = bdk::Wallet<MemoryDatabase>;
 
+// Syncs run on an in-memory snapshot of the wallet database, so that the wallet can be read while
+// syncing. The synced snapshot is written back to the database once the sync succeeded.
 pub struct Wallet {
-    blockchain: ElectrumBlockchain,
+    blockchain: Arc<FailoverBlockchain<AnyBlockchain>>,
+    scan_backend: ScanBackend,
     wallet: Mutex<BdkWallet>,
-    wallet_to_sync: Mutex<BdkWallet>,
+    // Held during syncs and resets of the database, so that they don't overlap.
+    sync_lock: Mutex<()>,
+    descriptor: String,
+    change_descriptor: String,
+    background_sync_stop: Mutex<Option<Sender<()>>>,
+    // Every sync is numbered when it's requested. An abort applies to all syncs requested until
+    // then, including the ones still waiting for the sync lock.
+    sync_requests: AtomicU64,
+    aborted_sync_requests: AtomicU64,
+    // Whether a sync succeeded since the wallet was created or its database was reset. Until then,
+    // the data in the local database is the one of the last sync of a previous instance, if any.
+    synced: AtomicBool,
+    audit_log: AuditLog,
+    labels: LabelStore,
+    tx_proposals: TxProposalStore,
+    fiat_valuation: Mutex<Option<Arc<FiatValuation>>>,
+    // The session whose auth level decides whether tx proposals may be signed.
+    auth: Mutex<Option<Arc<Auth>>>,
+    spending_policy: Mutex<SpendingPolicy>,
+    // How far back send txs are checked for identical payments. None disables the check.
+    duplicate_payment_window: Mutex<Option<Duration>>,
+    db_path: PathBuf,
+    wallet_id: String,
+    broadcast_fallback: Option<EsploraBlockchain>,
+    confirm_in_blocks_range: RangeInclusive<u32>,
+    // Descriptors with the external keys of prepared sweep txs, by the id of the unsigned tx. They
+    // are only kept in memory until the tx is signed.
+    sweep_descriptors: Mutex<HashMap<Txid, Vec<SweepInputs>>>,
+    // Fees of signed sweep txs, by the id of the signed tx. BDK can't compute them, as the inputs
+    // of a sweep tx don't belong to the wallet.
+    sweep_fees: Mutex<HashMap<Txid, u64>>,
+}
+
+pub trait SyncCallback: Send + Sync {
+    fn on_sync_finished(&self);
+
+    fn on_sync_failed(&self, msg: String);
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyncPhase {
+    Connecting,
+    FetchingHeaders,
+    ScanningScriptPubkeys { index: u32, total: u32 },
+}
+
+pub trait SyncProgressCallback: Send + Sync {
+    fn on_progress(&self, phase: SyncPhase);
+}
+
+pub struct Recipient {
+    pub address: String,
+    pub amount_sat: u64,
+}
+
+pub struct PaymentRequest {
+    pub address: String,
+    pub bip21_uri: String,
+}
+
+pub struct IncomingTx {
+    pub id: String,
+    pub amount: Amount,
+    pub fee_rate_sat_per_vbyte: Option<f32>,
+    pub signals_rbf: bool,
+    pub status: TxStatus,
+}
+
+pub struct ZeroConfRisk {
+    pub id: String,
+    pub amount: Amount,
+    pub status: TxStatus,
+    pub signals_rbf: bool,
+    pub fee_rate_sat_per_vbyte: Option<f32>,
+    pub estimated_fee_rate_sat_per_vbyte: Option<f32>,
+    pub has_unconfirmed_parents: Option<bool>,
 }
 
 pub struct Tx {
     pub id: String,
     pub blob: Vec<u8>,
-    pub on_chain_fee_sat: u64,
-    pub output_sat: u64,
+    pub on_chain_fee: Amount,
+    pub output: Amount,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Balance {
+    pub confirmed: Amount,
+    pub trusted_pending: Amount,
+    pub untrusted_pending: Amount,
+    pub immature: Amount,
+}
+
+impl From<bdk::Balance> for Balance {
+    fn from(balance: bdk::Balance) -> Self {
+        Self {
+            confirmed: Amount::from_sat(balance.confirmed),
+            trusted_pending: Amount::from_sat(balance.trusted_pending),
+            untrusted_pending: Amount::from_sat(balance.untrusted_pending),
+            immature: Amount::from_sat(balance.immature),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetailedBalance {
+    pub external: Amount,
+    pub internal: Amount,
+    pub confirmed: Amount,
+    pub unconfirmed_incoming: Amount,
+    pub unconfirmed_self_transfers: Amount,
+}
+
+pub struct FeeEstimate {
+    pub fee_sat: u64,
+    pub fee_rate_sat_per_vbyte: f32,
+    pub tx_vsize: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeHistogramEntry {
+    pub fee_rate_sat_per_vbyte: f32,
+    pub vsize: u64,
+    pub cumulative_vsize: u64,
+    pub estimated_blocks: u32,
+}
+
+pub enum BroadcastChannel {
+    BlockchainBackend,
+    HttpFallback,
+}
+
+pub struct BroadcastResult {
+    pub tx_details: TxDetails,
+    pub channel: BroadcastChannel,
+}
+
+pub struct SignedTx {
+    pub id: String,
+    pub blob: Vec<u8>,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -47,36 +289,363 @@ pub enum TxStatus {
     InMempool,
     Confirmed {
         number_of_blocks: u32,
+        block_height: u32,
         confirmed_at: SystemTime,
     },
 }
 
+pub struct DecodedTxInput {
+    pub outpoint: String,
+    pub value_sat: u64,
+}
+
+pub struct DecodedTxOutput {
+    pub address: Option<String>,
+    pub value_sat: u64,
+    pub is_mine: bool,
+}
+
+pub struct DecodedTx {
+    pub id: String,
+    pub inputs: Vec<DecodedTxInput>,
+    pub outputs: Vec<DecodedTxOutput>,
+    pub on_chain_fee_sat: u64,
+    pub fee_rate_sat_per_vbyte: f32,
+    pub is_rbf: bool,
+}
+
+pub struct UtxoDetails {
+    pub outpoint: String,
+    pub value_sat: u64,
+    pub status: TxStatus,
+    pub derivation_index: u32,
+    pub is_change: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Keychain {
+    External,
+    Internal,
+}
+
+impl From<KeychainKind> for Keychain {
+    fn from(keychain: KeychainKind) -> Self {
+        match keychain {
+            KeychainKind::External => Keychain::External,
+            KeychainKind::Internal => Keychain::Internal,
+        }
+    }
+}
+
+impl From<Keychain> for KeychainKind {
+    fn from(keychain: Keychain) -> Self {
+        match keychain {
+            Keychain::External => KeychainKind::External,
+            Keychain::Internal => KeychainKind::Internal,
+        }
+    }
+}
+
+// The inputs of a sweep tx that are signed with one of the descriptors of the swept key.
+#[derive(Clone)]
+struct SweepInputs {
+    descriptor: String,
+    outpoints: Vec<OutPoint>,
+}
+
+// Determines the fee rate of a prepared tx.
+enum FeeTarget {
+    ConfirmInBlocks(u32),
+    // Doesn't require the blockchain backend, so txs can be prepared offline.
+    SatPerVbyte(f32),
+}
+
+impl FeeTarget {
+    fn validate(&self, confirm_in_blocks_range: &RangeInclusive<u32>) -> Result<()> {
+        match self {
+            FeeTarget::ConfirmInBlocks(confirm_in_blocks) => {
+                validate_confirm_in_blocks(*confirm_in_blocks, confirm_in_blocks_range)?;
+            }
+            FeeTarget::SatPerVbyte(fee_rate) => {
+                if !(MIN_FEE_RATE_SAT_PER_VBYTE..=MAX_FEE_RATE_SAT_PER_VBYTE).contains(fee_rate) {
+                    return Err(invalid_input(format!(
+                        "Invalid fee rate. Please use a fee rate in the range [{MIN_FEE_RATE_SAT_PER_VBYTE}; {MAX_FEE_RATE_SAT_PER_VBYTE}] sat/vbyte"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct CachedBalance {
+    pub balance: Balance,
+    pub last_synced_at: Option<SystemTime>,
+    pub is_synced: bool,
+}
+
+pub struct AddressInfo {
+    pub address: String,
+    pub keychain: Keychain,
+    pub derivation_index: u32,
+    pub times_used: u32,
+    pub received_sat: u64,
+}
+
+pub struct WalletDiagnostics {
+    pub backend_reachable: bool,
+    pub backend_latency_ms: Option<u64>,
+    pub backend_tip_height: Option<u32>,
+    pub synced_tip_height: u32,
+    pub last_synced_at: Option<SystemTime>,
+    pub db_size_bytes: u64,
+    pub number_of_utxos: u32,
+    pub number_of_unconfirmed_txs: u32,
+}
+
 pub struct TxDetails {
     pub id: String,
     pub output_address: String,
-    pub output_sat: u64,
-    pub on_chain_fee_sat: u64,
+    pub outputs: Vec<DecodedTxOutput>,
+    pub output: Amount,
+    pub on_chain_fee: Amount,
     pub status: TxStatus,
+    pub fiat_value: Option<FiatValue>,
 }
 
 impl Wallet {
     pub fn new(config: Config) -> Result<Self> {
-        let client = Client::new(&config.electrum_url).map_to_runtime_error(
-            WalletRuntimeErrorCode::RemoteServiceUnavailable,
-            "Failed to create an electrum client",
+        Self::ensure_valid_config(&config)?;
+        let blockchain = Self::create_blockchain(
+            &config.blockchain_backend,
+            config.stop_gap,
+            config.socks5_proxy.clone(),
+            config.timeout_secs,
+            config.electrum_retries,
+        )?;
+        Self::open(config, Arc::new(blockchain))
+    }
+
+    // The provided connection is used instead of one to the blockchain backend of the config. Only
+    // the scans of syncs connect to the backend of the config, see `scan_until_aborted()`.
+    pub(crate) fn with_blockchain(
+        config: Config,
+        blockchain: Arc<FailoverBlockchain<AnyBlockchain>>,
+    ) -> Result<Self> {
+        Self::ensure_valid_config(&config)?;
+        Self::open(config, blockchain)
+    }
+
+    fn ensure_valid_config(config: &Config) -> Result<()> {
+        let problems = config.validate();
+        if !problems.is_empty() {
+            let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+            return Err(invalid_input(format!(
+                "Invalid config: {}",
+                problems.join("; ")
+            )));
+        }
+        Ok(())
+    }
+
+    fn open(config: Config, blockchain: Arc<FailoverBlockchain<AnyBlockchain>>) -> Result<Self> {
+        let (database, audit_log, labels, tx_proposals) = Self::open_databases(&config)?;
+        let descriptor = get_receive_descriptor(&config.watch_descriptor)?;
+        let change_descriptor = get_change_descriptor(&config.watch_descriptor)?;
+        let wallet = bdk::Wallet::new(
+            &descriptor,
+            Some(&change_descriptor),
+            config.network,
+            database,
+        )
+        .map_to_permanent_failure("Failed to create wallet")?;
+        let wallet_id = get_descriptor_id(&config.watch_descriptor)?;
+        let confirm_in_blocks_range = get_confirm_in_blocks_range(
+            config.min_confirm_in_blocks,
+            config.max_confirm_in_blocks,
         )?;
-        let blockchain = ElectrumBlockchain::from(client);
+        let broadcast_fallback = match &config.broadcast_fallback_url {
+            Some(url) => Some(Self::create_broadcast_fallback(
+                url,
+                config.socks5_proxy.clone(),
+                config.timeout_secs,
+            )?),
+            None => None,
+        };
 
-        let (wallet, wallet_to_sync) = Self::load_wallets(&config)?;
+        let scan_backend = ScanBackend {
+            backend: config.blockchain_backend.clone(),
+            stop_gap: config.stop_gap,
+            socks5_proxy: config.socks5_proxy.clone(),
+            timeout_secs: config.timeout_secs,
+            electrum_retries: config.electrum_retries,
+        };
 
         Ok(Self {
             blockchain,
+            scan_backend,
             wallet: Mutex::new(wallet),
-            wallet_to_sync: Mutex::new(wallet_to_sync),
+            sync_lock: Mutex::new(()),
+            descriptor,
+            change_descriptor,
+            background_sync_stop: Mutex::new(None),
+            sync_requests: AtomicU64::new(0),
+            aborted_sync_requests: AtomicU64::new(0),
+            synced: AtomicBool::new(false),
+            audit_log,
+            labels,
+            tx_proposals,
+            fiat_valuation: Mutex::new(None),
+            auth: Mutex::new(None),
+            spending_policy: Mutex::new(SpendingPolicy::default()),
+            duplicate_payment_window: Mutex::new(None),
+            db_path: PathBuf::from(&config.wallet_db_path),
+            wallet_id,
+            broadcast_fallback,
+            confirm_in_blocks_range,
+            sweep_descriptors: Mutex::new(HashMap::new()),
+            sweep_fees: Mutex::new(HashMap::new()),
         })
     }
 
+    pub(crate) fn create_blockchain(
+        backend: &BlockchainBackend,
+        stop_gap: Option<u32>,
+        socks5_proxy: Option<String>,
+        timeout: Option<u8>,
+        electrum_retries: Option<u8>,
+    ) -> Result<FailoverBlockchain<AnyBlockchain>> {
+        let stop_gap = stop_gap.unwrap_or(DEFAULT_STOP_GAP);
+        if !(1..=MAX_STOP_GAP).contains(&stop_gap) {
+            return Err(invalid_input(format!(
+                "Invalid stop gap. Please use a stop gap in the range [1; {MAX_STOP_GAP}]"
+            )));
+        }
+        let stop_gap = stop_gap as usize;
+        if timeout == Some(0) {
+            return Err(invalid_input(
+                "Invalid timeout. Please use a timeout of at least 1 second",
+            ));
+        }
+        let retry = electrum_retries.unwrap_or(DEFAULT_ELECTRUM_RETRIES);
+        if matches!(
+            backend,
+            BlockchainBackend::Electrum { .. } | BlockchainBackend::ElectrumPool { .. }
+        ) && socks5_proxy.is_some()
+            && timeout.is_some()
+        {
+            // The electrum client doesn't support timeouts on connections through a SOCKS5 proxy.
+            return Err(invalid_input(
+                "A timeout can't be combined with a SOCKS5 proxy for an electrum backend",
+            ));
+        }
+        let (urls, connect): (Vec<String>, Connect<AnyBlockchain>) = match backend {
+            BlockchainBackend::Electrum { url } => (
+                vec![url.clone()],
+                Box::new(move |url: &str| {
+                    Self::connect_electrum(url, stop_gap, socks5_proxy.clone(), timeout, retry)
+                }),
+            ),
+            BlockchainBackend::ElectrumPool { urls } => {
+                if urls.is_empty() {
+                    return Err(invalid_input("At least one electrum url is required"));
+                }
+                (
+                    urls.clone(),
+                    Box::new(move |url: &str| {
+                        Self::connect_electrum(url, stop_gap, socks5_proxy.clone(), timeout, retry)
+                    }),
+                )
+            }
+            BlockchainBackend::Esplora { url } => (
+                vec![url.clone()],
+                Box::new(move |url: &str| {
+                    let config = EsploraBlockchainConfig {
+                        base_url: url.to_string(),
+                        proxy: socks5_proxy
+                            .as_ref()
+                            .map(|proxy| format!("socks5://{proxy}")),
+                        concurrency: None,
+                        stop_gap,
+                        timeout: timeout.map(u64::from),
+                    };
+                    Ok(EsploraBlockchain::from_config(&config)?.into())
+                }),
+            ),
+        };
+        Ok(FailoverBlockchain::new(urls, connect))
+    }
+
+    // The fallback is only used to broadcast txs. Esplora's HTTP API is implemented by
+    // mempool.space as well, so their instances can be used too.
+    fn create_broadcast_fallback(
+        url: &str,
+        socks5_proxy: Option<String>,
+        timeout: Option<u8>,
+    ) -> Result<EsploraBlockchain> {
+        let config = EsploraBlockchainConfig {
+            base_url: url.to_string(),
+            proxy: socks5_proxy.map(|proxy| format!("socks5://{proxy}")),
+            concurrency: None,
+            stop_gap: DEFAULT_STOP_GAP as usize,
+            timeout: timeout.map(u64::from),
+        };
+        EsploraBlockchain::from_config(&config)
+            .map_to_invalid_input("Invalid broadcast fallback configuration")
+    }
+
+    fn connect_electrum(
+        url: &str,
+        stop_gap: usize,
+        socks5_proxy: Option<String>,
+        timeout: Option<u8>,
+        retry: u8,
+    ) -> std::result::Result<AnyBlockchain, Error> {
+        // BDK passes these settings on to the `ConfigBuilder` of the electrum client. The timeout
+        // applies to connecting as well as to every read and write on the connection, so a slow
+        // server fails the operation instead of blocking it indefinitely.
+        let config = ElectrumBlockchainConfig {
+            url: url.to_string(),
+            socks5: socks5_proxy,
+            retry,
+            timeout,
+            stop_gap,
+            validate_domain: true,
+        };
+        Ok(ElectrumBlockchain::from_config(&config)?.into())
+    }
+
+    // Identical for all wallets of the same descriptor, independent of the notation it's given in.
+    pub fn get_wallet_id(&self) -> String {
+        self.wallet_id.clone()
+    }
+
     pub fn get_balance(&self) -> Result<Balance> {
+        Ok(Balance::from(self.get_bdk_balance()?))
+    }
+
+    // Only reads the local database, so it works offline and before the first sync. The time of the
+    // last sync lets the app tell how outdated the balance may be.
+    pub fn get_cached_balance(&self) -> Result<CachedBalance> {
+        Ok(CachedBalance {
+            balance: self.get_balance()?,
+            last_synced_at: self.get_last_synced_at()?,
+            is_synced: self.synced.load(Ordering::SeqCst),
+        })
+    }
+
+    pub fn get_detailed_balance(&self) -> Result<DetailedBalance> {
+        let wallet = self.wallet.lock().unwrap();
+        let utxos = wallet
+            .list_unspent()
+            .map_to_permanent_failure("Failed to list UTXOs")?;
+        let txs = wallet
+            .list_transactions(false)
+            .map_to_permanent_failure("Failed to list transactions")?;
+        Ok(to_detailed_balance(&utxos, &txs))
+    }
+
+    pub(crate) fn get_bdk_balance(&self) -> Result<bdk::Balance> {
         let wallet = self.wallet.lock().unwrap();
 
         let balance = wallet
@@ -86,6 +655,57 @@ impl Wallet {
         Ok(balance)
     }
 
+    pub fn set_exchange_rate_provider(
+        &self,
+        provider: Box<dyn ExchangeRateProvider>,
+        currency_code: String,
+    ) {
+        *self.fiat_valuation.lock().unwrap() =
+            Some(Arc::new(FiatValuation::new(provider, currency_code)));
+    }
+
+    // Replaces any previously set Auth.
+    pub fn set_auth(&self, auth: Arc<Auth>) {
+        *self.auth.lock().unwrap() = Some(auth);
+    }
+
+    // The policy is kept in memory only, so it needs to be set again after restarting the app.
+    pub fn set_spending_policy(&self, policy: SpendingPolicy) {
+        *self.spending_policy.lock().unwrap() = policy;
+    }
+
+    // Same as the spending policy, the window is kept in memory only.
+    pub fn set_duplicate_payment_window(&self, window: Option<Duration>) {
+        *self.duplicate_payment_window.lock().unwrap() = window;
+    }
+
+    pub fn get_fiat_balance(&self) -> Result<Option<FiatValue>> {
+        let balance = self.get_bdk_balance()?;
+        Ok(self
+            .get_fiat_valuation()
+            .and_then(|valuation| valuation.get_current_value(balance.get_total())))
+    }
+
+    // The provider is called without holding any lock, so that it can't deadlock the wallet.
+    fn add_fiat_values(&self, txs_details: &mut [TxDetails]) {
+        let valuation = match self.get_fiat_valuation() {
+            Some(valuation) => valuation,
+            None => return,
+        };
+        let txs: Vec<(u64, &TxStatus)> = txs_details
+            .iter()
+            .map(|tx| (tx.output.sat, &tx.status))
+            .collect();
+        let fiat_values = valuation.get_tx_values(&txs);
+        for (tx, fiat_value) in txs_details.iter_mut().zip(fiat_values) {
+            tx.fiat_value = fiat_value;
+        }
+    }
+
+    fn get_fiat_valuation(&self) -> Option<Arc<FiatValuation>> {
+        self.fiat_valuation.lock().unwrap().clone()
+    }
+
     pub fn parse_address(
         &self,
         address: String,
@@ -94,6 +714,14 @@ impl Wallet {
         parse_address(address, network).map(|a| a.to_string())
     }
 
+    pub fn parse_payment_uri(
+        &self,
+        uri: String,
+    ) -> std::result::Result<PaymentUri, AddressParsingError> {
+        let network = self.wallet.lock().unwrap().network();
+        parse_payment_uri(uri, network)
+    }
+
     // To know if the local wallet has enough funds to create a drain tx, the most accurate
     // option is to actually try to prepare a drain tx.
     //
@@ -114,27 +742,90 @@ impl Wallet {
                 .address
         };
 
-        match self.prepare_drain_tx_internal(local_address, confirm_in_blocks) {
+        match self
+            .prepare_drain_tx_internal(local_address, FeeTarget::ConfirmInBlocks(confirm_in_blocks))
+        {
             Ok(_) => Ok(true),
             Err(perro::Error::RuntimeError {
-                code: WalletRuntimeErrorCode::NotEnoughFunds,
+                code: WalletRuntimeErrorCode::NotEnoughFunds { .. },
                 ..
             }) => Ok(false),
             Err(e) => Err(e),
         }
     }
 
+    // Returns the amount a drain tx would send (denominated in sats), i.e. the value of all
+    // confirmed UTXOs minus the on-chain fee. Same as for `is_drain_tx_affordable()`, the tx is
+    // prepared spending to a local wallet address, so the actual amount may differ by a few sats
+    // depending on the type of the destination address.
+    pub fn get_max_sendable(&self, confirm_in_blocks: u32) -> Result<u64> {
+        validate_confirm_in_blocks(confirm_in_blocks, &self.confirm_in_blocks_range)?;
+
+        let local_address = {
+            self.wallet
+                .lock()
+                .unwrap()
+                .get_address(AddressIndex::Peek(0))
+                .map_to_permanent_failure("Failed to get address from local wallet")?
+                .address
+        };
+
+        match self
+            .prepare_drain_tx_internal(local_address, FeeTarget::ConfirmInBlocks(confirm_in_blocks))
+        {
+            Ok(tx) => Ok(tx.output.sat),
+            Err(perro::Error::RuntimeError {
+                code: WalletRuntimeErrorCode::NotEnoughFunds { .. },
+                ..
+            }) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_dust_limit(&self) -> u64 {
+        DUST_LIMIT_SAT
+    }
+
     pub fn prepare_drain_tx(&self, address: String, confirm_in_blocks: u32) -> Result<Tx> {
+        let tx = self.prepare_drain_tx_with_fee_target(
+            address,
+            FeeTarget::ConfirmInBlocks(confirm_in_blocks),
+        )?;
+        self.audit_log
+            .record(AuditEventKind::TxPrepared, Some(tx.id.clone()), "Drain tx");
+        Ok(tx)
+    }
+
+    // Only uses the local database, so the tx can be prepared while the blockchain backend is
+    // unreachable and be broadcast later.
+    pub fn prepare_drain_tx_with_fee_rate(
+        &self,
+        address: String,
+        fee_rate_sat_per_vbyte: f32,
+    ) -> Result<Tx> {
+        let tx = self.prepare_drain_tx_with_fee_target(
+            address,
+            FeeTarget::SatPerVbyte(fee_rate_sat_per_vbyte),
+        )?;
+        self.audit_log.record(
+            AuditEventKind::TxPrepared,
+            Some(tx.id.clone()),
+            format!("Drain tx with fee rate {fee_rate_sat_per_vbyte} sat/vbyte"),
+        );
+        Ok(tx)
+    }
+
+    fn prepare_drain_tx_with_fee_target(
+        &self,
+        address: String,
+        fee_target: FeeTarget,
+    ) -> Result<Tx> {
         let wallet = self.wallet.lock().unwrap();
         let network = wallet.network();
         let address =
             parse_address(address, network).map_to_invalid_input("Invalid bitcoin address")?;
 
-        if !(1..=25).contains(&confirm_in_blocks) {
-            return Err(invalid_input(
-                "Invalid block confirmation target. Please use a target in the range [1; 25]",
-            ));
-        }
+        fee_target.validate(&self.confirm_in_blocks_range)?;
 
         let address_is_mine = wallet
             .is_mine(&address.script_pubkey())
@@ -147,17 +838,11 @@ impl Wallet {
         }
         drop(wallet); // To release the lock.
 
-        self.prepare_drain_tx_internal(address, confirm_in_blocks)
+        self.prepare_drain_tx_internal(address, fee_target)
     }
 
-    fn prepare_drain_tx_internal(&self, address: Address, confirm_in_blocks: u32) -> Result<Tx> {
-        let fee_rate = self
-            .blockchain
-            .estimate_fee(confirm_in_blocks as usize)
-            .map_to_runtime_error(
-                WalletRuntimeErrorCode::ElectrumServiceUnavailable,
-                "Failed to estimate fee for drain tx",
-            )?;
+    fn prepare_drain_tx_internal(&self, address: Address, fee_target: FeeTarget) -> Result<Tx> {
+        let fee_rate = self.get_fee_rate(fee_target, "Failed to estimate fee for drain tx")?;
 
         let wallet = self.wallet.lock().unwrap();
 
@@ -175,7 +860,7 @@ impl Wallet {
             .allow_dust(false);
 
         let (psbt, tx_details) = tx_builder.finish().map_to_runtime_error(
-            WalletRuntimeErrorCode::NotEnoughFunds,
+            WalletRuntimeErrorCode::NotEnoughFunds { shortfall: None },
             "Failed to create PSBT",
         )?;
 
@@ -187,78 +872,1015 @@ impl Wallet {
         let tx = Tx {
             id: tx_details.txid.to_string(),
             blob: serialize(&psbt),
-            on_chain_fee_sat: fee,
-            output_sat: tx_details.sent - fee,
+            on_chain_fee: Amount::from_sat(fee),
+            output: Amount::from_sat(tx_details.sent - fee),
         };
 
         Ok(tx)
     }
 
-    pub fn sign_and_broadcast_tx(
-        &self,
-        tx_blob: Vec<u8>,
-        spend_descriptor: String,
-    ) -> Result<TxDetails> {
-        let mut psbt = deserialize::<Psbt>(&tx_blob).map_to_invalid_input("Invalid tx blob")?;
+    // Spends the smallest confirmed UTXOs, as they cost the most to spend relative to their value
+    // when fees are high.
+    pub fn prepare_consolidation_tx(&self, max_utxos: u32, confirm_in_blocks: u32) -> Result<Tx> {
+        if max_utxos < 2 {
+            return Err(invalid_input(
+                "Invalid maximum number of UTXOs. At least 2 UTXOs are needed to consolidate",
+            ));
+        }
+        let fee_target = FeeTarget::ConfirmInBlocks(confirm_in_blocks);
+        fee_target.validate(&self.confirm_in_blocks_range)?;
+        let fee_rate =
+            self.get_fee_rate(fee_target, "Failed to estimate fee for consolidation tx")?;
 
-        let signing_wallet = bdk::Wallet::new(
-            &spend_descriptor,
-            Some(&get_change_descriptor_from_descriptor(&spend_descriptor)?),
-            self.wallet.lock().unwrap().network(),
-            MemoryDatabase::new(),
-        )
-        .map_to_permanent_failure("Failed to create signing-capable wallet")?;
+        let wallet = self.wallet.lock().unwrap();
 
-        let is_finalized = signing_wallet
-            .sign(&mut psbt, SignOptions::default())
-            .map_to_permanent_failure("Failed to sign PSBT")?;
-        if !is_finalized {
-            return Err(permanent_failure("Wallet didn't sign all inputs"));
+        let confirmed_utxo_outpoints = Self::get_confirmed_utxo_outpoints(&wallet)?;
+        let mut utxos: Vec<LocalUtxo> = wallet
+            .list_unspent()
+            .map_to_permanent_failure("Failed to list UTXOs")?
+            .into_iter()
+            .filter(|utxo| confirmed_utxo_outpoints.contains(&utxo.outpoint))
+            .collect();
+        if utxos.len() < 2 {
+            return Err(invalid_input(
+                "At least 2 confirmed UTXOs are needed to consolidate",
+            ));
         }
+        utxos.sort_unstable_by_key(|utxo| utxo.txout.value);
+        let utxo_outpoints: Vec<OutPoint> = utxos
+            .iter()
+            .take(max_utxos as usize)
+            .map(|utxo| utxo.outpoint)
+            .collect();
 
-        let tx = psbt.extract_tx();
-        self.blockchain.broadcast(&tx).map_to_runtime_error(
-            WalletRuntimeErrorCode::ElectrumServiceUnavailable,
-            "Failed to broadcast tx",
-        )?;
-
-        self.sync()?;
-        let wallet = self.wallet.lock().unwrap();
-        let include_raw = true;
-        let tx = wallet
-            .get_tx(&tx.txid(), include_raw)
-            .map_to_permanent_failure("Failed to get tx from the wallet")?
-            .ok_or_else(|| permanent_failure("Just signed tx not found"))?;
-        Self::map_to_tx_details(tx, &wallet)
-    }
+        let address = wallet
+            .get_internal_address(AddressIndex::New)
+            .map_to_permanent_failure("Failed to get change address from local BDK wallet")?
+            .address;
 
-    pub fn get_tx_status(&self, txid: String) -> Result<TxStatus> {
-        let txid = Txid::from_str(&txid).map_to_invalid_input("Invalid tx id")?;
+        let mut tx_builder = wallet.build_tx();
+        tx_builder
+            .add_utxos(&utxo_outpoints)
+            .map_to_permanent_failure("Failed to add utxos to tx builder")?
+            .manually_selected_only()
+            .drain_to(address.script_pubkey())
+            .fee_rate(fee_rate)
+            .enable_rbf();
 
-        let wallet = self.wallet.lock().unwrap();
-        Self::get_tx_status_internal(&wallet, txid)
-    }
+        let (psbt, tx_details) = tx_builder.finish().map_to_runtime_error(
+            WalletRuntimeErrorCode::NotEnoughFunds { shortfall: None },
+            "Failed to create PSBT",
+        )?;
 
-    pub fn get_spending_txs(&self) -> Result<Vec<TxDetails>> {
-        let wallet = self.wallet.lock().unwrap();
+        let fee = match tx_details.fee {
+            None => return Err(permanent_failure("Empty fee using an Electrum backend")),
+            Some(f) => f,
+        };
 
-        let include_raw = true;
-        let txs_details = wallet
-            .list_transactions(include_raw)
-            .map_to_permanent_failure("Wallet failed to list txs")?
-            .into_iter()
-            // If we send more than receive (plus fee) it means that there is at
-            // least one foreign output.
-            .filter(|tx| tx.sent > tx.received + tx.fee.unwrap_or(0))
-            .map(|tx| Self::map_to_tx_details(tx, &wallet));
+        let tx = Tx {
+            id: tx_details.txid.to_string(),
+            blob: serialize(&psbt),
+            on_chain_fee: Amount::from_sat(fee),
+            output: Amount::from_sat(tx_details.received),
+        };
+        drop(wallet); // To release the lock.
+        self.audit_log.record(
+            AuditEventKind::TxPrepared,
+            Some(tx.id.clone()),
+            format!("Consolidation tx spending {} UTXOs", utxo_outpoints.len()),
+        );
 
-        let mut txs_details = try_collect(txs_details)?;
-        txs_details.sort_unstable_by_key(|tx| (tx.status.clone(), tx.id.clone()));
-        Ok(txs_details)
+        Ok(tx)
     }
 
-    pub fn get_addr(&self) -> Result<String> {
-        let wallet = self.wallet.lock().unwrap();
+    // A heuristic: the wallet has many confirmed UTXOs and low priority txs are currently cheap.
+    pub fn should_consolidate(&self) -> Result<bool> {
+        let confirmed_utxos = {
+            let wallet = self.wallet.lock().unwrap();
+            Self::get_confirmed_utxo_outpoints(&wallet)?.len()
+        };
+        if confirmed_utxos < CONSOLIDATION_MIN_UTXOS {
+            return Ok(false);
+        }
+        // Not restricted to the configured confirmation targets, as the target isn't chosen by users.
+        let fee_rate = self.get_fee_rate(
+            FeeTarget::ConfirmInBlocks(CONSOLIDATION_CONFIRM_IN_BLOCKS),
+            "Failed to estimate fee rate",
+        )?;
+        Ok(is_consolidation_worthwhile(
+            confirmed_utxos,
+            fee_rate.as_sat_per_vb(),
+        ))
+    }
+
+    // The key may control funds on several address types, each one is looked up on the blockchain
+    // backend. All of them are swept in a single tx: the wallet of the first funded type builds
+    // the tx and the UTXOs of the other types are added as foreign UTXOs.
+    pub fn prepare_sweep_from_key(
+        &self,
+        private_key: String,
+        confirm_in_blocks: u32,
+    ) -> Result<Tx> {
+        let fee_target = FeeTarget::ConfirmInBlocks(confirm_in_blocks);
+        fee_target.validate(&self.confirm_in_blocks_range)?;
+        let network = self.wallet.lock().unwrap().network();
+        let private_key = parse_private_key(&private_key, network)?;
+        let fee_rate = self.get_fee_rate(fee_target, "Failed to estimate fee for sweep tx")?;
+
+        let mut funded_wallets = Vec::new();
+        for descriptor in get_sweep_descriptors(&private_key) {
+            let wallet = bdk::Wallet::new(&descriptor, None, network, MemoryDatabase::new())
+                .map_to_permanent_failure("Failed to create sweep wallet")?;
+            self.blockchain
+                .call(|blockchain| wallet.sync(blockchain, SyncOptions::default()))
+                .map_err(|e| map_blockchain_error(e, "Failed to look up the funds of the key"))?;
+            let utxos = wallet
+                .list_unspent()
+                .map_to_permanent_failure("Failed to list UTXOs of sweep wallet")?;
+            if !utxos.is_empty() {
+                funded_wallets.push((wallet, descriptor, utxos));
+            }
+        }
+        let (sweep_wallet, _, _) = funded_wallets.first().ok_or_else(|| {
+            runtime_error(
+                WalletRuntimeErrorCode::NotEnoughFunds { shortfall: None },
+                "The key doesn't control any funds",
+            )
+        })?;
+
+        let address = self
+            .wallet
+            .lock()
+            .unwrap()
+            .get_address(AddressIndex::New)
+            .map_to_permanent_failure("Failed to get address from local BDK wallet")?
+            .address;
+
+        let mut tx_builder = sweep_wallet.build_tx();
+        tx_builder
+            .drain_wallet()
+            .drain_to(address.script_pubkey())
+            .fee_rate(fee_rate)
+            .enable_rbf();
+        for (wallet, _, utxos) in &funded_wallets[1..] {
+            let satisfaction_weight = wallet
+                .get_descriptor_for_keychain(KeychainKind::External)
+                .max_satisfaction_weight()
+                .map_to_permanent_failure("Failed to compute max satisfaction weight")?;
+            for utxo in utxos {
+                let only_witness_utxo = false;
+                let psbt_input = wallet
+                    .get_psbt_input(utxo.clone(), None, only_witness_utxo)
+                    .map_to_permanent_failure("Failed to get PSBT input of sweep wallet")?;
+                tx_builder
+                    .add_foreign_utxo(utxo.outpoint, psbt_input, satisfaction_weight)
+                    .map_to_permanent_failure("Failed to add UTXO to sweep tx")?;
+            }
+        }
+        let (psbt, tx_details) = tx_builder.finish().map_to_runtime_error(
+            WalletRuntimeErrorCode::NotEnoughFunds { shortfall: None },
+            "Failed to create PSBT",
+        )?;
+
+        let fee = match tx_details.fee {
+            None => return Err(permanent_failure("Empty fee using an Electrum backend")),
+            Some(f) => f,
+        };
+
+        // The amounts of foreign UTXOs don't count as sent, so the output is taken from the tx.
+        let tx = Tx {
+            id: tx_details.txid.to_string(),
+            blob: serialize(&psbt),
+            on_chain_fee: Amount::from_sat(fee),
+            output: Amount::from_sat(psbt.unsigned_tx.output.iter().map(|o| o.value).sum()),
+        };
+        let sweep_inputs = funded_wallets
+            .into_iter()
+            .map(|(_, descriptor, utxos)| SweepInputs {
+                descriptor,
+                outpoints: utxos.into_iter().map(|utxo| utxo.outpoint).collect(),
+            })
+            .collect();
+        self.sweep_descriptors
+            .lock()
+            .unwrap()
+            .insert(tx_details.txid, sweep_inputs);
+        self.audit_log.record(
+            AuditEventKind::TxPrepared,
+            Some(tx.id.clone()),
+            "Sweep tx from an external key",
+        );
+
+        Ok(tx)
+    }
+
+    pub fn estimate_fee_rate(&self, confirm_in_blocks: u32) -> Result<f32> {
+        validate_confirm_in_blocks(confirm_in_blocks, &self.confirm_in_blocks_range)?;
+
+        let fee_rate = self
+            .blockchain
+            .call(|blockchain| blockchain.estimate_fee(confirm_in_blocks as usize))
+            .map_err(|e| map_blockchain_error(e, "Failed to estimate fee rate"))?;
+
+        Ok(fee_rate.as_sat_per_vb())
+    }
+
+    // Sorted from the highest to the lowest fee rate. Only Electrum servers provide the histogram.
+    pub fn get_fee_histogram(&self) -> Result<Vec<FeeHistogramEntry>> {
+        let histogram = self
+            .blockchain
+            .call(get_fee_histogram)
+            .map_err(|e| map_blockchain_error(e, "Failed to get fee histogram"))?
+            .ok_or_else(|| {
+                invalid_input("The fee histogram is only available with an Electrum backend")
+            })?;
+        parse_fee_histogram(&histogram).ok_or_else(|| {
+            runtime_error(
+                WalletRuntimeErrorCode::GenericError,
+                "Failed to get fee histogram: invalid response",
+            )
+        })
+    }
+
+    fn get_fee_rate(&self, fee_target: FeeTarget, estimation_error_msg: &str) -> Result<FeeRate> {
+        match fee_target {
+            FeeTarget::ConfirmInBlocks(confirm_in_blocks) => self
+                .blockchain
+                .call(|blockchain| blockchain.estimate_fee(confirm_in_blocks as usize))
+                .map_err(|e| map_blockchain_error(e, estimation_error_msg)),
+            FeeTarget::SatPerVbyte(fee_rate) => Ok(FeeRate::from_sat_per_vb(fee_rate)),
+        }
+    }
+
+    // Coin selection determines the number of inputs and whether a change output is needed, so
+    // the most accurate estimation is obtained by preparing the tx and dropping it right away.
+    pub fn estimate_tx_fee(
+        &self,
+        address: String,
+        amount: u64,
+        confirm_in_blocks: u32,
+    ) -> Result<FeeEstimate> {
+        let (tx, _) = self.prepare_send_tx_internal(
+            vec![Recipient {
+                address,
+                amount_sat: amount,
+            }],
+            None,
+            None,
+            FeeTarget::ConfirmInBlocks(confirm_in_blocks),
+        )?;
+        let psbt = deserialize::<Psbt>(&tx.blob)
+            .map_to_permanent_failure("Failed to deserialize prepared tx")?;
+
+        let wallet = self.wallet.lock().unwrap();
+        let tx_vsize = Self::estimate_signed_vsize(&wallet, &psbt.unsigned_tx)?;
+
+        Ok(FeeEstimate {
+            fee_sat: tx.on_chain_fee.sat,
+            fee_rate_sat_per_vbyte: tx.on_chain_fee.sat as f32 / tx_vsize as f32,
+            tx_vsize: tx_vsize as u64,
+        })
+    }
+
+    pub fn prepare_fee_bump_tx(&self, txid: String, new_confirm_in_blocks: u32) -> Result<Tx> {
+        let txid = Txid::from_str(&txid).map_to_invalid_input("Invalid tx id")?;
+
+        validate_confirm_in_blocks(new_confirm_in_blocks, &self.confirm_in_blocks_range)?;
+
+        let fee_rate = self
+            .blockchain
+            .call(|blockchain| blockchain.estimate_fee(new_confirm_in_blocks as usize))
+            .map_err(|e| map_blockchain_error(e, "Failed to estimate fee for fee bump tx"))?;
+
+        let wallet = self.wallet.lock().unwrap();
+
+        let include_raw = true;
+        let original_tx = wallet
+            .get_tx(&txid, include_raw)
+            .map_to_permanent_failure("Failed to get tx from the wallet")?
+            .ok_or_else(|| invalid_input("Tx not found in the wallet"))?;
+        let original_raw_tx = original_tx
+            .transaction
+            .ok_or_else(|| permanent_failure("Tx does not have raw tx"))?;
+
+        let mut tx_builder = wallet.build_fee_bump(txid).map_err(|e| match e {
+            Error::TransactionNotFound => invalid_input("Tx not found in the wallet"),
+            Error::TransactionConfirmed => invalid_input("Tx is already confirmed"),
+            Error::IrreplaceableTransaction => invalid_input("Tx does not signal RBF"),
+            _ => permanent_failure(format!("Failed to create fee bump tx builder: {e}")),
+        })?;
+
+        // A drain tx has no change output to take the additional fee from, so the single
+        // foreign output has to be reduced instead.
+        if let [output] = original_raw_tx.output.as_slice() {
+            let output_is_mine = wallet
+                .is_mine(&output.script_pubkey)
+                .map_to_permanent_failure("Failed to check if output belongs to the wallet")?;
+            if !output_is_mine {
+                tx_builder
+                    .allow_shrinking(output.script_pubkey.clone())
+                    .map_to_permanent_failure("Failed to allow shrinking the drain output")?;
+            }
+        }
+
+        tx_builder.fee_rate(fee_rate).enable_rbf();
+
+        let (psbt, tx_details) = tx_builder.finish().map_err(|e| match e {
+            Error::FeeRateTooLow { .. } | Error::FeeTooLow { .. } => invalid_input(
+                "The estimated fee rate for the new confirmation target is not higher than the one of the original tx",
+            ),
+            Error::InsufficientFunds { .. } => runtime_error(
+                WalletRuntimeErrorCode::NotEnoughFunds { shortfall: None },
+                "Not enough funds to bump the fee of the tx",
+            ),
+            _ => permanent_failure(format!("Failed to create PSBT: {e}")),
+        })?;
+
+        let fee = match tx_details.fee {
+            None => return Err(permanent_failure("Empty fee using an Electrum backend")),
+            Some(f) => f,
+        };
+
+        let tx = Tx {
+            id: tx_details.txid.to_string(),
+            blob: serialize(&psbt),
+            on_chain_fee: Amount::from_sat(fee),
+            output: Amount::from_sat(tx_details.sent - tx_details.received - fee),
+        };
+        self.audit_log.record(
+            AuditEventKind::TxPrepared,
+            Some(tx.id.clone()),
+            format!("Fee bump tx replacing {txid}"),
+        );
+
+        Ok(tx)
+    }
+
+    pub fn decode_tx_blob(&self, tx_blob: Vec<u8>) -> Result<DecodedTx> {
+        let psbt = deserialize::<Psbt>(&tx_blob).map_to_invalid_input("Invalid tx blob")?;
+        let unsigned_tx = &psbt.unsigned_tx;
+
+        if psbt.inputs.len() != unsigned_tx.input.len() {
+            return Err(invalid_input(
+                "Invalid tx blob: inconsistent number of inputs",
+            ));
+        }
+
+        let inputs = try_collect(unsigned_tx.input.iter().zip(psbt.inputs.iter()).map(
+            |(input, psbt_input)| {
+                Ok(DecodedTxInput {
+                    outpoint: input.previous_output.to_string(),
+                    value_sat: get_psbt_input_value(psbt_input, &input.previous_output)?,
+                })
+            },
+        ))?;
+
+        let wallet = self.wallet.lock().unwrap();
+
+        let outputs = Self::map_to_decoded_outputs(&unsigned_tx.output, &wallet)?;
+
+        let input_sat = inputs
+            .iter()
+            .try_fold(0u64, |sum, i| checked_add_sat(sum, i.value_sat))?;
+        let output_sat = outputs
+            .iter()
+            .try_fold(0u64, |sum, o| checked_add_sat(sum, o.value_sat))?;
+        let on_chain_fee_sat = input_sat
+            .checked_sub(output_sat)
+            .ok_or_else(|| invalid_input("Invalid tx blob: outputs exceed inputs"))?;
+
+        let estimated_vsize = Self::estimate_signed_vsize(&wallet, unsigned_tx)?;
+
+        Ok(DecodedTx {
+            id: unsigned_tx.txid().to_string(),
+            inputs,
+            outputs,
+            on_chain_fee_sat,
+            fee_rate_sat_per_vbyte: on_chain_fee_sat as f32 / estimated_vsize as f32,
+            is_rbf: unsigned_tx.is_explicitly_rbf(),
+        })
+    }
+
+    pub fn sign_and_broadcast_tx(
+        &self,
+        tx_blob: Vec<u8>,
+        spend_descriptor: String,
+    ) -> Result<TxDetails> {
+        let signed_tx = self.sign_tx(tx_blob, spend_descriptor)?;
+        self.broadcast_tx(signed_tx.blob)
+    }
+
+    // Sweep txs prepared by `prepare_sweep_from_key()` are signed with the external key instead of
+    // the spend descriptor. Txs saved as proposals are only signed for owner sessions.
+    pub fn sign_tx(&self, tx_blob: Vec<u8>, spend_descriptor: String) -> Result<SignedTx> {
+        let mut psbt = deserialize::<Psbt>(&tx_blob).map_to_invalid_input("Invalid tx blob")?;
+
+        self.ensure_may_sign(&psbt)?;
+
+        let unsigned_txid = psbt.unsigned_tx.txid();
+        let sweep_inputs = self
+            .sweep_descriptors
+            .lock()
+            .unwrap()
+            .get(&unsigned_txid)
+            .cloned();
+        let sweep_fee = match &sweep_inputs {
+            Some(sweep_inputs) => {
+                self.sign_sweep_tx(&mut psbt, sweep_inputs)?;
+                self.sweep_descriptors
+                    .lock()
+                    .unwrap()
+                    .remove(&unsigned_txid);
+                Some(get_psbt_fee(&psbt)?)
+            }
+            None => {
+                let is_finalized = self
+                    .create_signing_wallet(&spend_descriptor)?
+                    .sign(&mut psbt, SignOptions::default())
+                    .map_to_permanent_failure("Failed to sign PSBT")?;
+                if !is_finalized {
+                    return Err(permanent_failure("Wallet didn't sign all inputs"));
+                }
+                None
+            }
+        };
+
+        let tx = psbt.extract_tx();
+        if let Some(sweep_fee) = sweep_fee {
+            self.sweep_fees.lock().unwrap().insert(tx.txid(), sweep_fee);
+        }
+        self.audit_log
+            .record(AuditEventKind::TxSigned, Some(tx.txid().to_string()), "");
+        Ok(SignedTx {
+            id: tx.txid().to_string(),
+            blob: serialize(&tx),
+        })
+    }
+
+    // Adds the signatures of the spend descriptor's keys to a tx without finalizing it, so that
+    // the signatures of other cosigners of a multisig wallet can be added later.
+    pub fn sign_psbt(&self, tx_blob: Vec<u8>, spend_descriptor: String) -> Result<Vec<u8>> {
+        let mut psbt = deserialize::<Psbt>(&tx_blob).map_to_invalid_input("Invalid tx blob")?;
+        self.ensure_may_sign(&psbt)?;
+
+        let signing_wallet = self.create_signing_wallet(&spend_descriptor)?;
+
+        let sign_options = SignOptions {
+            try_finalize: false,
+            ..Default::default()
+        };
+        signing_wallet
+            .sign(&mut psbt, sign_options)
+            .map_to_permanent_failure("Failed to sign PSBT")?;
+        self.audit_log.record(
+            AuditEventKind::TxSigned,
+            Some(psbt.unsigned_tx.txid().to_string()),
+            "Partially signed",
+        );
+
+        Ok(serialize(&psbt))
+    }
+
+    pub fn combine_psbts(&self, tx_blobs: Vec<Vec<u8>>) -> Result<Vec<u8>> {
+        let mut psbts = try_collect(
+            tx_blobs
+                .iter()
+                .map(|blob| deserialize::<Psbt>(blob).map_to_invalid_input("Invalid tx blob")),
+        )?
+        .into_iter();
+
+        let mut combined = psbts
+            .next()
+            .ok_or_else(|| invalid_input("No tx blobs provided"))?;
+        for psbt in psbts {
+            combined
+                .combine(psbt)
+                .map_to_invalid_input("Tx blobs don't belong to the same tx")?;
+        }
+
+        Ok(serialize(&combined))
+    }
+
+    pub fn finalize_psbt(&self, tx_blob: Vec<u8>) -> Result<SignedTx> {
+        let mut psbt = deserialize::<Psbt>(&tx_blob).map_to_invalid_input("Invalid tx blob")?;
+
+        let is_finalized = self
+            .wallet
+            .lock()
+            .unwrap()
+            .finalize_psbt(&mut psbt, SignOptions::default())
+            .map_to_invalid_input("Failed to finalize PSBT")?;
+        if !is_finalized {
+            return Err(invalid_input("Tx doesn't have enough signatures yet"));
+        }
+
+        let tx = psbt.extract_tx();
+        self.audit_log.record(
+            AuditEventKind::TxSigned,
+            Some(tx.txid().to_string()),
+            "Finalized",
+        );
+        Ok(SignedTx {
+            id: tx.txid().to_string(),
+            blob: serialize(&tx),
+        })
+    }
+
+    // The signers of a BDK wallet sign every input they are given. So each descriptor of the swept
+    // key signs a copy of the PSBT in which the inputs of the other descriptors are marked as
+    // final, and only its own inputs are taken from that copy.
+    fn sign_sweep_tx(&self, psbt: &mut Psbt, sweep_inputs: &[SweepInputs]) -> Result<()> {
+        let network = self.wallet.lock().unwrap().network();
+        let outpoints: Vec<OutPoint> = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect();
+        for sweep_input in sweep_inputs {
+            let sweep_wallet = bdk::Wallet::new(
+                &sweep_input.descriptor,
+                None,
+                network,
+                MemoryDatabase::new(),
+            )
+            .map_to_permanent_failure("Failed to create sweep wallet")?;
+            let is_own = |outpoint: &OutPoint| sweep_input.outpoints.contains(outpoint);
+
+            let mut own_psbt = psbt.clone();
+            for (input, outpoint) in own_psbt.inputs.iter_mut().zip(&outpoints) {
+                if !is_own(outpoint) {
+                    input.final_script_witness = Some(Witness::new());
+                }
+            }
+            let is_finalized = sweep_wallet
+                .sign(&mut own_psbt, SignOptions::default())
+                .map_to_permanent_failure("Failed to sign PSBT")?;
+            if !is_finalized {
+                return Err(permanent_failure("Wallet didn't sign all inputs"));
+            }
+
+            for (index, outpoint) in outpoints.iter().enumerate() {
+                if is_own(outpoint) {
+                    psbt.inputs[index] = own_psbt.inputs[index].clone();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn create_signing_wallet(&self, spend_descriptor: &str) -> Result<BdkMemoryWallet> {
+        bdk::Wallet::new(
+            &get_receive_descriptor(spend_descriptor)?,
+            Some(&get_change_descriptor(spend_descriptor)?),
+            self.wallet.lock().unwrap().network(),
+            MemoryDatabase::new(),
+        )
+        .map_to_permanent_failure("Failed to create signing-capable wallet")
+    }
+
+    pub fn export_psbt_base64(&self, tx_blob: Vec<u8>) -> Result<String> {
+        let psbt = deserialize::<Psbt>(&tx_blob).map_to_invalid_input("Invalid tx blob")?;
+        Ok(psbt.to_string())
+    }
+
+    // Finalizes a PSBT that has been signed by an external signer (e.g. a hardware wallet).
+    // The returned signed tx blob can be broadcast using `broadcast_tx()`.
+    pub fn import_signed_psbt_base64(&self, psbt: String) -> Result<Vec<u8>> {
+        let mut psbt = Psbt::from_str(psbt.trim()).map_to_invalid_input("Invalid base64 PSBT")?;
+
+        let is_finalized = self
+            .wallet
+            .lock()
+            .unwrap()
+            .finalize_psbt(&mut psbt, SignOptions::default())
+            .map_to_invalid_input("Failed to finalize PSBT")?;
+        if !is_finalized {
+            return Err(invalid_input("PSBT is not fully signed"));
+        }
+
+        let tx = psbt.extract_tx();
+        self.audit_log.record(
+            AuditEventKind::TxSigned,
+            Some(tx.txid().to_string()),
+            "Signed by an external signer",
+        );
+        Ok(serialize(&tx))
+    }
+
+    // Txs spending funds reserved by a proposal may only be signed by owner sessions, even if they
+    // aren't the proposed tx itself. All txs have to comply with the spending policy.
+    fn ensure_may_sign(&self, psbt: &Psbt) -> Result<()> {
+        if self
+            .tx_proposals
+            .spends_reserved_outpoint(&psbt.unsigned_tx)?
+        {
+            let auth = self.auth.lock().unwrap().clone();
+            ensure_may_sign_proposal(auth.as_deref())?;
+        }
+        let violation = {
+            let wallet = self.wallet.lock().unwrap();
+            let tx_details = Self::to_unsynced_tx_details(psbt.unsigned_tx.clone(), &wallet)?;
+            self.find_spending_policy_violation(&wallet, &tx_details)?
+        };
+        self.enforce_spending_policy(violation)
+    }
+
+    pub fn broadcast_tx(&self, signed_tx_blob: Vec<u8>) -> Result<TxDetails> {
+        Ok(self.broadcast_tx_with_channel(signed_tx_blob)?.tx_details)
+    }
+
+    pub fn broadcast_tx_with_channel(&self, signed_tx_blob: Vec<u8>) -> Result<BroadcastResult> {
+        let tx = deserialize::<Transaction>(&signed_tx_blob)
+            .map_to_invalid_input("Invalid signed tx blob")?;
+
+        let channel = self.broadcast(&tx)?;
+        let channel_description = match channel {
+            BroadcastChannel::BlockchainBackend => "",
+            BroadcastChannel::HttpFallback => "Broadcast through the HTTP fallback",
+        };
+        self.audit_log.record(
+            AuditEventKind::TxBroadcast,
+            Some(tx.txid().to_string()),
+            channel_description,
+        );
+        if let Err(e) = self.tx_proposals.delete(&tx.txid().to_string()) {
+            warn!("Failed to delete tx proposal of tx {}: {e}", tx.txid());
+        }
+
+        // The blockchain backend may be the reason the fallback had to be used, so the tx details
+        // are computed from the local database if syncing fails.
+        if let Err(e) = self.sync() {
+            warn!("Failed to sync after broadcasting tx {}: {e}", tx.txid());
+        }
+        let sweep_fee = self.sweep_fees.lock().unwrap().remove(&tx.txid());
+        let mut tx_details = {
+            let wallet = self.wallet.lock().unwrap();
+            let include_raw = true;
+            let tx = match wallet
+                .get_tx(&tx.txid(), include_raw)
+                .map_to_permanent_failure("Failed to get tx from the wallet")?
+            {
+                Some(tx) => tx,
+                None => Self::to_unsynced_tx_details(tx, &wallet)?,
+            };
+            match sweep_fee {
+                Some(fee_sat) => Self::map_to_sweep_tx_details(tx, fee_sat, &wallet)?,
+                None => Self::map_to_tx_details(tx, &wallet)?,
+            }
+        };
+        self.add_fiat_values(std::slice::from_mut(&mut tx_details));
+        Ok(BroadcastResult {
+            tx_details,
+            channel,
+        })
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<BroadcastChannel> {
+        let error = match self.blockchain.call(|blockchain| blockchain.broadcast(tx)) {
+            Ok(()) => return Ok(BroadcastChannel::BlockchainBackend),
+            Err(e) => e,
+        };
+        let fallback = match &self.broadcast_fallback {
+            Some(fallback) => fallback,
+            None => {
+                return Err(map_broadcast_error(
+                    error,
+                    tx.vsize(),
+                    "Failed to broadcast tx",
+                ))
+            }
+        };
+        warn!(
+            "Failed to broadcast tx {}, using the fallback: {error}",
+            tx.txid()
+        );
+        // The fallback doesn't report why a tx was rejected, so the error of the blockchain backend
+        // is returned.
+        match fallback.broadcast(tx) {
+            Ok(()) => Ok(BroadcastChannel::HttpFallback),
+            Err(fallback_error) => Err(map_broadcast_error(
+                error,
+                tx.vsize(),
+                &format!(
+                    "Failed to broadcast tx. Broadcasting through the fallback failed as well ({fallback_error})"
+                ),
+            )),
+        }
+    }
+
+    // Only inputs spending UTXOs of the wallet are known, so the fee is only set if all inputs
+    // belong to the wallet.
+    fn to_unsynced_tx_details(tx: Transaction, wallet: &BdkWallet) -> Result<TransactionDetails> {
+        let mut sent = 0;
+        let mut all_inputs_known = true;
+        for input in &tx.input {
+            match wallet
+                .get_utxo(input.previous_output)
+                .map_to_permanent_failure("Failed to get UTXO from the wallet")?
+            {
+                Some(utxo) => sent = checked_add_sat(sent, utxo.txout.value)?,
+                None => all_inputs_known = false,
+            }
+        }
+        let mut received = 0;
+        for output in &tx.output {
+            if wallet
+                .is_mine(&output.script_pubkey)
+                .map_to_permanent_failure("Failed to check if output belongs to the wallet")?
+            {
+                received = checked_add_sat(received, output.value)?;
+            }
+        }
+        let output_total = tx
+            .output
+            .iter()
+            .try_fold(0u64, |sum, output| checked_add_sat(sum, output.value))?;
+        let fee = if all_inputs_known {
+            sent.checked_sub(output_total)
+        } else {
+            None
+        };
+
+        Ok(TransactionDetails {
+            txid: tx.txid(),
+            transaction: Some(tx),
+            received,
+            sent,
+            fee,
+            confirmation_time: None,
+        })
+    }
+
+    pub fn get_tx_status(&self, txid: String) -> Result<TxStatus> {
+        let txid = Txid::from_str(&txid).map_to_invalid_input("Invalid tx id")?;
+
+        let wallet = self.wallet.lock().unwrap();
+        Self::get_tx_status_internal(&wallet, txid)
+    }
+
+    // The statuses are keyed by the tx ids as provided. All of them are counted up to the same tip,
+    // as the wallet is locked only once.
+    pub fn get_tx_statuses(&self, txids: Vec<String>) -> Result<HashMap<String, TxStatus>> {
+        let txids = txids
+            .into_iter()
+            .map(|id| Txid::from_str(&id).map(|txid| (id, txid)))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_to_invalid_input("Invalid tx id")?;
+
+        let wallet = self.wallet.lock().unwrap();
+        let tip_height = Self::get_synced_tip_height(&wallet)?;
+        let include_raw = false;
+        let mut statuses = HashMap::new();
+        for (id, txid) in txids {
+            let tx = wallet
+                .get_tx(&txid, include_raw)
+                .map_to_permanent_failure("Failed to get tx from the wallet")?;
+            statuses.insert(id, Self::to_tx_status(tx, tip_height));
+        }
+        Ok(statuses)
+    }
+
+    // The tx is taken from the local database, while the fee estimation and the state of the
+    // parents are queried from the blockchain backend. Values the backend can't provide are None.
+    pub fn assess_incoming_tx(&self, txid: String) -> Result<ZeroConfRisk> {
+        let txid = Txid::from_str(&txid).map_to_invalid_input("Invalid tx id")?;
+
+        let (tx, status, received_script_pubkey) = {
+            let wallet = self.wallet.lock().unwrap();
+            let include_raw = true;
+            let tx = wallet
+                .get_tx(&txid, include_raw)
+                .map_to_permanent_failure("Failed to get tx from the wallet")?
+                .ok_or_else(|| invalid_input("Tx not found in the wallet"))?;
+            let raw_tx = tx
+                .transaction
+                .as_ref()
+                .ok_or_else(|| permanent_failure("Tx does not have raw tx"))?;
+            let mut received_script_pubkey = None;
+            for output in &raw_tx.output {
+                if wallet
+                    .is_mine(&output.script_pubkey)
+                    .map_to_permanent_failure("Failed to check if output belongs to the wallet")?
+                {
+                    received_script_pubkey = Some(output.script_pubkey.clone());
+                    break;
+                }
+            }
+            let received_script_pubkey = received_script_pubkey
+                .ok_or_else(|| invalid_input("The tx doesn't pay to the wallet"))?;
+            let tip_height = Self::get_synced_tip_height(&wallet)?;
+            let status = Self::to_tx_status(Some(tx.clone()), tip_height);
+            (tx, status, received_script_pubkey)
+        };
+        let raw_tx = tx
+            .transaction
+            .as_ref()
+            .ok_or_else(|| permanent_failure("Tx does not have raw tx"))?;
+
+        let fee_rate_sat_per_vbyte = tx.fee.map(|fee| fee as f32 / raw_tx.vsize() as f32);
+        let estimated_fee_rate_sat_per_vbyte = match self
+            .blockchain
+            .call(|blockchain| blockchain.estimate_fee(ZERO_CONF_CONFIRM_IN_BLOCKS))
+        {
+            Ok(fee_rate) => Some(fee_rate.as_sat_per_vb()),
+            Err(e) => {
+                warn!("Failed to estimate fee rate: {e}");
+                None
+            }
+        };
+        let has_unconfirmed_parents = match status {
+            TxStatus::Confirmed { .. } => Some(false),
+            _ => match self.blockchain.call(|blockchain| {
+                has_unconfirmed_parents(blockchain, &txid, &received_script_pubkey)
+            }) {
+                Ok(has_unconfirmed_parents) => has_unconfirmed_parents,
+                Err(e) => {
+                    warn!("Failed to get the state of the parents of tx {txid}: {e}");
+                    None
+                }
+            },
+        };
+
+        Ok(ZeroConfRisk {
+            id: txid.to_string(),
+            amount: Amount::from_sat(tx.received),
+            status,
+            signals_rbf: raw_tx.is_explicitly_rbf(),
+            fee_rate_sat_per_vbyte,
+            estimated_fee_rate_sat_per_vbyte,
+            has_unconfirmed_parents,
+        })
+    }
+
+    pub fn get_spending_txs(&self) -> Result<Vec<TxDetails>> {
+        let mut txs_details = {
+            let wallet = self.wallet.lock().unwrap();
+
+            let include_raw = true;
+            let txs_details = wallet
+                .list_transactions(include_raw)
+                .map_to_permanent_failure("Wallet failed to list txs")?
+                .into_iter()
+                // If we send more than receive (plus fee) it means that there is at
+                // least one foreign output.
+                .filter(|tx| tx.sent > tx.received + tx.fee.unwrap_or(0))
+                .map(|tx| Self::map_to_tx_details(tx, &wallet));
+            try_collect(txs_details)?
+        };
+
+        txs_details.sort_unstable_by_key(|tx| (tx.status.clone(), tx.id.clone()));
+        self.add_fiat_values(&mut txs_details);
+        Ok(txs_details)
+    }
+
+    // Returns the txs of the wallet that paid exactly the amount to the address within the provided
+    // time span before now, e.g. to catch invoices that were submitted twice.
+    pub fn find_payments_to(
+        &self,
+        address: String,
+        amount_sat: u64,
+        within: Duration,
+    ) -> Result<Vec<TxDetails>> {
+        let mut txs_details = {
+            let wallet = self.wallet.lock().unwrap();
+            let address = parse_address(address, wallet.network())
+                .map_to_invalid_input("Invalid bitcoin address")?;
+            let payments =
+                Self::find_payments(&wallet, &address.script_pubkey(), amount_sat, within)?;
+            try_collect(
+                payments
+                    .into_iter()
+                    .map(|tx| Self::map_to_tx_details(tx, &wallet)),
+            )?
+        };
+
+        txs_details.sort_unstable_by_key(|tx| (tx.status.clone(), tx.id.clone()));
+        self.add_fiat_values(&mut txs_details);
+        Ok(txs_details)
+    }
+
+    fn find_payments(
+        wallet: &BdkWallet,
+        script_pubkey: &Script,
+        amount_sat: u64,
+        within: Duration,
+    ) -> Result<Vec<TransactionDetails>> {
+        let now = SystemTime::now();
+        let include_raw = true;
+        Ok(wallet
+            .list_transactions(include_raw)
+            .map_to_permanent_failure("Wallet failed to list txs")?
+            .into_iter()
+            .filter(|tx| is_recent_payment_to(tx, script_pubkey, amount_sat, within, now))
+            .collect())
+    }
+
+    pub fn export_tx_history(
+        &self,
+        format: ExportFormat,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> Result<String> {
+        let wallet = self.wallet.lock().unwrap();
+
+        let include_raw = true;
+        let mut entries = Vec::new();
+        for tx in wallet
+            .list_transactions(include_raw)
+            .map_to_permanent_failure("Wallet failed to list txs")?
+        {
+            let confirmed_at = tx.confirmation_time.as_ref().map(|block_time| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(block_time.timestamp)
+            });
+            // Unconfirmed txs are only part of an open-ended time range.
+            let is_in_range = match confirmed_at {
+                Some(confirmed_at) => {
+                    from.map_or(true, |from| confirmed_at >= from)
+                        && to.map_or(true, |to| confirmed_at <= to)
+                }
+                None => to.is_none(),
+            };
+            if is_in_range {
+                entries.push(Self::map_to_tx_history_entry(tx, confirmed_at, &wallet)?);
+            }
+        }
+        // Chronological order with unconfirmed txs last.
+        entries.sort_unstable_by(|a, b| {
+            (a.confirmed_at.is_none(), a.confirmed_at, &a.txid).cmp(&(
+                b.confirmed_at.is_none(),
+                b.confirmed_at,
+                &b.txid,
+            ))
+        });
+
+        Ok(format_tx_history(&entries, format))
+    }
+
+    fn map_to_tx_history_entry(
+        tx: TransactionDetails,
+        confirmed_at: Option<SystemTime>,
+        wallet: &BdkWallet,
+    ) -> Result<TxHistoryEntry> {
+        let raw_tx = tx
+            .transaction
+            .as_ref()
+            .ok_or_else(|| permanent_failure("Tx does not have raw tx"))?;
+
+        let (direction, amount_sat, fee_sat, output) = if tx.sent > tx.received {
+            let fee = tx.fee.unwrap_or(0);
+            let amount_sat = (tx.sent - tx.received).saturating_sub(fee);
+            let output = Self::find_foreign_output(&raw_tx.output, wallet)?;
+            (Direction::Outgoing, amount_sat, tx.fee, output)
+        } else {
+            // The fee of an incoming tx is paid by the sender.
+            let output = Self::find_own_output(&raw_tx.output, wallet)?;
+            (Direction::Incoming, tx.received - tx.sent, None, output)
+        };
+        let address = output
+            .and_then(|script| Address::from_script(&script, wallet.network()).ok())
+            .map(|address| address.to_string());
+
+        Ok(TxHistoryEntry {
+            txid: tx.txid.to_string(),
+            direction,
+            amount_sat,
+            fee_sat,
+            confirmed_at,
+            address,
+        })
+    }
+
+    pub fn list_utxos(&self) -> Result<Vec<UtxoDetails>> {
+        let wallet = self.wallet.lock().unwrap();
+
+        let utxos = wallet
+            .list_unspent()
+            .map_to_permanent_failure("Failed to list UTXOs")?;
+
+        let mut utxos_details = Vec::with_capacity(utxos.len());
+        for utxo in utxos {
+            let (_, derivation_index) = wallet
+                .database()
+                .get_path_from_script_pubkey(&utxo.txout.script_pubkey)
+                .map_to_permanent_failure("Failed to get derivation path of UTXO")?
+                .ok_or_else(|| permanent_failure("UTXO does not belong to the wallet"))?;
+
+            utxos_details.push(UtxoDetails {
+                outpoint: utxo.outpoint.to_string(),
+                value_sat: utxo.txout.value,
+                status: Self::get_tx_status_internal(&wallet, utxo.outpoint.txid)?,
+                derivation_index,
+                is_change: utxo.keychain == KeychainKind::Internal,
+            });
+        }
+
+        utxos_details.sort_unstable_by_key(|utxo| (utxo.status.clone(), utxo.outpoint.clone()));
+        Ok(utxos_details)
+    }
+
+    pub fn get_addr(&self) -> Result<String> {
+        let wallet = self.wallet.lock().unwrap();
 
         let address = wallet
             .get_address(AddressIndex::New)
@@ -268,6 +1890,204 @@ impl Wallet {
         Ok(address.to_string())
     }
 
+    // Only addresses that have already been derived by the local wallet (handed out or looked ahead
+    // during sync) are known to belong to it.
+    pub fn is_address_mine(&self, address: String) -> Result<bool> {
+        let wallet = self.wallet.lock().unwrap();
+        let address = parse_address(address, wallet.network())
+            .map_to_invalid_input("Invalid bitcoin address")?;
+
+        wallet
+            .is_mine(&address.script_pubkey())
+            .map_to_permanent_failure("Failed to check if address belongs to the wallet")
+    }
+
+    pub fn get_address_info(&self, address: String) -> Result<AddressInfo> {
+        let wallet = self.wallet.lock().unwrap();
+        let address = parse_address(address, wallet.network())
+            .map_to_invalid_input("Invalid bitcoin address")?;
+        let script = address.script_pubkey();
+
+        let (keychain, derivation_index) = wallet
+            .database()
+            .get_path_from_script_pubkey(&script)
+            .map_to_permanent_failure("Failed to get derivation path of address")?
+            .ok_or_else(|| invalid_input("The address does not belong to the wallet"))?;
+        let (times_used, received_sat) = Self::get_address_usage(&wallet)?
+            .get(&script)
+            .copied()
+            .unwrap_or_default();
+
+        Ok(AddressInfo {
+            address: address.to_string(),
+            keychain: keychain.into(),
+            derivation_index,
+            times_used,
+            received_sat,
+        })
+    }
+
+    // Returns for every script the wallet has received funds on the number of txs paying to it and
+    // the total amount received (denominated in sats).
+    fn get_address_usage(wallet: &BdkWallet) -> Result<HashMap<Script, (u32, u64)>> {
+        let txs = wallet
+            .list_transactions(true)
+            .map_to_permanent_failure("Failed to list transactions")?;
+
+        let mut usage: HashMap<Script, (u32, u64)> = HashMap::new();
+        for tx in txs.iter().filter_map(|tx| tx.transaction.as_ref()) {
+            let mut tx_received: HashMap<&Script, u64> = HashMap::new();
+            for output in &tx.output {
+                *tx_received.entry(&output.script_pubkey).or_default() += output.value;
+            }
+            for (script, received_sat) in tx_received {
+                let (times_used, total_received_sat) = usage.entry(script.clone()).or_default();
+                *times_used += 1;
+                *total_received_sat += received_sat;
+            }
+        }
+        Ok(usage)
+    }
+
+    // Lists the addresses of the keychain that have been handed out so far, ordered by derivation
+    // index. Addresses only looked ahead during sync are included if they have been used.
+    pub fn list_addresses(
+        &self,
+        keychain: Keychain,
+        include_used: bool,
+    ) -> Result<Vec<AddressInfo>> {
+        let wallet = self.wallet.lock().unwrap();
+        let keychain_kind = KeychainKind::from(keychain);
+        let usage = Self::get_address_usage(&wallet)?;
+        let last_index = wallet
+            .database()
+            .get_last_index(keychain_kind)
+            .map_to_permanent_failure("Failed to get last derivation index")?;
+
+        let scripts = wallet
+            .database()
+            .iter_script_pubkeys(Some(keychain_kind))
+            .map_to_permanent_failure("Failed to list scripts of the wallet")?;
+        let mut addresses = Vec::new();
+        for script in scripts {
+            let (_, derivation_index) = wallet
+                .database()
+                .get_path_from_script_pubkey(&script)
+                .map_to_permanent_failure("Failed to get derivation path of address")?
+                .ok_or_else(|| permanent_failure("Script does not belong to the wallet"))?;
+            let (times_used, received_sat) = usage.get(&script).copied().unwrap_or_default();
+            let is_used = times_used > 0;
+            let is_handed_out = matches!(last_index, Some(last) if derivation_index <= last);
+            if !(is_handed_out || is_used) || (is_used && !include_used) {
+                continue;
+            }
+
+            let address = Address::from_script(&script, wallet.network())
+                .ok_or_else(|| permanent_failure("Failed to get address from script"))?;
+            addresses.push(AddressInfo {
+                address: address.to_string(),
+                keychain,
+                derivation_index,
+                times_used,
+                received_sat,
+            });
+        }
+        addresses.sort_unstable_by_key(|address| address.derivation_index);
+        Ok(addresses)
+    }
+
+    pub fn create_payment_request(
+        &self,
+        amount_sat: Option<u64>,
+        label: Option<String>,
+        message: Option<String>,
+    ) -> Result<PaymentRequest> {
+        if amount_sat == Some(0) {
+            return Err(invalid_input("The requested amount must be positive"));
+        }
+
+        let address = self
+            .wallet
+            .lock()
+            .unwrap()
+            .get_address(AddressIndex::New)
+            .map_to_permanent_failure("Failed to get address from local BDK wallet")?
+            .address;
+
+        Ok(PaymentRequest {
+            address: address.to_string(),
+            bip21_uri: build_bip21_uri(&address, amount_sat, label.as_deref(), message.as_deref()),
+        })
+    }
+
+    // Polls by syncing the wallet. Txs that were already in the local database when the call
+    // started don't count as incoming. A failing sync is retried on the next poll, its error is
+    // only returned if no sync succeeded before the timeout. Returns None if no tx was detected.
+    pub fn watch_for_incoming(
+        &self,
+        address: String,
+        timeout_secs: u32,
+    ) -> Result<Option<IncomingTx>> {
+        let script_pubkey = {
+            let wallet = self.wallet.lock().unwrap();
+            let address = parse_address(address, wallet.network())
+                .map_to_invalid_input("Invalid bitcoin address")?;
+            let address_is_mine = wallet
+                .is_mine(&address.script_pubkey())
+                .map_to_permanent_failure("Failed to check if address belongs to the wallet")?;
+            if !address_is_mine {
+                return Err(invalid_input("The address doesn't belong to the wallet"));
+            }
+            address.script_pubkey()
+        };
+        let known_txids = self.get_txids_paying_to(&script_pubkey)?;
+
+        let deadline = Instant::now() + Duration::from_secs(u64::from(timeout_secs));
+        let mut last_sync_error = None;
+        let mut any_sync_succeeded = false;
+        loop {
+            match self.sync() {
+                Ok(()) => any_sync_succeeded = true,
+                Err(e) => {
+                    warn!("Failed to sync while watching for incoming txs: {e}");
+                    last_sync_error = Some(e);
+                }
+            }
+            let wallet = self.wallet.lock().unwrap();
+            let txs = wallet
+                .list_transactions(true)
+                .map_to_permanent_failure("Failed to list transactions")?;
+            if let Some(tx) = txs.into_iter().find(|tx| {
+                get_amount_paid_to(tx, &script_pubkey) > 0 && !known_txids.contains(&tx.txid)
+            }) {
+                let tip_height = Self::get_synced_tip_height(&wallet)?;
+                return Ok(Some(Self::to_incoming_tx(tx, &script_pubkey, tip_height)));
+            }
+            drop(wallet); // To release the lock while waiting.
+
+            let now = Instant::now();
+            if now >= deadline {
+                return match last_sync_error {
+                    Some(e) if !any_sync_succeeded => Err(e),
+                    _ => Ok(None),
+                };
+            }
+            thread::sleep(INCOMING_TX_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    fn get_txids_paying_to(&self, script_pubkey: &Script) -> Result<HashSet<Txid>> {
+        let wallet = self.wallet.lock().unwrap();
+        let txs = wallet
+            .list_transactions(true)
+            .map_to_permanent_failure("Failed to list transactions")?;
+        Ok(txs
+            .into_iter()
+            .filter(|tx| get_amount_paid_to(tx, script_pubkey) > 0)
+            .map(|tx| tx.txid)
+            .collect())
+    }
+
     // Not stated in the UDL file -> at the moment is just used in tests
     pub fn prepare_send_tx(
         &self,
@@ -275,54 +2095,192 @@ impl Wallet {
         amount: u64,
         confirm_in_blocks: u32,
     ) -> Result<Tx> {
+        self.prepare_batch_send_tx(
+            vec![Recipient {
+                address,
+                amount_sat: amount,
+            }],
+            confirm_in_blocks,
+        )
+    }
+
+    pub fn prepare_batch_send_tx(
+        &self,
+        recipients: Vec<Recipient>,
+        confirm_in_blocks: u32,
+    ) -> Result<Tx> {
+        let tx = self.prepare_payment_tx(
+            recipients,
+            None,
+            None,
+            FeeTarget::ConfirmInBlocks(confirm_in_blocks),
+        )?;
+        self.audit_log
+            .record(AuditEventKind::TxPrepared, Some(tx.id.clone()), "Send tx");
+        Ok(tx)
+    }
+
+    // Only uses the local database, so the tx can be prepared while the blockchain backend is
+    // unreachable and be broadcast later.
+    pub fn prepare_batch_send_tx_with_fee_rate(
+        &self,
+        recipients: Vec<Recipient>,
+        fee_rate_sat_per_vbyte: f32,
+    ) -> Result<Tx> {
+        let tx = self.prepare_payment_tx(
+            recipients,
+            None,
+            None,
+            FeeTarget::SatPerVbyte(fee_rate_sat_per_vbyte),
+        )?;
+        self.audit_log.record(
+            AuditEventKind::TxPrepared,
+            Some(tx.id.clone()),
+            format!("Send tx with fee rate {fee_rate_sat_per_vbyte} sat/vbyte"),
+        );
+        Ok(tx)
+    }
+
+    // The memo is attached as an OP_RETURN output, e.g. the hash of an order reference.
+    pub fn prepare_send_tx_with_memo(
+        &self,
+        address: String,
+        amount: u64,
+        memo: Vec<u8>,
+        confirm_in_blocks: u32,
+    ) -> Result<Tx> {
+        if memo.is_empty() || memo.len() > MAX_MEMO_LEN {
+            return Err(invalid_input(format!(
+                "Invalid memo. Please use a memo of 1 to {MAX_MEMO_LEN} bytes"
+            )));
+        }
+
+        let tx = self.prepare_payment_tx(
+            vec![Recipient {
+                address,
+                amount_sat: amount,
+            }],
+            None,
+            Some(memo),
+            FeeTarget::ConfirmInBlocks(confirm_in_blocks),
+        )?;
+        self.audit_log.record(
+            AuditEventKind::TxPrepared,
+            Some(tx.id.clone()),
+            "Send tx with memo",
+        );
+        Ok(tx)
+    }
+
+    pub fn prepare_send_tx_with_utxos(
+        &self,
+        address: String,
+        amount: u64,
+        utxos: Vec<String>,
+        confirm_in_blocks: u32,
+    ) -> Result<Tx> {
+        if utxos.is_empty() {
+            return Err(invalid_input("At least one UTXO is required"));
+        }
+        let utxos =
+            try_collect(utxos.iter().map(|utxo| {
+                OutPoint::from_str(utxo).map_to_invalid_input("Invalid UTXO outpoint")
+            }))?;
+
+        let tx = self.prepare_payment_tx(
+            vec![Recipient {
+                address,
+                amount_sat: amount,
+            }],
+            Some(utxos),
+            None,
+            FeeTarget::ConfirmInBlocks(confirm_in_blocks),
+        )?;
+        self.audit_log.record(
+            AuditEventKind::TxPrepared,
+            Some(tx.id.clone()),
+            "Send tx with manually selected UTXOs",
+        );
+        Ok(tx)
+    }
+
+    // If `selected_utxos` is provided, only those UTXOs are spent. Otherwise, all confirmed UTXOs
+    // are available to coin selection.
+    fn prepare_send_tx_internal(
+        &self,
+        recipients: Vec<Recipient>,
+        selected_utxos: Option<Vec<OutPoint>>,
+        memo: Option<Vec<u8>>,
+        fee_target: FeeTarget,
+    ) -> Result<(Tx, TransactionDetails)> {
+        if recipients.is_empty() {
+            return Err(invalid_input("At least one recipient is required"));
+        }
+
         let wallet = self.wallet.lock().unwrap();
         let network = wallet.network();
-        let address =
-            parse_address(address, network).map_to_invalid_input("Invalid bitcoin address")?;
 
-        if !(1..=25).contains(&confirm_in_blocks) {
-            return Err(invalid_input(
-                "Invalid block confirmation target. Please use a target in the range [1; 25]",
-            ));
-        }
+        let mut outputs = Vec::with_capacity(recipients.len());
+        for recipient in recipients {
+            let address = parse_address(recipient.address, network)
+                .map_to_invalid_input("Invalid bitcoin address")?;
+
+            let address_is_mine = wallet
+                .is_mine(&address.script_pubkey())
+                .map_to_permanent_failure("Failed to check if address belongs to the wallet")?;
+            if address_is_mine {
+                return Err(runtime_error(
+                    WalletRuntimeErrorCode::SendToOurselves,
+                    "Trying to send funds to an address belonging to the wallet",
+                ));
+            }
 
-        let address_is_mine = wallet
-            .is_mine(&address.script_pubkey())
-            .map_to_permanent_failure("Failed to check if address belongs to the wallet")?;
-        if address_is_mine {
-            return Err(runtime_error(
-                WalletRuntimeErrorCode::SendToOurselves,
-                "Trying to drain wallet to address belonging to the wallet",
-            ));
+            outputs.push((address.script_pubkey(), recipient.amount_sat));
         }
+        let amount_sat = outputs
+            .iter()
+            .try_fold(0u64, |sum, (_, amount_sat)| sum.checked_add(*amount_sat))
+            .ok_or_else(|| invalid_input("The sum of the amounts is too large"))?;
+
+        fee_target.validate(&self.confirm_in_blocks_range)?;
         drop(wallet); // To release the lock.
 
-        let fee_rate = self
-            .blockchain
-            .estimate_fee(confirm_in_blocks as usize)
-            .map_to_runtime_error(
-                WalletRuntimeErrorCode::ElectrumServiceUnavailable,
-                "Failed to estimate fee for send tx",
-            )?;
+        let fee_rate = self.get_fee_rate(fee_target, "Failed to estimate fee for send tx")?;
 
         let wallet = self.wallet.lock().unwrap();
 
         let confirmed_utxo_outpoints = Self::get_confirmed_utxo_outpoints(&wallet)?;
+        let utxo_outpoints = match selected_utxos {
+            None => confirmed_utxo_outpoints,
+            Some(selected_utxos) => {
+                if let Some(utxo) = selected_utxos
+                    .iter()
+                    .find(|utxo| !confirmed_utxo_outpoints.contains(utxo))
+                {
+                    return Err(invalid_input(format!(
+                        "UTXO {utxo} is unknown, already spent or not confirmed"
+                    )));
+                }
+                selected_utxos
+            }
+        };
 
         let mut tx_builder = wallet.build_tx();
 
         tx_builder
-            .add_utxos(&confirmed_utxo_outpoints)
+            .add_utxos(&utxo_outpoints)
             .map_to_permanent_failure("Failed to add utxos to tx builder")?
             .manually_selected_only()
-            .add_recipient(address.script_pubkey(), amount)
+            .set_recipients(outputs)
             .fee_rate(fee_rate)
             .enable_rbf();
+        if let Some(memo) = &memo {
+            tx_builder.add_data(memo);
+        }
 
-        let (psbt, tx_details) = tx_builder.finish().map_to_runtime_error(
-            WalletRuntimeErrorCode::NotEnoughFunds,
-            "Failed to create PSBT",
-        )?;
+        let (psbt, tx_details) = tx_builder
+            .finish()
+            .map_err(|e| map_to_not_enough_funds(e, amount_sat))?;
 
         let fee = match tx_details.fee {
             None => return Err(permanent_failure("Empty fee using an Electrum backend")),
@@ -332,14 +2290,116 @@ impl Wallet {
         let tx = Tx {
             id: tx_details.txid.to_string(),
             blob: serialize(&psbt),
-            on_chain_fee_sat: fee,
-            output_sat: tx_details.sent - fee,
+            on_chain_fee: Amount::from_sat(fee),
+            output: Amount::from_sat(tx_details.sent - tx_details.received - fee),
+        };
+
+        Ok((tx, tx_details))
+    }
+
+    // Preparing a payment fails if it was already made within the duplicate payment window or if it
+    // exceeds the spending policy. Fee estimations don't pay anything, so they skip both checks.
+    fn prepare_payment_tx(
+        &self,
+        recipients: Vec<Recipient>,
+        selected_utxos: Option<Vec<OutPoint>>,
+        memo: Option<Vec<u8>>,
+        fee_target: FeeTarget,
+    ) -> Result<Tx> {
+        self.ensure_no_duplicate_payment(&recipients)?;
+        let (tx, tx_details) =
+            self.prepare_send_tx_internal(recipients, selected_utxos, memo, fee_target)?;
+
+        let violation = {
+            let wallet = self.wallet.lock().unwrap();
+            self.find_spending_policy_violation(&wallet, &tx_details)?
         };
+        self.enforce_spending_policy(violation)?;
 
         Ok(tx)
     }
 
-    fn get_tx_status_internal(wallet: &bdk::Wallet<Tree>, txid: Txid) -> Result<TxStatus> {
+    fn ensure_no_duplicate_payment(&self, recipients: &[Recipient]) -> Result<()> {
+        let window = match *self.duplicate_payment_window.lock().unwrap() {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+
+        let wallet = self.wallet.lock().unwrap();
+        for recipient in recipients {
+            let address = parse_address(recipient.address.clone(), wallet.network())
+                .map_to_invalid_input("Invalid bitcoin address")?;
+            let payments = Self::find_payments(
+                &wallet,
+                &address.script_pubkey(),
+                recipient.amount_sat,
+                window,
+            )?;
+            if let Some(payment) = payments.first() {
+                return Err(runtime_error(
+                    WalletRuntimeErrorCode::DuplicatePayment {
+                        txid: payment.txid.to_string(),
+                    },
+                    format!(
+                        "{} sats were already paid to {address} in tx {}",
+                        recipient.amount_sat, payment.txid
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Funds leaving the wallet are the amount sent minus change, i.e. including the fee. Txs only
+    // spending foreign inputs (e.g. sweeps) don't count.
+    fn find_spending_policy_violation(
+        &self,
+        wallet: &BdkWallet,
+        tx: &TransactionDetails,
+    ) -> Result<Option<SpendingPolicyViolation>> {
+        let policy = self.spending_policy.lock().unwrap().clone();
+        if policy == SpendingPolicy::default() {
+            return Ok(None);
+        }
+        let now = SystemTime::now();
+        let previous_spends: Vec<Spend> = wallet
+            .list_transactions(false)
+            .map_to_permanent_failure("Wallet failed to list txs")?
+            .into_iter()
+            .filter(|previous_tx| previous_tx.txid != tx.txid && previous_tx.sent > 0)
+            .map(|previous_tx| Spend {
+                amount_sat: previous_tx.sent.saturating_sub(previous_tx.received),
+                spent_at: get_sent_at(&previous_tx, now),
+            })
+            .collect();
+        let tx_sat = tx.sent.saturating_sub(tx.received);
+        Ok(find_policy_violation(
+            &policy,
+            tx_sat,
+            &previous_spends,
+            now,
+        ))
+    }
+
+    fn enforce_spending_policy(&self, violation: Option<SpendingPolicyViolation>) -> Result<()> {
+        let auth = self.auth.lock().unwrap().clone();
+        enforce_spending_policy(violation, auth.as_deref())
+    }
+
+    // The tx isn't signed yet, so the weight of the signatures has to be estimated.
+    fn estimate_signed_vsize(wallet: &BdkWallet, unsigned_tx: &Transaction) -> Result<usize> {
+        let satisfaction_weight = wallet
+            .get_descriptor_for_keychain(KeychainKind::External)
+            .max_satisfaction_weight()
+            .map_to_permanent_failure("Failed to compute max satisfaction weight")?;
+        let segwit_marker_and_flag_weight = 2;
+        let estimated_weight = unsigned_tx.weight()
+            + segwit_marker_and_flag_weight
+            + satisfaction_weight * unsigned_tx.input.len();
+        Ok((estimated_weight + 3) / 4)
+    }
+
+    fn get_tx_status_internal(wallet: &BdkWallet, txid: Txid) -> Result<TxStatus> {
         let tip_height = Self::get_synced_tip_height(wallet)?;
         let include_raw = false;
         let tx = wallet
@@ -349,61 +2409,502 @@ impl Wallet {
     }
 
     pub fn sync(&self) -> Result<()> {
-        let mut wallet_to_sync = self.wallet_to_sync.lock().unwrap();
-        wallet_to_sync
-            .sync(&self.blockchain, SyncOptions::default())
-            .map_err(|e| match e {
-                Error::Electrum(_) => {
-                    runtime_error(WalletRuntimeErrorCode::ElectrumServiceUnavailable, e)
+        self.sync_with_optional_progress(None)
+    }
+
+    pub fn sync_with_progress(&self, callback: Box<dyn SyncProgressCallback>) -> Result<()> {
+        self.sync_with_optional_progress(Some(callback.as_ref()))
+    }
+
+    fn sync_with_optional_progress(
+        &self,
+        callback: Option<&dyn SyncProgressCallback>,
+    ) -> Result<()> {
+        let result = self.sync_internal(callback);
+        match &result {
+            Ok(()) => self
+                .audit_log
+                .record(AuditEventKind::SyncSucceeded, None, ""),
+            Err(e) => self
+                .audit_log
+                .record(AuditEventKind::SyncFailed, None, e.to_string()),
+        }
+        result
+    }
+
+    // BDK doesn't report progress while scanning with the Electrum or Esplora backend, so the scan
+    // is only reported when it starts and once it's done. The total is the number of script pubkeys
+    // the wallet knows about, which grows if the scan finds txs of the last ones.
+    fn sync_internal(&self, callback: Option<&dyn SyncProgressCallback>) -> Result<()> {
+        let report = |phase| {
+            if let Some(callback) = callback {
+                callback.on_progress(phase);
+            }
+        };
+        let request = self.sync_requests.fetch_add(1, Ordering::SeqCst) + 1;
+        let is_aborted = || self.aborted_sync_requests.load(Ordering::SeqCst) >= request;
+        let check_aborted = || {
+            if is_aborted() {
+                return Err(Error::Generic("The sync was aborted".to_string()));
+            }
+            Ok(())
+        };
+        let aborted_error =
+            || runtime_error(WalletRuntimeErrorCode::SyncAborted, "The sync was aborted");
+        let _sync_guard = self.sync_lock.lock().unwrap();
+        if is_aborted() {
+            return Err(aborted_error());
+        }
+        let wallet_to_sync = self.create_wallet_to_sync()?;
+        let (scan_relay, scan_blockchain) = self.connect_for_scan()?;
+        report(SyncPhase::Connecting);
+        let result = self
+            .blockchain
+            .call(|blockchain| {
+                check_aborted()?;
+                if callback.is_some() {
+                    report(SyncPhase::FetchingHeaders);
+                    blockchain.get_height()?;
+                    check_aborted()?;
                 }
-                Error::Sled(e) => permanent_failure(e),
-                _ => runtime_error(
-                    WalletRuntimeErrorCode::GenericError,
-                    "Failed to sync the BDK wallet",
-                ),
-            })?;
-        let mut wallet = self.wallet.lock().unwrap();
-        std::mem::swap(&mut *wallet_to_sync, &mut *wallet);
+                Ok(())
+            })
+            .and_then(|()| {
+                let total = count_script_pubkeys(&wallet_to_sync)?;
+                report(SyncPhase::ScanningScriptPubkeys { index: 0, total });
+                let synced_wallet = Self::scan_until_aborted(
+                    wallet_to_sync,
+                    scan_blockchain,
+                    scan_relay,
+                    is_aborted,
+                )?;
+                let total = count_script_pubkeys(&synced_wallet)?;
+                report(SyncPhase::ScanningScriptPubkeys {
+                    index: total,
+                    total,
+                });
+                Ok(synced_wallet)
+            });
+        // The result of a sync that completed after it got aborted is discarded as well, so that
+        // the caller can rely on the wallet not changing after an abort.
+        if is_aborted() {
+            return Err(aborted_error());
+        }
+        let synced_wallet =
+            result.map_err(|e| map_blockchain_error(e, "Failed to sync the BDK wallet"))?;
+        let wallet = self.wallet.lock().unwrap();
+        let mut database = get_write_handle(&*wallet.database())?;
+        apply_snapshot(&*synced_wallet.database(), &mut database)
+            .map_to_permanent_failure("Failed to store the synced wallet data")?;
+        self.synced.store(true, Ordering::SeqCst);
         Ok(())
     }
 
-    fn load_wallets(config: &Config) -> Result<(BdkWallet, BdkWallet)> {
-        let db_path = Path::new(&config.wallet_db_path);
-        let db = sled::open(db_path).map_to_permanent_failure("Failed to open sled database")?;
-
-        let change_descriptor = get_change_descriptor_from_descriptor(&config.watch_descriptor)?;
-        let change_descriptor = Some(&change_descriptor);
-
-        let wallet_1 = {
-            let db_tree = db
-                .open_tree("bdk-wallet-database-1")
-                .map_to_permanent_failure("Failed to open sled database tree")?;
-            bdk::Wallet::new(
-                &config.watch_descriptor,
-                change_descriptor,
-                config.network,
-                db_tree,
-            )
-            .map_to_permanent_failure("Failed to create wallet")?
-        };
-
-        let wallet_2 = {
-            let db_tree = db
-                .open_tree("bdk-wallet-database-2")
-                .map_to_permanent_failure("Failed to open sled database tree")?;
-            bdk::Wallet::new(
-                &config.watch_descriptor,
-                change_descriptor,
-                config.network,
-                db_tree,
-            )
-            .map_to_permanent_failure("Failed to create wallet")?
+    // A BDK sync can't be interrupted, so the scan runs on its own thread. If the sync gets
+    // aborted, the connections of the relay are closed, which makes the scan fail right away. The
+    // thread only updates the copy of the wallet made for the sync, so its result is discarded.
+    fn scan_until_aborted(
+        wallet_to_sync: BdkMemoryWallet,
+        blockchain: FailoverBlockchain<AnyBlockchain>,
+        relay: InterruptibleRelay,
+        is_aborted: impl Fn() -> bool,
+    ) -> std::result::Result<BdkMemoryWallet, Error> {
+        let (result_sender, result_receiver) = channel();
+        thread::spawn(move || {
+            let result = blockchain
+                .call(|blockchain| wallet_to_sync.sync(blockchain, SyncOptions::default()));
+            let _ = result_sender.send(result.map(|()| wallet_to_sync));
+        });
+        loop {
+            match result_receiver.recv_timeout(SYNC_ABORT_POLL_INTERVAL) {
+                Ok(result) => return result,
+                Err(RecvTimeoutError::Timeout) => {
+                    if is_aborted() {
+                        relay.interrupt();
+                        return Err(Error::Generic("The sync was aborted".to_string()));
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::Generic("The sync thread panicked".to_string()));
+                }
+            }
+        }
+    }
+
+    // The relay enforces the timeout for the electrum client, which doesn't support timeouts on
+    // connections through a SOCKS5 proxy. The esplora client enforces it itself.
+    fn connect_for_scan(&self) -> Result<(InterruptibleRelay, FailoverBlockchain<AnyBlockchain>)> {
+        let backend = &self.scan_backend;
+        let timeout = backend
+            .timeout_secs
+            .map(|timeout| Duration::from_secs(u64::from(timeout)));
+        let (relay_timeout, client_timeout) = match backend.backend {
+            BlockchainBackend::Esplora { .. } => (None, backend.timeout_secs),
+            _ => (timeout, None),
         };
+        let relay = InterruptibleRelay::start(backend.socks5_proxy.clone(), relay_timeout)
+            .map_to_permanent_failure("Failed to start the relay for the sync")?;
+        let blockchain = Self::create_blockchain(
+            &backend.backend,
+            backend.stop_gap,
+            Some(relay.address()),
+            client_timeout,
+            backend.electrum_retries,
+        )?;
+        Ok((relay, blockchain))
+    }
 
-        if Self::get_synced_tip_height(&wallet_1)? > Self::get_synced_tip_height(&wallet_2)? {
-            Ok((wallet_1, wallet_2))
-        } else {
-            Ok((wallet_2, wallet_1))
+    fn create_wallet_to_sync(&self) -> Result<BdkMemoryWallet> {
+        let wallet = self.wallet.lock().unwrap();
+        let mut snapshot = take_snapshot(&*wallet.database())
+            .map_to_permanent_failure("Failed to take a snapshot of the wallet database")?;
+        forget_recent_confirmations(&mut snapshot, MAX_REORG_DEPTH)
+            .map_to_permanent_failure("Failed to prepare the snapshot for the sync")?;
+        bdk::Wallet::new(
+            &self.descriptor,
+            Some(&self.change_descriptor),
+            wallet.network(),
+            snapshot,
+        )
+        .map_to_permanent_failure("Failed to create wallet")
+    }
+
+    // The sync returns once it notices the abort, see `scan_until_aborted()`.
+    pub fn abort_sync(&self) {
+        let requested = self.sync_requests.load(Ordering::SeqCst);
+        self.aborted_sync_requests
+            .fetch_max(requested, Ordering::SeqCst);
+    }
+
+    pub fn get_audit_log(
+        &self,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> Result<Vec<AuditEvent>> {
+        self.audit_log.get(from, to)
+    }
+
+    pub fn set_label(&self, label: Label) -> Result<()> {
+        let network = self.wallet.lock().unwrap().network();
+        validate_reference(label.label_type, &label.reference, network)?;
+        self.labels.set(&label)
+    }
+
+    pub fn list_labels(&self) -> Result<Vec<Label>> {
+        self.labels.list()
+    }
+
+    pub fn export_labels(&self) -> Result<String> {
+        Ok(to_bip329(&self.labels.list()?))
+    }
+
+    // Existing labels of the imported references are replaced, all others are kept. Returns the
+    // number of imported labels.
+    pub fn import_labels(&self, jsonl: String) -> Result<u32> {
+        let network = self.wallet.lock().unwrap().network();
+        let labels = parse_bip329(&jsonl, network)?;
+        for label in &labels {
+            self.labels.set(label)?;
+        }
+        Ok(labels.len() as u32)
+    }
+
+    // Proposals are keyed by the tx id, so saving a tx again replaces the memo of its proposal. The
+    // proposal of a tx is deleted once the tx is broadcast.
+    pub fn save_tx_proposal(&self, tx: Tx, memo: String) -> Result<()> {
+        let psbt = deserialize::<Psbt>(&tx.blob).map_to_invalid_input("Invalid tx blob")?;
+        if psbt.unsigned_tx.txid().to_string() != tx.id {
+            return Err(invalid_input("The tx id doesn't match the tx blob"));
+        }
+        self.tx_proposals.save(&TxProposal {
+            tx,
+            memo,
+            created_at: SystemTime::now(),
+        })
+    }
+
+    pub fn list_tx_proposals(&self) -> Result<Vec<TxProposal>> {
+        self.tx_proposals.list()
+    }
+
+    // Only owner sessions may delete proposals, otherwise deleting one would release its funds to
+    // be signed without an owner.
+    pub fn delete_tx_proposal(&self, id: String) -> Result<()> {
+        let auth = self.auth.lock().unwrap().clone();
+        ensure_may_sign_proposal(auth.as_deref())?;
+        if !self.tx_proposals.delete(&id)? {
+            return Err(invalid_input("Tx proposal not found"));
+        }
+        Ok(())
+    }
+
+    // Collects information about the state of the wallet to be attached to bug reports. An
+    // unreachable blockchain backend is reported in the result instead of failing the call.
+    pub fn get_diagnostics(&self) -> Result<WalletDiagnostics> {
+        let started_at = Instant::now();
+        let backend_tip_height = match self.blockchain.call(|blockchain| blockchain.get_height()) {
+            Ok(height) => Some(height),
+            Err(e) => {
+                warn!("Failed to reach the blockchain backend: {e}");
+                None
+            }
+        };
+        let backend_latency_ms =
+            backend_tip_height.map(|_| started_at.elapsed().as_millis() as u64);
+
+        let last_synced_at = self.get_last_synced_at()?;
+
+        let wallet = self.wallet.lock().unwrap();
+        let number_of_utxos = wallet
+            .list_unspent()
+            .map_to_permanent_failure("Failed to list UTXOs")?
+            .len();
+        let number_of_unconfirmed_txs = wallet
+            .list_transactions(false)
+            .map_to_permanent_failure("Failed to list transactions")?
+            .iter()
+            .filter(|tx| tx.confirmation_time.is_none())
+            .count();
+
+        Ok(WalletDiagnostics {
+            backend_reachable: backend_tip_height.is_some(),
+            backend_latency_ms,
+            backend_tip_height,
+            synced_tip_height: Self::get_synced_tip_height(&wallet)?,
+            last_synced_at,
+            db_size_bytes: get_dir_size(&self.db_path)
+                .map_to_permanent_failure("Failed to get size of the wallet database")?,
+            number_of_utxos: number_of_utxos as u32,
+            number_of_unconfirmed_txs: number_of_unconfirmed_txs as u32,
+        })
+    }
+
+    fn get_last_synced_at(&self) -> Result<Option<SystemTime>> {
+        self.audit_log.get_last_synced_at()
+    }
+
+    pub fn export_audit_log(
+        &self,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> Result<String> {
+        Ok(to_json(&self.audit_log.get(from, to)?))
+    }
+
+    // Electrum and Esplora syncs retrieve the whole history of every script, so a rescan always
+    // starts from the genesis block. The start height is only accepted for backends that can scan
+    // from a given block, none of the supported ones can.
+    pub fn rescan(&self, _from_height: Option<u32>) -> Result<()> {
+        self.reset_db()?;
+        self.sync()
+    }
+
+    // Only the BDK wallet databases are cleared, the audit log is kept.
+    pub fn reset_db(&self) -> Result<()> {
+        // Same locking order as in `sync()`. A running sync would write its results back otherwise.
+        let _sync_guard = self.sync_lock.lock().unwrap();
+        let wallet = self.wallet.lock().unwrap();
+        let database = wallet.database();
+        match &*database {
+            AnyDatabase::Sled(tree) => tree
+                .clear()
+                .map_to_permanent_failure("Failed to clear the wallet database")?,
+            // Everything except the descriptor checksums and the schema version.
+            AnyDatabase::Sqlite(sqlite) => sqlite
+                .connection
+                .execute_batch(
+                    "DELETE FROM script_pubkeys;
+                    DELETE FROM utxos;
+                    DELETE FROM transactions;
+                    DELETE FROM transaction_details;
+                    DELETE FROM last_derivation_indices;
+                    DELETE FROM sync_time;",
+                )
+                .map_to_permanent_failure("Failed to clear the wallet database")?,
+            AnyDatabase::Memory(_) => {
+                return Err(permanent_failure("Unexpected in-memory wallet database"))
+            }
+        }
+        self.synced.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // Runs `sync()` on a dedicated thread, so that the caller doesn't need to provide its own
+    // thread to avoid blocking. The outcome is reported through the provided callback.
+    pub fn sync_async(self: Arc<Self>, callback: Box<dyn SyncCallback>) {
+        thread::spawn(move || match self.sync() {
+            Ok(()) => callback.on_sync_finished(),
+            Err(e) => callback.on_sync_failed(e.to_string()),
+        });
+    }
+
+    // Spawns a thread that syncs the wallet every `interval_secs` seconds and notifies the
+    // listener about changes. The thread only holds a weak reference to the wallet, so it
+    // terminates once the wallet is dropped.
+    pub fn start_background_sync(
+        self: Arc<Self>,
+        interval_secs: u64,
+        listener: Box<dyn WalletEventListener>,
+    ) -> Result<()> {
+        if interval_secs == 0 {
+            return Err(invalid_input(
+                "The background sync interval must be positive",
+            ));
+        }
+
+        let mut background_sync_stop = self.background_sync_stop.lock().unwrap();
+        if background_sync_stop.is_some() {
+            return Err(invalid_input("Background sync is already running"));
+        }
+
+        let mut previous_snapshot = self.take_snapshot()?;
+        let (stop_sender, stop_receiver) = channel();
+        *background_sync_stop = Some(stop_sender);
+
+        let weak_wallet = Arc::downgrade(&self);
+        thread::spawn(move || loop {
+            let wallet = match weak_wallet.upgrade() {
+                Some(wallet) => wallet,
+                None => break,
+            };
+            match wallet.sync().and_then(|()| wallet.take_snapshot()) {
+                Ok(snapshot) => {
+                    notify_changes(&previous_snapshot, &snapshot, listener.as_ref());
+                    previous_snapshot = snapshot;
+                }
+                Err(e) => warn!("Background sync failed: {e}"),
+            }
+            drop(wallet);
+
+            match stop_receiver.recv_timeout(Duration::from_secs(interval_secs)) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn stop_background_sync(&self) {
+        if let Some(stop_sender) = self.background_sync_stop.lock().unwrap().take() {
+            let _ = stop_sender.send(());
+        }
+    }
+
+    fn take_snapshot(&self) -> Result<WalletSnapshot> {
+        let wallet = self.wallet.lock().unwrap();
+
+        let balance = wallet
+            .get_balance()
+            .map_to_permanent_failure("Failed to get balance from bdk wallet")?
+            .into();
+
+        let tip_height = Self::get_synced_tip_height(&wallet)?;
+        let include_raw = false;
+        let txs = wallet
+            .list_transactions(include_raw)
+            .map_to_permanent_failure("Wallet failed to list txs")?
+            .into_iter()
+            .map(|tx| {
+                let txid = tx.txid;
+                let is_incoming = tx.received > tx.sent;
+                let status = Self::to_tx_status(Some(tx), tip_height);
+                (
+                    txid,
+                    TxSnapshot {
+                        is_incoming,
+                        status,
+                    },
+                )
+            })
+            .collect();
+
+        Ok(WalletSnapshot { balance, txs })
+    }
+
+    // Opens the two BDK wallet databases (see `sync()`) and the audit log.
+    fn open_databases(
+        config: &Config,
+    ) -> Result<(AnyDatabase, AuditLog, LabelStore, TxProposalStore)> {
+        let db_path = Path::new(&config.wallet_db_path);
+        match config.database {
+            DatabaseBackend::Sled => {
+                let db = sled::open(db_path).map_err(|e| match e {
+                    // sled locks the database file exclusively, but doesn't have a dedicated
+                    // error for it.
+                    sled::Error::Io(ref io_error)
+                        if io_error.to_string().contains("could not acquire lock") =>
+                    {
+                        runtime_error(
+                            WalletRuntimeErrorCode::DatabaseLocked,
+                            "The wallet database is in use by another Wallet instance or process",
+                        )
+                    }
+                    _ => permanent_failure(format!("Failed to open sled database: {e}")),
+                })?;
+                let open_tree = |name| {
+                    db.open_tree(name)
+                        .map_to_permanent_failure("Failed to open sled database tree")
+                };
+                let mut database: AnyDatabase = open_tree(WALLET_DATABASE)?.into();
+
+                let tree_names = db.tree_names();
+                let legacy_names: Vec<&str> = LEGACY_WALLET_DATABASES
+                    .into_iter()
+                    .filter(|name| tree_names.iter().any(|tree| &tree[..] == name.as_bytes()))
+                    .collect();
+                let legacy_databases = legacy_names
+                    .iter()
+                    .map(|name| Ok(open_tree(*name)?.into()))
+                    .collect::<Result<Vec<AnyDatabase>>>()?;
+                migrate_legacy_databases(&mut database, &legacy_databases)?;
+                for name in legacy_names {
+                    db.drop_tree(name)
+                        .map_to_permanent_failure("Failed to drop legacy wallet database")?;
+                }
+
+                Ok((
+                    database,
+                    AuditLog::open_sled(&db)?,
+                    LabelStore::open_sled(&db)?,
+                    TxProposalStore::open_sled(&db)?,
+                ))
+            }
+            DatabaseBackend::Sqlite => {
+                std::fs::create_dir_all(db_path)
+                    .map_to_permanent_failure("Failed to create wallet db directory")?;
+                let open_sqlite = |path: &Path| {
+                    SqliteDatabase::from_config(&SqliteDbConfiguration {
+                        path: path.to_string_lossy().to_string(),
+                    })
+                    .map_to_permanent_failure("Failed to open SQLite database")
+                };
+                let mut database: AnyDatabase =
+                    open_sqlite(&db_path.join(format!("{WALLET_DATABASE}.sqlite")))?.into();
+
+                let legacy_paths: Vec<PathBuf> = LEGACY_WALLET_DATABASES
+                    .iter()
+                    .map(|name| db_path.join(format!("{name}.sqlite")))
+                    .filter(|path| path.exists())
+                    .collect();
+                let legacy_databases = legacy_paths
+                    .iter()
+                    .map(|path| Ok(open_sqlite(path)?.into()))
+                    .collect::<Result<Vec<AnyDatabase>>>()?;
+                migrate_legacy_databases(&mut database, &legacy_databases)?;
+                drop(legacy_databases);
+                for path in legacy_paths {
+                    std::fs::remove_file(path)
+                        .map_to_permanent_failure("Failed to remove legacy wallet database")?;
+                }
+
+                Ok((
+                    database,
+                    AuditLog::open_sqlite(&db_path.join("audit-log.sqlite"))?,
+                    LabelStore::open_sqlite(&db_path.join("labels.sqlite"))?,
+                    TxProposalStore::open_sqlite(&db_path.join("tx-proposals.sqlite"))?,
+                ))
+            }
         }
     }
 
@@ -418,7 +2919,7 @@ impl Wallet {
         }
     }
 
-    fn get_confirmed_utxo_outpoints(wallet: &bdk::Wallet<Tree>) -> Result<Vec<OutPoint>> {
+    fn get_confirmed_utxo_outpoints(wallet: &BdkWallet) -> Result<Vec<OutPoint>> {
         let mut confirmed_utxo_outpoints: Vec<OutPoint> = Vec::new();
 
         for utxo in wallet
@@ -439,19 +2940,6 @@ impl Wallet {
     }
 
     fn map_to_tx_details(tx: TransactionDetails, wallet: &BdkWallet) -> Result<TxDetails> {
-        let tip_height = Self::get_synced_tip_height(wallet)?;
-
-        let raw_tx = tx
-            .transaction
-            .as_ref()
-            .ok_or_else(|| permanent_failure("Tx does not have raw tx"))?;
-
-        let foreign_output = Self::find_foreign_output(&raw_tx.output, wallet)?
-            .ok_or_else(|| permanent_failure("None of tx outputs are foreign"))?;
-        let output_address = Address::from_script(&foreign_output, wallet.network())
-            .map_to_permanent_failure("Failed to build address from script")?
-            .to_string();
-
         let on_chain_fee_sat = tx
             .fee
             .ok_or_else(|| permanent_failure("Tx does not have fee set"))?;
@@ -463,19 +2951,93 @@ impl Wallet {
         }
         let output_sat = tx.sent - tx.received - on_chain_fee_sat;
 
+        Self::build_tx_details(tx, output_sat, on_chain_fee_sat, wallet)
+    }
+
+    // The inputs of a sweep tx don't belong to the wallet, so nothing counts as sent and BDK doesn't
+    // know the fee. The output is the total of the tx outputs and the fee is taken from the PSBT.
+    fn map_to_sweep_tx_details(
+        tx: TransactionDetails,
+        fee_sat: u64,
+        wallet: &BdkWallet,
+    ) -> Result<TxDetails> {
+        let output_sat = tx
+            .transaction
+            .as_ref()
+            .ok_or_else(|| permanent_failure("Tx does not have raw tx"))?
+            .output
+            .iter()
+            .map(|output| output.value)
+            .sum();
+
+        Self::build_tx_details(tx, output_sat, fee_sat, wallet)
+    }
+
+    fn build_tx_details(
+        tx: TransactionDetails,
+        output_sat: u64,
+        on_chain_fee_sat: u64,
+        wallet: &BdkWallet,
+    ) -> Result<TxDetails> {
+        let tip_height = Self::get_synced_tip_height(wallet)?;
+
+        let raw_tx = tx
+            .transaction
+            .as_ref()
+            .ok_or_else(|| permanent_failure("Tx does not have raw tx"))?;
+
+        let outputs = Self::map_to_decoded_outputs(&raw_tx.output, wallet)?;
+        let output_address = get_primary_output_address(&outputs)
+            .ok_or_else(|| permanent_failure("None of tx outputs pays to an address"))?;
+
         Ok(TxDetails {
             id: tx.txid.to_string(),
             output_address,
-            output_sat,
-            on_chain_fee_sat,
+            outputs,
+            output: Amount::from_sat(output_sat),
+            on_chain_fee: Amount::from_sat(on_chain_fee_sat),
             status: Self::to_tx_status(Some(tx), tip_height),
+            fiat_value: None,
         })
     }
 
-    fn find_foreign_output(outputs: &Vec<TxOut>, wallet: &BdkWallet) -> Result<Option<Script>> {
-        // Waiting for Iterator::try_find() to become stable.
+    fn map_to_decoded_outputs(
+        outputs: &[TxOut],
+        wallet: &BdkWallet,
+    ) -> Result<Vec<DecodedTxOutput>> {
+        try_collect(outputs.iter().map(|output| {
+            Ok(DecodedTxOutput {
+                address: Address::from_script(&output.script_pubkey, wallet.network())
+                    .ok()
+                    .map(|a| a.to_string()),
+                value_sat: output.value,
+                is_mine: wallet
+                    .is_mine(&output.script_pubkey)
+                    .map_to_permanent_failure("Failed to check if output belongs to the wallet")?,
+            })
+        }))
+    }
+
+    // OP_RETURN outputs (e.g. memos) don't pay anyone, so they are skipped.
+    fn find_foreign_output(outputs: &Vec<TxOut>, wallet: &BdkWallet) -> Result<Option<Script>> {
+        // Waiting for Iterator::try_find() to become stable.
+        for output in outputs {
+            if output.script_pubkey.is_op_return() {
+                continue;
+            }
+            if !wallet
+                .is_mine(&output.script_pubkey)
+                .map_to_permanent_failure("Failed to check if output belongs to the wallet")?
+            {
+                return Ok(Some(output.script_pubkey.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    fn find_own_output(outputs: &[TxOut], wallet: &BdkWallet) -> Result<Option<Script>> {
         for output in outputs {
-            if !wallet
+            if wallet
                 .is_mine(&output.script_pubkey)
                 .map_to_permanent_failure("Failed to check if output belongs to the wallet")?
             {
@@ -485,18 +3047,47 @@ impl Wallet {
         Ok(None)
     }
 
+    fn to_incoming_tx(
+        tx: TransactionDetails,
+        script_pubkey: &Script,
+        tip_height: u32,
+    ) -> IncomingTx {
+        let (amount_sat, vsize, signals_rbf) = match &tx.transaction {
+            Some(raw_tx) => (
+                get_amount_paid_to(&tx, script_pubkey),
+                raw_tx.vsize(),
+                raw_tx.is_explicitly_rbf(),
+            ),
+            None => (0, 0, false),
+        };
+        // The fee is only known if the previous outputs of all inputs have been fetched.
+        let fee_rate_sat_per_vbyte = match tx.fee {
+            Some(fee) if vsize > 0 => Some(fee as f32 / vsize as f32),
+            _ => None,
+        };
+        IncomingTx {
+            id: tx.txid.to_string(),
+            amount: Amount::from_sat(amount_sat),
+            fee_rate_sat_per_vbyte,
+            signals_rbf,
+            status: Self::to_tx_status(Some(tx), tip_height),
+        }
+    }
+
     fn to_tx_status(tx: Option<TransactionDetails>, tip_height: u32) -> TxStatus {
         match tx {
             None => TxStatus::NotInMempool,
             Some(tx) => match tx.confirmation_time {
                 None => TxStatus::InMempool,
+                // The tip height of the last sync is stored together with the txs, so it's only
+                // below the confirmation height if the database doesn't have a sync time.
                 Some(block_time) => {
-                    debug_assert!(tip_height >= block_time.height);
-                    let number_of_blocks = 1 + tip_height - block_time.height;
+                    let number_of_blocks = 1 + tip_height.saturating_sub(block_time.height);
                     let confirmed_at =
                         SystemTime::UNIX_EPOCH + Duration::from_secs(block_time.timestamp);
                     TxStatus::Confirmed {
                         number_of_blocks,
+                        block_height: block_time.height,
                         confirmed_at,
                     }
                 }
@@ -505,20 +3096,333 @@ impl Wallet {
     }
 }
 
-fn get_change_descriptor_from_descriptor(descriptor: &str) -> Result<String> {
-    if !descriptor.ends_with("0/*)") {
-        return Err(invalid_input(
-            "Invalid descriptor: Descriptor doesn't end with \"0/*)\". Could it already be a change descriptor?",
-        ));
+// The values of a tx blob are provided by the caller, so their sum may overflow.
+fn checked_add_sat(sum: u64, value_sat: u64) -> Result<u64> {
+    sum.checked_add(value_sat)
+        .ok_or_else(|| invalid_input("Invalid tx blob"))
+}
+
+// Coin selection reports the amount needed including the fee, so the fee is what exceeds the amount
+// sent to the recipients.
+fn map_to_not_enough_funds(error: Error, amount_sat: u64) -> perro::Error<WalletRuntimeErrorCode> {
+    match error {
+        Error::InsufficientFunds { needed, available } => {
+            let shortfall = FundsShortfall {
+                required_sat: needed,
+                available_sat: available,
+                fee_sat: needed.saturating_sub(amount_sat),
+                missing_sat: needed.saturating_sub(available),
+            };
+            let msg = format!(
+                "Not enough funds: {} sats are required, but only {} sats are available",
+                shortfall.required_sat, shortfall.available_sat
+            );
+            runtime_error(
+                WalletRuntimeErrorCode::NotEnoughFunds {
+                    shortfall: Some(shortfall),
+                },
+                msg,
+            )
+        }
+        _ => runtime_error(
+            WalletRuntimeErrorCode::NotEnoughFunds { shortfall: None },
+            format!("Failed to create PSBT: {error}"),
+        ),
+    }
+}
+
+// Unconfirmed UTXOs of txs spending inputs of the wallet are change or transfers to ourselves.
+// Unlike incoming funds, they can't be double-spent by others.
+fn to_detailed_balance(utxos: &[LocalUtxo], txs: &[TransactionDetails]) -> DetailedBalance {
+    let txs: HashMap<&Txid, &TransactionDetails> = txs.iter().map(|tx| (&tx.txid, tx)).collect();
+    let mut external = 0;
+    let mut internal = 0;
+    let mut confirmed = 0;
+    let mut unconfirmed_incoming = 0;
+    let mut unconfirmed_self_transfers = 0;
+    for utxo in utxos {
+        let value = utxo.txout.value;
+        match utxo.keychain {
+            KeychainKind::External => external += value,
+            KeychainKind::Internal => internal += value,
+        }
+        match txs.get(&utxo.outpoint.txid) {
+            Some(tx) if tx.confirmation_time.is_some() => confirmed += value,
+            Some(tx) if tx.sent > 0 => unconfirmed_self_transfers += value,
+            _ => unconfirmed_incoming += value,
+        }
+    }
+    DetailedBalance {
+        external: Amount::from_sat(external),
+        internal: Amount::from_sat(internal),
+        confirmed: Amount::from_sat(confirmed),
+        unconfirmed_incoming: Amount::from_sat(unconfirmed_incoming),
+        unconfirmed_self_transfers: Amount::from_sat(unconfirmed_self_transfers),
+    }
+}
+
+fn is_consolidation_worthwhile(confirmed_utxos: usize, fee_rate_sat_per_vbyte: f32) -> bool {
+    confirmed_utxos >= CONSOLIDATION_MIN_UTXOS
+        && fee_rate_sat_per_vbyte <= CONSOLIDATION_MAX_FEE_RATE_SAT_PER_VBYTE
+}
+
+// The first foreign output is the recipient of a send tx. A tx without foreign outputs (e.g. a
+// consolidation) pays to the wallet itself, so its first own output is used instead. Outputs
+// without an address (e.g. OP_RETURN) are skipped.
+fn get_primary_output_address(outputs: &[DecodedTxOutput]) -> Option<String> {
+    let first_address = |is_mine: bool| {
+        outputs
+            .iter()
+            .filter(|output| output.is_mine == is_mine)
+            .find_map(|output| output.address.clone())
+    };
+    first_address(false).or_else(|| first_address(true))
+}
+
+// Electrum servers report the height of an unconfirmed tx as -1 if it spends unconfirmed outputs
+// and as 0 otherwise. Esplora doesn't provide this information without fetching every parent.
+fn has_unconfirmed_parents(
+    blockchain: &AnyBlockchain,
+    txid: &Txid,
+    script_pubkey: &Script,
+) -> std::result::Result<Option<bool>, Error> {
+    match blockchain {
+        AnyBlockchain::Electrum(electrum) => {
+            let history = electrum.script_get_history(script_pubkey)?;
+            Ok(history
+                .iter()
+                .find(|entry| &entry.tx_hash == txid)
+                .map(|entry| entry.height < 0))
+        }
+        AnyBlockchain::Esplora(_) => Ok(None),
+    }
+}
+
+pub(crate) fn get_confirm_in_blocks_range(
+    min_confirm_in_blocks: Option<u32>,
+    max_confirm_in_blocks: Option<u32>,
+) -> Result<RangeInclusive<u32>> {
+    let min = min_confirm_in_blocks.unwrap_or(DEFAULT_MIN_CONFIRM_IN_BLOCKS);
+    let max = max_confirm_in_blocks.unwrap_or(DEFAULT_MAX_CONFIRM_IN_BLOCKS);
+    if min < 1 || min > max || max > MAX_CONFIRM_IN_BLOCKS {
+        return Err(invalid_input(format!(
+            "Invalid block confirmation target range. Please use a range within [1; {MAX_CONFIRM_IN_BLOCKS}]"
+        )));
     }
+    Ok(min..=max)
+}
 
-    if descriptor.match_indices("0/*)").count() > 1 {
-        return Err(invalid_input(
-            "Invalid descriptor: Descriptor has multiple occurrences of substring \"0/*)\"",
+// The range is part of the error, so that apps can adapt the targets they offer.
+fn validate_confirm_in_blocks(
+    confirm_in_blocks: u32,
+    confirm_in_blocks_range: &RangeInclusive<u32>,
+) -> Result<()> {
+    if !confirm_in_blocks_range.contains(&confirm_in_blocks) {
+        let min_confirm_in_blocks = *confirm_in_blocks_range.start();
+        let max_confirm_in_blocks = *confirm_in_blocks_range.end();
+        return Err(runtime_error(
+            WalletRuntimeErrorCode::InvalidConfirmationTarget {
+                min_confirm_in_blocks,
+                max_confirm_in_blocks,
+            },
+            format!(
+                "Invalid block confirmation target. Please use a target in the range [{min_confirm_in_blocks}; {max_confirm_in_blocks}]"
+            ),
         ));
     }
+    Ok(())
+}
+
+fn get_fee_histogram(
+    blockchain: &AnyBlockchain,
+) -> std::result::Result<Option<serde_json::Value>, Error> {
+    match blockchain {
+        AnyBlockchain::Electrum(electrum) => Ok(Some(
+            electrum.raw_call("mempool.get_fee_histogram", vec![])?,
+        )),
+        AnyBlockchain::Esplora(_) => Ok(None),
+    }
+}
+
+// Electrum servers respond with pairs of a fee rate and the vsize of the mempool txs paying between
+// that fee rate and the next higher one. The number of blocks needed to confirm a tx paying a fee
+// rate is estimated by filling blocks with the txs paying more, ignoring txs arriving meanwhile.
+fn parse_fee_histogram(histogram: &serde_json::Value) -> Option<Vec<FeeHistogramEntry>> {
+    let mut cumulative_vsize = 0;
+    let mut entries = Vec::new();
+    for entry in histogram.as_array()? {
+        let fee_rate = entry.get(0)?.as_f64()?;
+        let vsize = entry.get(1)?.as_u64()?;
+        cumulative_vsize += vsize;
+        entries.push(FeeHistogramEntry {
+            fee_rate_sat_per_vbyte: fee_rate as f32,
+            vsize,
+            cumulative_vsize,
+            estimated_blocks: cumulative_vsize.div_ceil(MAX_BLOCK_VSIZE).max(1) as u32,
+        });
+    }
+    Some(entries)
+}
+
+// Unconfirmed txs have just been sent or are about to be confirmed, so they count as sent now.
+fn get_sent_at(tx: &TransactionDetails, now: SystemTime) -> SystemTime {
+    tx.confirmation_time
+        .as_ref()
+        .map(|block_time| SystemTime::UNIX_EPOCH + Duration::from_secs(block_time.timestamp))
+        .unwrap_or(now)
+}
+
+// Only txs spending funds of the wallet are payments, incoming txs paying the amount to one of
+// our addresses aren't.
+fn is_recent_payment_to(
+    tx: &TransactionDetails,
+    script_pubkey: &Script,
+    amount_sat: u64,
+    within: Duration,
+    now: SystemTime,
+) -> bool {
+    tx.sent > 0
+        && now.duration_since(get_sent_at(tx, now)).unwrap_or_default() <= within
+        && tx
+            .transaction
+            .iter()
+            .flat_map(|raw_tx| raw_tx.output.iter())
+            .any(|output| &output.script_pubkey == script_pubkey && output.value == amount_sat)
+}
+
+fn get_amount_paid_to(tx: &TransactionDetails, script_pubkey: &Script) -> u64 {
+    tx.transaction
+        .iter()
+        .flat_map(|raw_tx| raw_tx.output.iter())
+        .filter(|output| &output.script_pubkey == script_pubkey)
+        .map(|output| output.value)
+        .sum()
+}
+
+// Accepts WIF and hex encoded keys. Hex encoded keys are assumed to be compressed, like the keys of
+// all but the oldest wallets.
+fn parse_private_key(private_key: &str, network: Network) -> Result<PrivateKey> {
+    let private_key = private_key.trim();
+    if let Ok(key) = PrivateKey::from_wif(private_key) {
+        if (key.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+            return Err(invalid_input(
+                "Invalid private key: key is for a different network",
+            ));
+        }
+        return Ok(key);
+    }
+    let bytes = Vec::<u8>::from_hex(private_key)
+        .map_to_invalid_input("Invalid private key: expected WIF or 32 hex encoded bytes")?;
+    PrivateKey::from_slice(&bytes, network)
+        .map_to_invalid_input("Invalid private key: expected WIF or 32 hex encoded bytes")
+}
+
+// Uncompressed keys can't be used in segwit outputs.
+fn get_sweep_descriptors(private_key: &PrivateKey) -> Vec<String> {
+    let wif = private_key.to_wif();
+    if private_key.compressed {
+        vec![
+            format!("wpkh({wif})"),
+            format!("sh(wpkh({wif}))"),
+            format!("pkh({wif})"),
+        ]
+    } else {
+        vec![format!("pkh({wif})")]
+    }
+}
+
+fn get_psbt_input_value(psbt_input: &PsbtInput, outpoint: &OutPoint) -> Result<u64> {
+    if let Some(witness_utxo) = &psbt_input.witness_utxo {
+        return Ok(witness_utxo.value);
+    }
+    psbt_input
+        .non_witness_utxo
+        .as_ref()
+        .and_then(|tx| tx.output.get(outpoint.vout as usize))
+        .map(|output| output.value)
+        .ok_or_else(|| invalid_input("Invalid tx blob: input is missing UTXO information"))
+}
+
+fn get_psbt_fee(psbt: &Psbt) -> Result<u64> {
+    let input_sat = psbt.inputs.iter().zip(&psbt.unsigned_tx.input).try_fold(
+        0,
+        |sum, (psbt_input, input)| {
+            checked_add_sat(
+                sum,
+                get_psbt_input_value(psbt_input, &input.previous_output)?,
+            )
+        },
+    )?;
+    let output_sat = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .try_fold(0, |sum, output| checked_add_sat(sum, output.value))?;
+    input_sat
+        .checked_sub(output_sat)
+        .ok_or_else(|| invalid_input("Invalid tx blob: the outputs exceed the inputs"))
+}
+
+fn count_script_pubkeys<D: BatchDatabase>(
+    wallet: &bdk::Wallet<D>,
+) -> std::result::Result<u32, Error> {
+    Ok(wallet.database().iter_script_pubkeys(None)?.len() as u32)
+}
+
+// The legacy database that was synced last is copied, unless the database was synced already.
+fn migrate_legacy_databases(
+    database: &mut AnyDatabase,
+    legacy_databases: &[AnyDatabase],
+) -> Result<()> {
+    let get_synced_height = |database: &AnyDatabase| {
+        database
+            .get_sync_time()
+            .map(|sync_time| sync_time.map(|sync_time| sync_time.block_time.height))
+            .map_to_permanent_failure("Failed to get sync time")
+    };
+    if get_synced_height(database)?.is_some() {
+        return Ok(());
+    }
+    let mut most_recent = None;
+    for legacy_database in legacy_databases {
+        let height = get_synced_height(legacy_database)?;
+        if height.is_some() && most_recent.map_or(true, |(_, best_height)| height > best_height) {
+            most_recent = Some((legacy_database, height));
+        }
+    }
+    if let Some((legacy_database, _)) = most_recent {
+        let snapshot = take_snapshot(legacy_database)
+            .map_to_permanent_failure("Failed to read legacy wallet database")?;
+        apply_snapshot(&snapshot, database)
+            .map_to_permanent_failure("Failed to migrate legacy wallet database")?;
+    }
+    Ok(())
+}
+
+// `bdk::Wallet` only provides shared access to its database, so batches are committed through a
+// second handle to the same database.
+fn get_write_handle(database: &AnyDatabase) -> Result<AnyDatabase> {
+    match database {
+        AnyDatabase::Sled(tree) => Ok(AnyDatabase::Sled(tree.clone())),
+        AnyDatabase::Sqlite(sqlite) => Ok(AnyDatabase::Sqlite(SqliteDatabase::new(
+            sqlite.path.clone(),
+        ))),
+        AnyDatabase::Memory(_) => Err(permanent_failure("Unexpected in-memory wallet database")),
+    }
+}
 
-    Ok(descriptor.replacen("0/*)", "1/*)", 1))
+fn get_dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            get_dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
 }
 
 // Waiting for Iterator::try_collect() to become stable.
@@ -532,29 +3436,55 @@ fn try_collect<T, I: std::iter::IntoIterator<Item = Result<T>>>(iter: I) -> Resu
 
 #[cfg(test)]
 mod tests {
-    use crate::wallet::get_change_descriptor_from_descriptor;
-    use crate::{Config, Wallet};
-    use bdk::bitcoin::{Address, AddressType, Network};
+    use super::{
+        get_confirm_in_blocks_range, get_primary_output_address, get_sweep_descriptors,
+        is_consolidation_worthwhile, is_recent_payment_to, map_to_not_enough_funds,
+        parse_fee_histogram, parse_private_key, to_detailed_balance, validate_confirm_in_blocks,
+    };
+    use crate::errors::FundsShortfall;
+    use crate::{
+        BlockchainBackend, Config, DatabaseBackend, DecodedTxOutput, FeeHistogramEntry, Keychain,
+        Wallet, WalletRuntimeErrorCode,
+    };
+    use bdk::bitcoin::blockdata::opcodes::all::OP_RETURN;
+    use bdk::bitcoin::blockdata::script::Builder;
+    use bdk::bitcoin::{
+        Address, AddressType, Network, OutPoint, PackedLockTime, Script, Transaction, TxOut, Txid,
+    };
+    use bdk::database::{AnyDatabase, MemoryDatabase};
+    use bdk::{BlockTime, Error, KeychainKind, LocalUtxo, TransactionDetails};
     use std::fs::remove_dir_all;
     use std::str::FromStr;
-
-    const MAINNET_WATCH_DESCRIPTOR: &str = "wpkh([ddd71d79/84'/0'/0']xpub6Cg6Y9ynKKSjZ1EwscvwerJMU1PPPcdhjr2tQ783zE31NUfAF1EMY4qiEBfKkExF3eBruUiSpGZLeCaFiJZSeh3HzAjNANx3TT8QxdN8GUd/0/*)";
-    const MAINNET_WATCH_DESCRIPTOR_CHANGE: &str = "wpkh([ddd71d79/84'/0'/0']xpub6Cg6Y9ynKKSjZ1EwscvwerJMU1PPPcdhjr2tQ783zE31NUfAF1EMY4qiEBfKkExF3eBruUiSpGZLeCaFiJZSeh3HzAjNANx3TT8QxdN8GUd/1/*)";
+    use std::time::{Duration, SystemTime};
 
     const TESTNET_WATCH_DESCRIPTOR: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/0/*)";
-    const TESTNET_WATCH_DESCRIPTOR_CHANGE: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH8TeaK924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/1/*)";
+
+    const TESTNET_TAPROOT_WATCH_DESCRIPTOR: &str = "tr([aed2a027/86'/1'/0']tpubDDt26we3uvM1pEW3ztPbRYEzerwF9LLgaghX1BaUXuKsqvPFBrDisaQBvsEAbNmPgi33H2UdFVjiuFmze4uFZgQQx8X5wYF7bhK7SisDn6Q/0/*)";
+
+    fn test_config(wallet_db_path: &str) -> Config {
+        Config {
+            blockchain_backend: BlockchainBackend::Electrum {
+                url: "ssl://electrum.blockstream.info:60002".to_string(),
+            },
+            wallet_db_path: wallet_db_path.to_string(),
+            network: Network::Testnet,
+            watch_descriptor: TESTNET_WATCH_DESCRIPTOR.to_string(),
+            stop_gap: None,
+            database: DatabaseBackend::Sled,
+            socks5_proxy: None,
+            timeout_secs: None,
+            electrum_retries: None,
+            broadcast_fallback_url: None,
+            min_confirm_in_blocks: None,
+            max_confirm_in_blocks: None,
+        }
+    }
 
     #[test]
     fn test_get_addr() {
         let _ = remove_dir_all(".bdk-database-get-addr");
 
-        let wallet = Wallet::new(Config {
-            electrum_url: "ssl://electrum.blockstream.info:60002".to_string(),
-            wallet_db_path: ".bdk-database-get-addr".to_string(),
-            network: Network::Testnet,
-            watch_descriptor: TESTNET_WATCH_DESCRIPTOR.to_string(),
-        })
-        .unwrap();
+        let wallet = Wallet::new(test_config(".bdk-database-get-addr")).unwrap();
 
         let addr = wallet.get_addr().unwrap();
         assert_eq!(Address::from_str(&addr).unwrap().network, Network::Testnet);
@@ -568,28 +3498,525 @@ mod tests {
         assert_ne!(addr, addr_2);
     }
 
-    const INVALID_WATCH_DESCRIPTOR: &str = "wpkh([aed2a027/84'/1'/0']tpubDCvyR4gGk5U6r1Q1HMQtgZYMD3a9bVyt7Tv9BWgcBCQsff4aqR7arUGPTMaUbVwaH/0/*)K924GJr9nHyGPBtqSCD8BCjMnJb1qZFjK4ACfL/0/*)";
+    #[test]
+    fn test_create_payment_request() {
+        let _ = remove_dir_all(".bdk-database-create-payment-request");
+
+        let wallet = Wallet::new(test_config(".bdk-database-create-payment-request")).unwrap();
+
+        let request = wallet
+            .create_payment_request(Some(50_000), Some("Lipa Shop".to_string()), None)
+            .unwrap();
+        assert_eq!(
+            request.bip21_uri,
+            format!(
+                "bitcoin:{}?amount=0.0005&label=Lipa%20Shop",
+                request.address
+            )
+        );
+
+        let request_2 = wallet.create_payment_request(None, None, None).unwrap();
+        assert_ne!(request.address, request_2.address);
+        assert_eq!(
+            request_2.bip21_uri,
+            format!("bitcoin:{}", request_2.address)
+        );
+
+        assert!(wallet.create_payment_request(Some(0), None, None).is_err());
+    }
+
+    #[test]
+    fn test_address_ownership() {
+        let _ = remove_dir_all(".bdk-database-address-ownership");
+
+        let wallet = Wallet::new(test_config(".bdk-database-address-ownership")).unwrap();
+
+        let own_address = wallet.get_addr().unwrap();
+        let foreign_address = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string();
+        let mainnet_address = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4".to_string();
+
+        assert!(wallet.is_address_mine(own_address.clone()).unwrap());
+        assert!(!wallet.is_address_mine(foreign_address.clone()).unwrap());
+        assert!(wallet.is_address_mine(mainnet_address.clone()).is_err());
+
+        let info = wallet.get_address_info(own_address.clone()).unwrap();
+        assert_eq!(info.address, own_address);
+        assert_eq!(info.keychain, Keychain::External);
+        assert_eq!(info.derivation_index, 0);
+        assert_eq!(info.times_used, 0);
+        assert_eq!(info.received_sat, 0);
+
+        assert!(wallet.get_address_info(foreign_address).is_err());
+        assert!(wallet.get_address_info(mainnet_address).is_err());
+    }
+
+    #[test]
+    fn test_list_addresses() {
+        let _ = remove_dir_all(".bdk-database-list-addresses");
+
+        let wallet = Wallet::new(test_config(".bdk-database-list-addresses")).unwrap();
+
+        assert!(wallet
+            .list_addresses(Keychain::External, true)
+            .unwrap()
+            .is_empty());
+
+        let addr = wallet.get_addr().unwrap();
+        let addr_2 = wallet.get_addr().unwrap();
+
+        let addresses = wallet.list_addresses(Keychain::External, true).unwrap();
+        let listed: Vec<(String, u32)> = addresses
+            .iter()
+            .map(|a| (a.address.clone(), a.derivation_index))
+            .collect();
+        assert_eq!(listed, vec![(addr, 0), (addr_2, 1)]);
+        assert!(addresses.iter().all(|a| a.times_used == 0));
+
+        assert_eq!(
+            wallet
+                .list_addresses(Keychain::External, false)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(wallet
+            .list_addresses(Keychain::Internal, true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_get_max_sendable_of_empty_wallet() {
+        let _ = remove_dir_all(".bdk-database-max-sendable");
+
+        let wallet = Wallet::new(test_config(".bdk-database-max-sendable")).unwrap();
+
+        assert_eq!(wallet.get_max_sendable(6).unwrap(), 0);
+        assert!(wallet.get_max_sendable(0).is_err());
+        assert!(wallet.get_max_sendable(26).is_err());
+        assert_eq!(wallet.get_dust_limit(), 546);
+    }
+
+    #[test]
+    fn test_map_to_not_enough_funds() {
+        let error = map_to_not_enough_funds(
+            Error::InsufficientFunds {
+                needed: 10_500,
+                available: 8_000,
+            },
+            10_000,
+        );
+        assert!(matches!(
+            error,
+            perro::Error::RuntimeError {
+                code: WalletRuntimeErrorCode::NotEnoughFunds {
+                    shortfall: Some(FundsShortfall {
+                        required_sat: 10_500,
+                        available_sat: 8_000,
+                        fee_sat: 500,
+                        missing_sat: 2_500,
+                    })
+                },
+                ..
+            }
+        ));
+
+        let error = map_to_not_enough_funds(Error::NoRecipients, 10_000);
+        assert!(matches!(
+            error,
+            perro::Error::RuntimeError {
+                code: WalletRuntimeErrorCode::NotEnoughFunds { shortfall: None },
+                ..
+            }
+        ));
+    }
+
+    fn utxo(txid: Txid, value: u64, keychain: KeychainKind) -> LocalUtxo {
+        LocalUtxo {
+            outpoint: OutPoint { txid, vout: 0 },
+            txout: TxOut {
+                value,
+                script_pubkey: Script::new(),
+            },
+            keychain,
+            is_spent: false,
+        }
+    }
+
+    fn tx(txid: Txid, sent: u64, confirmation_time: Option<BlockTime>) -> TransactionDetails {
+        TransactionDetails {
+            transaction: None,
+            txid,
+            received: 0,
+            sent,
+            fee: None,
+            confirmation_time,
+        }
+    }
 
     #[test]
-    fn test_get_change_descriptor_from_descriptor() {
+    fn test_parse_private_key() {
+        const TESTNET_WIF: &str = "cMceqPhHedrhbcR9eXgzmfWy7kRqLyAxMYwFT6ABDWsiwUp9Nsq9";
+        const UNCOMPRESSED_MAINNET_WIF: &str =
+            "5HpjE2Hs7vjU4SN3YyPQCdhzCu92WoEeuE6PWNuiPyTu3ESGnzn";
+        let hex_key = "01".repeat(32);
+
+        let key = parse_private_key(TESTNET_WIF, Network::Testnet).unwrap();
+        assert_eq!(key, parse_private_key(&hex_key, Network::Testnet).unwrap());
         assert_eq!(
-            MAINNET_WATCH_DESCRIPTOR_CHANGE,
-            get_change_descriptor_from_descriptor(MAINNET_WATCH_DESCRIPTOR).unwrap()
+            key,
+            parse_private_key(TESTNET_WIF, Network::Signet).unwrap()
         );
+        assert_eq!(get_sweep_descriptors(&key).len(), 3);
 
+        let key = parse_private_key(UNCOMPRESSED_MAINNET_WIF, Network::Bitcoin).unwrap();
         assert_eq!(
-            TESTNET_WATCH_DESCRIPTOR_CHANGE,
-            get_change_descriptor_from_descriptor(TESTNET_WATCH_DESCRIPTOR).unwrap()
+            get_sweep_descriptors(&key),
+            vec![format!("pkh({UNCOMPRESSED_MAINNET_WIF})")]
         );
 
-        let result = get_change_descriptor_from_descriptor(MAINNET_WATCH_DESCRIPTOR_CHANGE);
-        assert!(result.is_err());
-        assert!(result.err().unwrap().to_string().contains("Invalid descriptor: Descriptor doesn't end with \"0/*)\". Could it already be a change descriptor?"));
+        assert!(parse_private_key(TESTNET_WIF, Network::Bitcoin).is_err());
+        assert!(parse_private_key(UNCOMPRESSED_MAINNET_WIF, Network::Testnet).is_err());
+        for invalid in ["", "not a key", "0101", &"00".repeat(32)] {
+            assert!(parse_private_key(invalid, Network::Testnet).is_err());
+        }
+    }
+
+    #[test]
+    fn test_find_foreign_output_skips_op_return() {
+        let wallet = bdk::Wallet::new(
+            TESTNET_WATCH_DESCRIPTOR,
+            None,
+            Network::Testnet,
+            AnyDatabase::Memory(MemoryDatabase::new()),
+        )
+        .unwrap();
+        let foreign_script = Address::from_str("tb1q4rmfylm7rt5sjsgcqpmclmtqn5ynhrt0yqnxnw")
+            .unwrap()
+            .script_pubkey();
+        let memo_script = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_slice(b"order 1234")
+            .into_script();
+        let output = |script_pubkey: &Script| TxOut {
+            value: 0,
+            script_pubkey: script_pubkey.clone(),
+        };
+
+        let outputs = vec![output(&memo_script), output(&foreign_script)];
+        assert_eq!(
+            Wallet::find_foreign_output(&outputs, &wallet).unwrap(),
+            Some(foreign_script)
+        );
+        let outputs = vec![output(&memo_script)];
+        assert_eq!(
+            Wallet::find_foreign_output(&outputs, &wallet).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_to_unsynced_tx_details_overflow() {
+        let wallet = bdk::Wallet::new(
+            TESTNET_WATCH_DESCRIPTOR,
+            None,
+            Network::Testnet,
+            AnyDatabase::Memory(MemoryDatabase::new()),
+        )
+        .unwrap();
+        let script_pubkey = Address::from_str("tb1q4rmfylm7rt5sjsgcqpmclmtqn5ynhrt0yqnxnw")
+            .unwrap()
+            .script_pubkey();
+        let output = |value| TxOut {
+            value,
+            script_pubkey: script_pubkey.clone(),
+        };
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: Vec::new(),
+            output: vec![output(u64::MAX), output(1)],
+        };
+
+        let result = Wallet::to_unsynced_tx_details(tx, &wallet);
+        assert!(matches!(result, Err(perro::Error::InvalidInput { .. })));
+    }
+
+    #[test]
+    fn test_get_confirm_in_blocks_range() {
+        assert_eq!(get_confirm_in_blocks_range(None, None).unwrap(), 1..=25);
+        assert_eq!(
+            get_confirm_in_blocks_range(Some(2), Some(144)).unwrap(),
+            2..=144
+        );
+        assert_eq!(
+            get_confirm_in_blocks_range(Some(6), Some(6)).unwrap(),
+            6..=6
+        );
+        for (min, max) in [(Some(0), None), (Some(26), None), (None, Some(1_009))] {
+            assert!(get_confirm_in_blocks_range(min, max).is_err());
+        }
+
+        let range = get_confirm_in_blocks_range(Some(2), Some(6)).unwrap();
+        assert!(validate_confirm_in_blocks(2, &range).is_ok());
+        assert!(validate_confirm_in_blocks(6, &range).is_ok());
+        assert!(matches!(
+            validate_confirm_in_blocks(7, &range),
+            Err(perro::Error::RuntimeError {
+                code: WalletRuntimeErrorCode::InvalidConfirmationTarget {
+                    min_confirm_in_blocks: 2,
+                    max_confirm_in_blocks: 6,
+                },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_fee_histogram() {
+        let histogram = serde_json::json!([[53.2, 102_030], [20.0, 900_000], [1.0, 1_500_000]]);
+        let entries = parse_fee_histogram(&histogram).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0],
+            FeeHistogramEntry {
+                fee_rate_sat_per_vbyte: 53.2,
+                vsize: 102_030,
+                cumulative_vsize: 102_030,
+                estimated_blocks: 1,
+            }
+        );
+        assert_eq!(entries[1].cumulative_vsize, 1_002_030);
+        assert_eq!(entries[1].estimated_blocks, 2);
+        assert_eq!(entries[2].cumulative_vsize, 2_502_030);
+        assert_eq!(entries[2].estimated_blocks, 3);
+
+        assert_eq!(
+            parse_fee_histogram(&serde_json::json!([])).unwrap(),
+            Vec::new()
+        );
+        assert!(parse_fee_histogram(&serde_json::json!({})).is_none());
+        assert!(parse_fee_histogram(&serde_json::json!([[1.0]])).is_none());
+        assert!(parse_fee_histogram(&serde_json::json!([["1.0", 100]])).is_none());
+    }
+
+    #[test]
+    fn test_is_consolidation_worthwhile() {
+        assert!(is_consolidation_worthwhile(20, 1.0));
+        assert!(is_consolidation_worthwhile(300, 5.0));
+        assert!(!is_consolidation_worthwhile(19, 1.0));
+        assert!(!is_consolidation_worthwhile(300, 5.1));
+    }
+
+    #[test]
+    fn test_get_primary_output_address() {
+        let output = |address: Option<&str>, is_mine: bool| DecodedTxOutput {
+            address: address.map(String::from),
+            value_sat: 1_000,
+            is_mine,
+        };
+
+        // Batch send with change and a memo.
+        let outputs = vec![
+            output(None, false),
+            output(Some("change"), true),
+            output(Some("recipient-1"), false),
+            output(Some("recipient-2"), false),
+        ];
+        assert_eq!(
+            get_primary_output_address(&outputs),
+            Some("recipient-1".to_string())
+        );
+
+        // Consolidation.
+        let outputs = vec![output(None, false), output(Some("own"), true)];
+        assert_eq!(
+            get_primary_output_address(&outputs),
+            Some("own".to_string())
+        );
+
+        assert_eq!(get_primary_output_address(&[output(None, false)]), None);
+    }
+
+    #[test]
+    fn test_is_recent_payment_to() {
+        let supplier_script = Address::from_str("tb1q4rmfylm7rt5sjsgcqpmclmtqn5ynhrt0yqnxnw")
+            .unwrap()
+            .script_pubkey();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_690_000_000);
+        let hour = Duration::from_secs(60 * 60);
+        let payment = |sent: u64, hours_ago: Option<u64>| TransactionDetails {
+            transaction: Some(Transaction {
+                version: 2,
+                lock_time: PackedLockTime::ZERO,
+                input: Vec::new(),
+                output: vec![TxOut {
+                    value: 50_000,
+                    script_pubkey: supplier_script.clone(),
+                }],
+            }),
+            confirmation_time: hours_ago.map(|hours_ago| BlockTime {
+                height: 100,
+                timestamp: 1_690_000_000 - hours_ago * 60 * 60,
+            }),
+            ..tx(Txid::from_str(&"1".repeat(64)).unwrap(), sent, None)
+        };
+
+        let within = 24 * hour;
+        for (tx, expected) in [
+            (payment(50_141, Some(2)), true),
+            (payment(50_141, None), true),
+            (payment(50_141, Some(25)), false),
+            // Received from the address instead of paid to it.
+            (payment(0, Some(2)), false),
+        ] {
+            assert_eq!(
+                is_recent_payment_to(&tx, &supplier_script, 50_000, within, now),
+                expected
+            );
+        }
+        let tx = payment(50_141, Some(2));
+        assert!(!is_recent_payment_to(
+            &tx,
+            &supplier_script,
+            40_000,
+            within,
+            now
+        ));
+        assert!(!is_recent_payment_to(
+            &tx,
+            &Script::new(),
+            50_000,
+            within,
+            now
+        ));
+    }
+
+    #[test]
+    fn test_to_detailed_balance() {
+        let confirmed_txid = Txid::from_str(&"1".repeat(64)).unwrap();
+        let incoming_txid = Txid::from_str(&"2".repeat(64)).unwrap();
+        let outgoing_txid = Txid::from_str(&"3".repeat(64)).unwrap();
+        let block_time = BlockTime {
+            height: 100,
+            timestamp: 1_690_000_000,
+        };
+
+        let balance = to_detailed_balance(
+            &[
+                utxo(confirmed_txid, 1_000, KeychainKind::External),
+                utxo(incoming_txid, 200, KeychainKind::External),
+                utxo(outgoing_txid, 30, KeychainKind::Internal),
+            ],
+            &[
+                tx(confirmed_txid, 0, Some(block_time)),
+                tx(incoming_txid, 0, None),
+                tx(outgoing_txid, 5_000, None),
+            ],
+        );
+        assert_eq!(balance.external.sat, 1_200);
+        assert_eq!(balance.internal.sat, 30);
+        assert_eq!(balance.confirmed.sat, 1_000);
+        assert_eq!(balance.unconfirmed_incoming.sat, 200);
+        assert_eq!(balance.unconfirmed_self_transfers.sat, 30);
+
+        let balance = to_detailed_balance(&[], &[]);
+        assert_eq!(balance.external.sat, 0);
+        assert_eq!(balance.unconfirmed_self_transfers.sat, 0);
+    }
+
+    #[test]
+    fn test_get_diagnostics() {
+        let _ = remove_dir_all(".bdk-database-diagnostics");
+
+        let wallet = Wallet::new(test_config(".bdk-database-diagnostics")).unwrap();
+
+        let diagnostics = wallet.get_diagnostics().unwrap();
+        assert!(diagnostics.backend_reachable);
+        assert!(diagnostics.backend_latency_ms.is_some());
+        assert!(diagnostics.backend_tip_height.unwrap() > 2_400_000);
+        assert_eq!(diagnostics.synced_tip_height, 0);
+        assert_eq!(diagnostics.last_synced_at, None);
+        assert!(diagnostics.db_size_bytes > 0);
+        assert_eq!(diagnostics.number_of_utxos, 0);
+        assert_eq!(diagnostics.number_of_unconfirmed_txs, 0);
+    }
+
+    #[test]
+    fn test_invalid_stop_gap() {
+        for stop_gap in [0, 1_001] {
+            let result = Wallet::new(Config {
+                stop_gap: Some(stop_gap),
+                ..test_config(".bdk-database-invalid-stop-gap")
+            });
+            assert!(result.is_err());
+            assert!(result
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("Invalid stop gap"));
+        }
+    }
+
+    #[test]
+    fn test_invalid_timeout() {
+        for (timeout_secs, socks5_proxy) in [(0, None), (10, Some("127.0.0.1:9050".to_string()))] {
+            let result = Wallet::new(Config {
+                socks5_proxy,
+                timeout_secs: Some(timeout_secs),
+                ..test_config(".bdk-database-invalid-timeout")
+            });
+            assert!(result.is_err());
+            assert!(result.err().unwrap().to_string().contains("timeout"));
+        }
+    }
 
-        let result = get_change_descriptor_from_descriptor(INVALID_WATCH_DESCRIPTOR);
+    #[test]
+    fn test_empty_electrum_pool() {
+        let result = Wallet::new(Config {
+            blockchain_backend: BlockchainBackend::ElectrumPool { urls: Vec::new() },
+            ..test_config(".bdk-database-empty-electrum-pool")
+        });
         assert!(result.is_err());
-        assert!(result.err().unwrap().to_string().contains(
-            "Invalid descriptor: Descriptor has multiple occurrences of substring \"0/*)\""
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("At least one electrum url is required"));
+    }
+
+    #[test]
+    fn test_database_locked() {
+        let _ = remove_dir_all(".bdk-database-locked");
+        let config = || test_config(".bdk-database-locked");
+
+        let _wallet = Wallet::new(config()).unwrap();
+        let result = Wallet::new(config());
+
+        assert!(matches!(
+            result,
+            Err(perro::Error::RuntimeError {
+                code: WalletRuntimeErrorCode::DatabaseLocked,
+                ..
+            })
         ));
     }
+
+    #[test]
+    fn test_get_taproot_addr() {
+        let _ = remove_dir_all(".bdk-database-get-taproot-addr");
+
+        let wallet = Wallet::new(Config {
+            watch_descriptor: TESTNET_TAPROOT_WATCH_DESCRIPTOR.to_string(),
+            ..test_config(".bdk-database-get-taproot-addr")
+        })
+        .unwrap();
+
+        let addr = wallet.get_addr().unwrap();
+        assert_eq!(Address::from_str(&addr).unwrap().network, Network::Testnet);
+        assert_eq!(
+            Address::from_str(&addr).unwrap().address_type().unwrap(),
+            AddressType::P2tr
+        );
+    }
 }
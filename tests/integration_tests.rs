@@ -1,15 +1,19 @@
 mod setup;
 
-use uniffi_lipabusinesslib::{Config, Wallet, WalletError, WalletRuntimeErrorCode};
+use uniffi_lipabusinesslib::{
+    BlockchainBackend, Config, DatabaseBackend, Keychain, Recipient, SyncCallback, SyncPhase,
+    SyncProgressCallback, Tx, TxStatus, Wallet, WalletError, WalletRuntimeErrorCode,
+};
 
 use bdk::bitcoin::consensus::deserialize;
 use bdk::bitcoin::psbt::Psbt;
-use bdk::bitcoin::{Address, Network, Txid};
+use bdk::bitcoin::{Address, Network, OutPoint, Txid};
 use std::fs::remove_dir_all;
 use std::net::TcpListener;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread::{sleep, spawn};
 use std::time::{Duration, Instant};
 
@@ -39,10 +43,20 @@ fn test_sync() {
     }
 
     let wallet = Wallet::new(Config {
-        electrum_url: "ssl://localhost:8888".to_string(),
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://localhost:8888".to_string(),
+        },
         wallet_db_path: ".bdk-database-sync".to_string(),
         network: Network::Testnet,
         watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
     })
     .unwrap();
     let wallet = Arc::new(wallet);
@@ -66,22 +80,538 @@ fn test_sync() {
     assert!(start.elapsed() < Duration::from_secs(2));
 }
 
+struct ChannelSyncCallback {
+    sender: Mutex<Sender<Result<(), String>>>,
+}
+
+impl SyncCallback for ChannelSyncCallback {
+    fn on_sync_finished(&self) {
+        self.sender.lock().unwrap().send(Ok(())).unwrap();
+    }
+
+    fn on_sync_failed(&self, msg: String) {
+        self.sender.lock().unwrap().send(Err(msg)).unwrap();
+    }
+}
+
+#[test]
+fn test_sync_async() {
+    let _ = remove_dir_all(".bdk-database-sync-async");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-sync-async".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+    let wallet = Arc::new(wallet);
+
+    let (sender, receiver) = channel();
+    let callback = Box::new(ChannelSyncCallback {
+        sender: Mutex::new(sender),
+    });
+
+    let start = Instant::now();
+    Arc::clone(&wallet).sync_async(callback);
+    // sync_async() returns immediately.
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    receiver
+        .recv_timeout(Duration::from_secs(60))
+        .unwrap()
+        .unwrap();
+    let balance = wallet.get_balance().unwrap();
+    assert_eq!(balance.confirmed.sat, 88009);
+}
+
+struct RecordingProgressCallback {
+    phases: Arc<Mutex<Vec<SyncPhase>>>,
+}
+
+impl SyncProgressCallback for RecordingProgressCallback {
+    fn on_progress(&self, phase: SyncPhase) {
+        self.phases.lock().unwrap().push(phase);
+    }
+}
+
+#[test]
+fn test_sync_with_progress() {
+    let _ = remove_dir_all(".bdk-database-sync-with-progress");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-sync-with-progress".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    let phases = Arc::new(Mutex::new(Vec::new()));
+    wallet
+        .sync_with_progress(Box::new(RecordingProgressCallback {
+            phases: Arc::clone(&phases),
+        }))
+        .unwrap();
+
+    let phases = phases.lock().unwrap();
+    assert_eq!(phases.len(), 4);
+    assert_eq!(phases[0], SyncPhase::Connecting);
+    assert_eq!(phases[1], SyncPhase::FetchingHeaders);
+    assert!(matches!(
+        phases[2],
+        SyncPhase::ScanningScriptPubkeys { index: 0, .. }
+    ));
+    match phases[3] {
+        SyncPhase::ScanningScriptPubkeys { index, total } => {
+            assert_eq!(index, total);
+            assert!(total > 0);
+        }
+        _ => panic!("Expected the scan to be reported as done"),
+    }
+    assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 88009);
+}
+
+struct AbortingProgressCallback {
+    wallet: Arc<Wallet>,
+    // Aborts once the scan of the script pubkeys started instead of while fetching the headers.
+    abort_while_scanning: bool,
+}
+
+impl SyncProgressCallback for AbortingProgressCallback {
+    fn on_progress(&self, phase: SyncPhase) {
+        let abort = match phase {
+            SyncPhase::FetchingHeaders => !self.abort_while_scanning,
+            SyncPhase::ScanningScriptPubkeys { index: 0, .. } => self.abort_while_scanning,
+            _ => false,
+        };
+        if abort {
+            self.wallet.abort_sync();
+        }
+    }
+}
+
+#[test]
+fn test_abort_sync() {
+    let _ = remove_dir_all(".bdk-database-abort-sync");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-abort-sync".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+    let wallet = Arc::new(wallet);
+
+    for abort_while_scanning in [false, true] {
+        let result = wallet.sync_with_progress(Box::new(AbortingProgressCallback {
+            wallet: Arc::clone(&wallet),
+            abort_while_scanning,
+        }));
+        assert!(matches!(
+            result,
+            Err(WalletError::RuntimeError {
+                code: WalletRuntimeErrorCode::SyncAborted,
+                ..
+            })
+        ));
+        assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 0);
+    }
+
+    // The abort doesn't affect the next sync.
+    wallet.sync().unwrap();
+    assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 88009);
+}
+
+#[test]
+fn test_new_wallet_offline() {
+    let _ = remove_dir_all(".bdk-database-offline");
+
+    // Nothing listens on the port, so connecting fails.
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "tcp://localhost:1".to_string(),
+        },
+        wallet_db_path: ".bdk-database-offline".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: Some(0),
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    assert!(wallet.get_addr().is_ok());
+    assert!(wallet.get_spending_txs().unwrap().is_empty());
+    assert!(matches!(
+        wallet.sync(),
+        Err(WalletError::RuntimeError {
+            code: WalletRuntimeErrorCode::ElectrumConnectionRefused,
+            ..
+        })
+    ));
+
+    let recipient = |amount_sat| Recipient {
+        address: TESTNET_ADDR.to_string(),
+        amount_sat,
+    };
+    let result =
+        wallet.prepare_batch_send_tx_with_fee_rate(vec![recipient(u64::MAX), recipient(1)], 1.0);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+}
+
+#[test]
+fn test_cached_data_before_first_sync() {
+    let _ = remove_dir_all(".bdk-database-cached");
+
+    let config = |url: &str, electrum_retries| Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: url.to_string(),
+        },
+        wallet_db_path: ".bdk-database-cached".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    };
+
+    let (balance, utxos, spending_tx_ids) = {
+        let wallet = Wallet::new(config("ssl://electrum.blockstream.info:60002", None)).unwrap();
+        let cached_balance = wallet.get_cached_balance().unwrap();
+        assert_eq!(cached_balance.balance.confirmed.sat, 0);
+        assert_eq!(cached_balance.last_synced_at, None);
+        assert!(!cached_balance.is_synced);
+
+        wallet.sync().unwrap();
+        let cached_balance = wallet.get_cached_balance().unwrap();
+        assert!(cached_balance.is_synced);
+        assert!(cached_balance.last_synced_at.is_some());
+        let spending_tx_ids: Vec<String> = wallet
+            .get_spending_txs()
+            .unwrap()
+            .into_iter()
+            .map(|tx| tx.id)
+            .collect();
+        (
+            wallet.get_balance().unwrap(),
+            wallet.list_utxos().unwrap(),
+            spending_tx_ids,
+        )
+    };
+
+    // Reopened with an unreachable backend, the wallet returns the data of the last sync.
+    let wallet = Wallet::new(config("tcp://localhost:1", Some(0))).unwrap();
+    let cached_balance = wallet.get_cached_balance().unwrap();
+    assert_eq!(cached_balance.balance.confirmed, balance.confirmed);
+    assert!(cached_balance.last_synced_at.is_some());
+    assert!(!cached_balance.is_synced);
+    let cached_spending_tx_ids: Vec<String> = wallet
+        .get_spending_txs()
+        .unwrap()
+        .into_iter()
+        .map(|tx| tx.id)
+        .collect();
+    assert_eq!(cached_spending_tx_ids, spending_tx_ids);
+    let txids: Vec<String> = utxos
+        .iter()
+        .map(|utxo| OutPoint::from_str(&utxo.outpoint).unwrap().txid.to_string())
+        .collect();
+    let statuses = wallet.get_tx_statuses(txids.clone()).unwrap();
+    for (utxo, txid) in utxos.into_iter().zip(txids) {
+        assert_eq!(wallet.get_tx_status(txid.clone()).unwrap(), utxo.status);
+        assert_eq!(statuses[&txid], utxo.status);
+    }
+    let result = wallet.get_tx_statuses(vec!["invalid".to_string()]);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+}
+
 #[test]
 fn test_get_balance_testnet_electrum() {
     let _ = remove_dir_all(".bdk-database-get-balance");
 
     let wallet = Wallet::new(Config {
-        electrum_url: "ssl://electrum.blockstream.info:60002".to_string(),
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
         wallet_db_path: ".bdk-database-get-balance".to_string(),
         network: Network::Testnet,
         watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+    let balance = wallet.get_balance().unwrap();
+
+    assert_eq!(balance.confirmed.sat, 88009);
+}
+
+#[test]
+fn test_get_balance_testnet_sqlite() {
+    let _ = remove_dir_all(".bdk-database-get-balance-sqlite");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-get-balance-sqlite".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sqlite,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+    assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 88009);
+
+    wallet.rescan(None).unwrap();
+    assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 88009);
+    assert!(!wallet.get_audit_log(None, None).unwrap().is_empty());
+}
+
+#[test]
+fn test_get_balance_testnet_electrum_pool() {
+    let _ = remove_dir_all(".bdk-database-get-balance-electrum-pool");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::ElectrumPool {
+            urls: vec![
+                // Nothing listens on the discard port, so the wallet has to fail over.
+                "tcp://127.0.0.1:9".to_string(),
+                "ssl://electrum.blockstream.info:60002".to_string(),
+            ],
+        },
+        wallet_db_path: ".bdk-database-get-balance-electrum-pool".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+    assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 88009);
+}
+
+#[test]
+fn test_rescan() {
+    let _ = remove_dir_all(".bdk-database-rescan");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-rescan".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+    assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 88009);
+
+    wallet.reset_db().unwrap();
+    assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 0);
+    assert!(wallet.list_utxos().unwrap().is_empty());
+
+    wallet.rescan(None).unwrap();
+    assert_eq!(wallet.get_balance().unwrap().confirmed.sat, 88009);
+}
+
+#[test]
+fn test_list_utxos() {
+    let _ = remove_dir_all(".bdk-database-list-utxos");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-list-utxos".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+    let utxos = wallet.list_utxos().unwrap();
+
+    assert!(!utxos.is_empty());
+    assert_eq!(utxos.iter().map(|u| u.value_sat).sum::<u64>(), 88009);
+    assert!(utxos
+        .iter()
+        .all(|u| matches!(u.status, TxStatus::Confirmed { .. })));
+}
+
+#[test]
+fn test_watch_for_incoming() {
+    let _ = remove_dir_all(".bdk-database-watch-for-incoming");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-watch-for-incoming".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    let result = wallet.watch_for_incoming(TESTNET_ADDR.to_string(), 0);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+
+    wallet.sync().unwrap();
+    let used_address = wallet
+        .list_addresses(Keychain::External, true)
+        .unwrap()
+        .into_iter()
+        .find(|address| address.times_used == 1 && address.received_sat > 0)
+        .unwrap();
+    // Txs that are already known aren't incoming.
+    assert!(wallet
+        .watch_for_incoming(used_address.address.clone(), 0)
+        .unwrap()
+        .is_none());
+
+    wallet.reset_db().unwrap();
+    let incoming_tx = wallet
+        .watch_for_incoming(used_address.address, 0)
+        .unwrap()
+        .unwrap();
+    assert_eq!(incoming_tx.amount.sat, used_address.received_sat);
+    assert!(matches!(incoming_tx.status, TxStatus::Confirmed { .. }));
+
+    let unused_address = wallet.get_addr().unwrap();
+    let start = Instant::now();
+    assert!(wallet
+        .watch_for_incoming(unused_address, 1)
+        .unwrap()
+        .is_none());
+    assert!(start.elapsed() >= Duration::from_secs(1));
+
+    let risk = wallet.assess_incoming_tx(incoming_tx.id.clone()).unwrap();
+    assert_eq!(risk.id, incoming_tx.id);
+    assert_eq!(risk.amount.sat, incoming_tx.amount.sat);
+    assert!(matches!(risk.status, TxStatus::Confirmed { .. }));
+    assert_eq!(risk.has_unconfirmed_parents, Some(false));
+
+    let unknown_txid =
+        "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a".to_string();
+    let result = wallet.assess_incoming_tx(unknown_txid);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+}
+
+#[test]
+fn test_get_balance_testnet_esplora() {
+    let _ = remove_dir_all(".bdk-database-get-balance-esplora");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Esplora {
+            url: "https://blockstream.info/testnet/api".to_string(),
+        },
+        wallet_db_path: ".bdk-database-get-balance-esplora".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
     })
     .unwrap();
 
     wallet.sync().unwrap();
     let balance = wallet.get_balance().unwrap();
 
-    assert_eq!(balance.confirmed, 88009);
+    assert_eq!(balance.confirmed.sat, 88009);
 }
 
 const TESTNET_ADDR: &str = "tb1q3ctet25lk00cmvrtkmu9dmah2kj077m4n4aqtm";
@@ -91,10 +621,20 @@ fn test_prepare_drain_tx() {
     let _ = remove_dir_all(".bdk-database-prepare-drain-tx");
 
     let wallet = Wallet::new(Config {
-        electrum_url: "ssl://electrum.blockstream.info:60002".to_string(),
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
         wallet_db_path: ".bdk-database-prepare-drain-tx".to_string(),
         network: Network::Testnet,
         watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
     })
     .unwrap();
 
@@ -115,7 +655,7 @@ fn test_prepare_drain_tx() {
         .prepare_drain_tx(TESTNET_ADDR.to_string(), 1)
         .unwrap();
 
-    assert_eq!(drain_tx.output_sat + drain_tx.on_chain_fee_sat, 88009);
+    assert_eq!(drain_tx.output.sat + drain_tx.on_chain_fee.sat, 88009);
 
     let psbt = deserialize::<Psbt>(&drain_tx.blob).unwrap();
 
@@ -127,12 +667,208 @@ fn test_prepare_drain_tx() {
     assert_eq!(psbt.unsigned_tx.output.len(), 1);
     assert_eq!(
         psbt.unsigned_tx.output.get(0).unwrap().value,
-        drain_tx.output_sat
+        drain_tx.output.sat
     );
     assert_eq!(
         psbt.unsigned_tx.output.get(0).unwrap().script_pubkey,
         Address::from_str(TESTNET_ADDR).unwrap().script_pubkey()
     );
+
+    let decoded_tx = wallet.decode_tx_blob(drain_tx.blob.clone()).unwrap();
+    assert_eq!(decoded_tx.id, drain_tx.id);
+    assert_eq!(decoded_tx.on_chain_fee_sat, drain_tx.on_chain_fee.sat);
+    assert_eq!(
+        decoded_tx.inputs.iter().map(|i| i.value_sat).sum::<u64>(),
+        88009
+    );
+    assert_eq!(decoded_tx.outputs.len(), 1);
+    let output = decoded_tx.outputs.first().unwrap();
+    assert_eq!(output.address, Some(TESTNET_ADDR.to_string()));
+    assert_eq!(output.value_sat, drain_tx.output.sat);
+    assert!(!output.is_mine);
+    assert!(decoded_tx.fee_rate_sat_per_vbyte > 0.0);
+    assert!(decoded_tx.is_rbf);
+
+    let result = wallet.decode_tx_blob(vec![1, 2, 3]);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+
+    let psbt_base64 = wallet.export_psbt_base64(drain_tx.blob.clone()).unwrap();
+    assert_eq!(Psbt::from_str(&psbt_base64).unwrap(), psbt);
+
+    // The PSBT has not been signed.
+    let result = wallet.import_signed_psbt_base64(psbt_base64);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+
+    let mismatched_tx = Tx {
+        id: "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a".to_string(),
+        blob: drain_tx.blob.clone(),
+        on_chain_fee: drain_tx.on_chain_fee.clone(),
+        output: drain_tx.output.clone(),
+    };
+    let result = wallet.save_tx_proposal(mismatched_tx, "Mismatched".to_string());
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+
+    let drain_txid = drain_tx.id.clone();
+    let drain_blob = drain_tx.blob.clone();
+    wallet
+        .save_tx_proposal(drain_tx, "Payout".to_string())
+        .unwrap();
+    let proposals = wallet.list_tx_proposals().unwrap();
+    assert_eq!(proposals.len(), 1);
+    assert_eq!(proposals[0].tx.id, drain_txid);
+    assert_eq!(proposals[0].tx.blob, drain_blob);
+    assert_eq!(proposals[0].memo, "Payout");
+
+    wallet.delete_tx_proposal(drain_txid.clone()).unwrap();
+    assert!(wallet.list_tx_proposals().unwrap().is_empty());
+    let result = wallet.delete_tx_proposal(drain_txid);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+}
+
+#[test]
+fn test_prepare_send_tx_with_utxos() {
+    let _ = remove_dir_all(".bdk-database-prepare-send-tx-with-utxos");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-prepare-send-tx-with-utxos".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+
+    let result = wallet.prepare_send_tx_with_utxos(TESTNET_ADDR.to_string(), 1000, Vec::new(), 1);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+
+    let unknown_utxo =
+        "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a:0".to_string();
+    let result =
+        wallet.prepare_send_tx_with_utxos(TESTNET_ADDR.to_string(), 1000, vec![unknown_utxo], 1);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+
+    let utxo = wallet.list_utxos().unwrap().remove(0);
+    let tx = wallet
+        .prepare_send_tx_with_utxos(
+            TESTNET_ADDR.to_string(),
+            1000,
+            vec![utxo.outpoint.clone()],
+            1,
+        )
+        .unwrap();
+
+    let psbt = deserialize::<Psbt>(&tx.blob).unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(
+        psbt.unsigned_tx.input.get(0).unwrap().previous_output,
+        OutPoint::from_str(&utxo.outpoint).unwrap()
+    );
+}
+
+#[test]
+fn test_prepare_tx_with_fee_rate() {
+    let _ = remove_dir_all(".bdk-database-prepare-tx-with-fee-rate");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-prepare-tx-with-fee-rate".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+
+    for invalid_fee_rate in [0.5, 1_001.0, f32::NAN] {
+        let result =
+            wallet.prepare_drain_tx_with_fee_rate(TESTNET_ADDR.to_string(), invalid_fee_rate);
+        assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+    }
+
+    let drain_tx = wallet
+        .prepare_drain_tx_with_fee_rate(TESTNET_ADDR.to_string(), 2.0)
+        .unwrap();
+    assert_eq!(drain_tx.output.sat + drain_tx.on_chain_fee.sat, 88009);
+    let decoded_tx = wallet.decode_tx_blob(drain_tx.blob).unwrap();
+    assert!((decoded_tx.fee_rate_sat_per_vbyte - 2.0).abs() < 0.1);
+
+    let send_tx = wallet
+        .prepare_batch_send_tx_with_fee_rate(
+            vec![Recipient {
+                address: TESTNET_ADDR.to_string(),
+                amount_sat: 1000,
+            }],
+            5.0,
+        )
+        .unwrap();
+    assert_eq!(send_tx.output.sat, 1000);
+    assert!(send_tx.on_chain_fee.sat > drain_tx.on_chain_fee.sat);
+}
+
+#[test]
+fn test_fee_estimation() {
+    let _ = remove_dir_all(".bdk-database-fee-estimation");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-fee-estimation".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITH_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+
+    assert!(wallet.estimate_fee_rate(1).unwrap() > 0.0);
+    let result = wallet.estimate_fee_rate(0);
+    assert!(matches!(
+        result,
+        Err(WalletError::RuntimeError {
+            code: WalletRuntimeErrorCode::InvalidConfirmationTarget {
+                min_confirm_in_blocks: 1,
+                max_confirm_in_blocks: 25,
+            },
+            ..
+        })
+    ));
+
+    let fee_estimate = wallet
+        .estimate_tx_fee(TESTNET_ADDR.to_string(), 1000, 1)
+        .unwrap();
+    assert!(fee_estimate.fee_sat > 0);
+    assert!(fee_estimate.tx_vsize > 0);
+    assert!(fee_estimate.fee_rate_sat_per_vbyte > 0.0);
 }
 
 #[test]
@@ -140,10 +876,20 @@ fn test_drain_empty_wallet() {
     let _ = remove_dir_all(".bdk-database-drain-empty-wallet");
 
     let wallet = Wallet::new(Config {
-        electrum_url: "ssl://electrum.blockstream.info:60002".to_string(),
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
         wallet_db_path: ".bdk-database-drain-empty-wallet".to_string(),
         network: Network::Testnet,
         watch_descriptor: WATCH_DESCRIPTOR_WITHOUT_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
     })
     .unwrap();
 
@@ -154,12 +900,44 @@ fn test_drain_empty_wallet() {
     assert!(matches!(
         drain_tx_result,
         Err(WalletError::RuntimeError {
-            code: WalletRuntimeErrorCode::NotEnoughFunds,
+            code: WalletRuntimeErrorCode::NotEnoughFunds { .. },
             ..
         })
     ));
 }
 
+#[test]
+fn test_fee_bump_unknown_tx() {
+    let _ = remove_dir_all(".bdk-database-fee-bump-unknown-tx");
+
+    let wallet = Wallet::new(Config {
+        blockchain_backend: BlockchainBackend::Electrum {
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+        },
+        wallet_db_path: ".bdk-database-fee-bump-unknown-tx".to_string(),
+        network: Network::Testnet,
+        watch_descriptor: WATCH_DESCRIPTOR_WITHOUT_FUNDS.to_string(),
+        stop_gap: None,
+        database: DatabaseBackend::Sled,
+        socks5_proxy: None,
+        timeout_secs: None,
+        electrum_retries: None,
+        broadcast_fallback_url: None,
+        min_confirm_in_blocks: None,
+        max_confirm_in_blocks: None,
+    })
+    .unwrap();
+
+    wallet.sync().unwrap();
+
+    let result = wallet.prepare_fee_bump_tx("invalid".to_string(), 1);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+
+    let unknown_txid = "3f6e6e0c3c1e5fd5b1b5a5a0c3d8d64b1a84b3a5b04c8b6d2f5b5f6e6c8d4f2a";
+    let result = wallet.prepare_fee_bump_tx(unknown_txid.to_string(), 1);
+    assert!(matches!(result, Err(WalletError::InvalidInput { .. })));
+}
+
 // Caution: Run these tests sequentially, otherwise they will corrupt each other,
 //      because they are manipulating their environment:
 //      cargo test --features nigiri -- --test-threads 1
@@ -167,14 +945,17 @@ fn test_drain_empty_wallet() {
 mod nigiri_tests {
     use crate::setup::nigiri;
     use bdk::bitcoin::consensus::deserialize;
+    use bdk::bitcoin::hashes::hex::FromHex;
     use bdk::bitcoin::psbt::Psbt;
-    use bdk::bitcoin::{Address, Network};
-    use bdk::Balance;
+    use bdk::bitcoin::secp256k1::Secp256k1;
+    use bdk::bitcoin::{Address, Network, PrivateKey};
     use std::fs::remove_dir_all;
     use std::str::FromStr;
     use std::thread::sleep;
     use std::time::{Duration, SystemTime};
-    use uniffi_lipabusinesslib::{Config, TxStatus, Wallet};
+    use uniffi_lipabusinesslib::{
+        generate_keypair, Balance, BlockchainBackend, Config, DatabaseBackend, TxStatus, Wallet,
+    };
 
     const REGTEST_WATCH_DESCRIPTOR: &str = "wpkh([aeaaaa34/84'/1'/0']tpubDD9QqCT2Y9P3BV7o8a8ajDqHmwWq5XAHKsunr9vjGVYKiRdFQqqC9wuq7jgKdUi8YesiTHiAkNurq7mx7dLDGRCxY4v8fbSa8ZS53MxLrP2/0/*)";
     const REGTEST_SPEND_DESCRIPTOR: &str = "wpkh([aeaaaa34]tprv8ZgxMBicQKsPd8WGzHdgwybWcHrnFkedrEpLTrVR2hfeVPcNUV7K3TT8oSVuNAuotQAevK5S34gWtaMKGoreD2Sq7Mp5HnXqMfxwfiDnVBF/84'/1'/0'/0/*)";
@@ -188,10 +969,20 @@ mod nigiri_tests {
         nigiri::start();
 
         let wallet = Wallet::new(Config {
-            electrum_url: "localhost:50000".to_string(),
+            blockchain_backend: BlockchainBackend::Electrum {
+                url: "localhost:50000".to_string(),
+            },
             wallet_db_path: ".bdk-database-drain-funds".to_string(),
             network: Network::Regtest,
             watch_descriptor: REGTEST_WATCH_DESCRIPTOR.to_string(),
+            stop_gap: None,
+            database: DatabaseBackend::Sled,
+            socks5_proxy: None,
+            timeout_secs: None,
+            electrum_retries: None,
+            broadcast_fallback_url: None,
+            min_confirm_in_blocks: None,
+            max_confirm_in_blocks: None,
         })
         .unwrap();
 
@@ -213,12 +1004,12 @@ mod nigiri_tests {
         wallet.sync().unwrap();
         assert_eq!(
             wallet.get_balance().unwrap(),
-            Balance {
+            Balance::from(bdk::Balance {
                 immature: 0,
                 trusted_pending: 0,
                 untrusted_pending: 10_000_000,
                 confirmed: 20_000_000,
-            }
+            })
         );
 
         assert!(wallet.is_drain_tx_affordable(1).unwrap());
@@ -226,14 +1017,14 @@ mod nigiri_tests {
             .prepare_drain_tx(REGTEST_TARGET_ADDR.to_string(), 1)
             .unwrap();
 
-        assert_eq!(drain_tx.output_sat + drain_tx.on_chain_fee_sat, 20_000_000);
+        assert_eq!(drain_tx.output.sat + drain_tx.on_chain_fee.sat, 20_000_000);
 
         let psbt = deserialize::<Psbt>(&drain_tx.blob).unwrap();
 
         assert_eq!(psbt.unsigned_tx.output.len(), 1);
         assert_eq!(
             psbt.unsigned_tx.output.get(0).unwrap().value,
-            drain_tx.output_sat
+            drain_tx.output.sat
         );
         assert_eq!(
             psbt.unsigned_tx.output.get(0).unwrap().script_pubkey,
@@ -259,12 +1050,12 @@ mod nigiri_tests {
 
         assert_eq!(
             wallet.get_balance().unwrap(),
-            Balance {
+            Balance::from(bdk::Balance {
                 immature: 0,
                 trusted_pending: 0,
                 untrusted_pending: 10_000_000,
                 confirmed: 0
-            }
+            })
         );
 
         // Drain tx appears in the list of spending txs.
@@ -273,8 +1064,11 @@ mod nigiri_tests {
         let spending_tx = spending_txs.first().unwrap();
         assert_eq!(spending_tx.id, drain_tx.id);
         assert_eq!(spending_tx.output_address, REGTEST_TARGET_ADDR);
-        assert_eq!(spending_tx.output_sat, 19999822);
-        assert_eq!(spending_tx.on_chain_fee_sat, 178);
+        assert_eq!(spending_tx.outputs.len(), 1);
+        assert!(!spending_tx.outputs[0].is_mine);
+        assert_eq!(spending_tx.outputs[0].value_sat, 19999822);
+        assert_eq!(spending_tx.output.sat, 19999822);
+        assert_eq!(spending_tx.on_chain_fee.sat, 178);
         assert_eq!(spending_tx.status, TxStatus::InMempool);
 
         assert_eq!(
@@ -295,18 +1089,19 @@ mod nigiri_tests {
             spending_tx.status,
             TxStatus::Confirmed {
                 number_of_blocks: 1,
+                block_height: _,
                 confirmed_at: _,
             }
         ));
 
         assert_eq!(
             wallet.get_balance().unwrap(),
-            Balance {
+            Balance::from(bdk::Balance {
                 immature: 0,
                 trusted_pending: 0,
                 untrusted_pending: 0,
                 confirmed: 10_000_000
-            }
+            })
         );
 
         let tx_status_after_1_conf = wallet.get_tx_status(drain_tx.id.clone()).unwrap();
@@ -314,16 +1109,21 @@ mod nigiri_tests {
             tx_status_after_1_conf,
             TxStatus::Confirmed {
                 number_of_blocks: 1,
+                block_height: _,
                 confirmed_at: _,
             }
         ));
 
-        let confirmed_at_after_1_conf =
-            if let TxStatus::Confirmed { confirmed_at, .. } = tx_status_after_1_conf {
-                confirmed_at
-            } else {
-                panic!();
-            };
+        let (block_height_after_1_conf, confirmed_at_after_1_conf) = if let TxStatus::Confirmed {
+            block_height,
+            confirmed_at,
+            ..
+        } = tx_status_after_1_conf
+        {
+            (block_height, confirmed_at)
+        } else {
+            panic!();
+        };
         // Confirm that confirmed_at is close to now (+/- 5 minutes)
         assert!(SystemTime::now() + Duration::from_secs(300) > confirmed_at_after_1_conf);
         assert!(SystemTime::now() - Duration::from_secs(300) < confirmed_at_after_1_conf);
@@ -336,6 +1136,7 @@ mod nigiri_tests {
             wallet.get_tx_status(drain_tx.id.clone()).unwrap(),
             TxStatus::Confirmed {
                 number_of_blocks: 2,
+                block_height: block_height_after_1_conf,
                 confirmed_at: confirmed_at_after_1_conf,
             }
         );
@@ -348,6 +1149,7 @@ mod nigiri_tests {
             wallet.get_tx_status(drain_tx.id.clone()).unwrap(),
             TxStatus::Confirmed {
                 number_of_blocks: 12,
+                block_height: block_height_after_1_conf,
                 confirmed_at: confirmed_at_after_1_conf
             }
         );
@@ -356,9 +1158,11 @@ mod nigiri_tests {
         let tx = wallet
             .prepare_send_tx(REGTEST_TARGET_ADDR.to_string(), 9_999_400, 1)
             .unwrap();
-        let broadcasted_tx = wallet
-            .sign_and_broadcast_tx(tx.blob, REGTEST_SPEND_DESCRIPTOR.to_string())
+        let signed_tx = wallet
+            .sign_tx(tx.blob, REGTEST_SPEND_DESCRIPTOR.to_string())
             .unwrap();
+        assert_eq!(signed_tx.id, tx.id);
+        let broadcasted_tx = wallet.broadcast_tx(signed_tx.blob).unwrap();
         assert_eq!(broadcasted_tx.id, tx.id);
 
         // Spend tx appears in the list of spending txs.
@@ -368,8 +1172,8 @@ mod nigiri_tests {
         let draining_tx = spending_txs.last().unwrap();
         assert_eq!(spending_tx.id, tx.id);
         assert_eq!(spending_tx.output_address, REGTEST_TARGET_ADDR);
-        assert_eq!(spending_tx.output_sat, 9_999_400);
-        assert_eq!(spending_tx.on_chain_fee_sat, 209);
+        assert_eq!(spending_tx.output.sat, 9_999_400);
+        assert_eq!(spending_tx.on_chain_fee.sat, 209);
         assert_eq!(spending_tx.status, TxStatus::InMempool);
         assert_eq!(draining_tx.id, drain_tx.id);
 
@@ -379,12 +1183,12 @@ mod nigiri_tests {
         wallet.sync().unwrap();
         assert_eq!(
             wallet.get_balance().unwrap(),
-            Balance {
+            Balance::from(bdk::Balance {
                 immature: 0,
                 trusted_pending: 0,
                 untrusted_pending: 0,
                 confirmed: 391,
-            }
+            })
         );
 
         // After sending tx confirmed, ordering is preserved.
@@ -398,4 +1202,219 @@ mod nigiri_tests {
         // 391 sats is not enough to create a drain tx
         assert!(!wallet.is_drain_tx_affordable(1).unwrap());
     }
+
+    #[test]
+    fn test_tx_status_across_reorg() {
+        let _ = remove_dir_all(".bdk-database-reorg");
+
+        nigiri::start();
+
+        let wallet = Wallet::new(Config {
+            blockchain_backend: BlockchainBackend::Electrum {
+                url: "localhost:50000".to_string(),
+            },
+            wallet_db_path: ".bdk-database-reorg".to_string(),
+            network: Network::Regtest,
+            watch_descriptor: REGTEST_WATCH_DESCRIPTOR.to_string(),
+            stop_gap: None,
+            database: DatabaseBackend::Sled,
+            socks5_proxy: None,
+            timeout_secs: None,
+            electrum_retries: None,
+            broadcast_fallback_url: None,
+            min_confirm_in_blocks: None,
+            max_confirm_in_blocks: None,
+        })
+        .unwrap();
+
+        let our_addr = wallet.get_addr().unwrap();
+        let tx_id = nigiri::fund_address(0.1, &our_addr).unwrap().to_string();
+        sleep(Duration::from_secs(5));
+
+        wallet.sync().unwrap();
+        let block_height = match wallet.get_tx_status(tx_id.clone()).unwrap() {
+            TxStatus::Confirmed {
+                number_of_blocks: 1,
+                block_height,
+                ..
+            } => block_height,
+            status => panic!("Unexpected status {status:?}"),
+        };
+
+        // The block confirming the tx is reorged out, so the tx goes back to the mempool.
+        nigiri::invalidate_block(&nigiri::get_block_hash(block_height)).unwrap();
+        sleep(Duration::from_secs(5));
+
+        wallet.sync().unwrap();
+        assert_eq!(
+            wallet.get_tx_status(tx_id.clone()).unwrap(),
+            TxStatus::InMempool
+        );
+        assert_eq!(
+            wallet.get_balance().unwrap(),
+            Balance::from(bdk::Balance {
+                immature: 0,
+                trusted_pending: 0,
+                untrusted_pending: 10_000_000,
+                confirmed: 0,
+            })
+        );
+
+        // The new chain confirms the tx again at the same height.
+        nigiri::mine_blocks(2).unwrap();
+        sleep(Duration::from_secs(5));
+
+        wallet.sync().unwrap();
+        assert!(matches!(
+            wallet.get_tx_status(tx_id.clone()).unwrap(),
+            TxStatus::Confirmed {
+                number_of_blocks: 2,
+                block_height: height,
+                ..
+            } if height == block_height
+        ));
+
+        // A reorg replacing the confirming block moves the tx into the new block of the same height.
+        nigiri::reorg(2).unwrap();
+        sleep(Duration::from_secs(5));
+
+        wallet.sync().unwrap();
+        assert!(matches!(
+            wallet.get_tx_status(tx_id).unwrap(),
+            TxStatus::Confirmed {
+                number_of_blocks: 3,
+                block_height: height,
+                ..
+            } if height == block_height
+        ));
+        assert_eq!(
+            wallet.get_balance().unwrap(),
+            Balance::from(bdk::Balance {
+                immature: 0,
+                trusted_pending: 0,
+                untrusted_pending: 0,
+                confirmed: 10_000_000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_watch_for_incoming_flow() {
+        let _ = remove_dir_all(".bdk-database-watch-for-incoming-flow");
+
+        nigiri::start();
+
+        let wallet = Wallet::new(Config {
+            blockchain_backend: BlockchainBackend::Electrum {
+                url: "localhost:50000".to_string(),
+            },
+            wallet_db_path: ".bdk-database-watch-for-incoming-flow".to_string(),
+            network: Network::Regtest,
+            watch_descriptor: REGTEST_WATCH_DESCRIPTOR.to_string(),
+            stop_gap: None,
+            database: DatabaseBackend::Sled,
+            socks5_proxy: None,
+            timeout_secs: None,
+            electrum_retries: None,
+            broadcast_fallback_url: None,
+            min_confirm_in_blocks: None,
+            max_confirm_in_blocks: None,
+        })
+        .unwrap();
+
+        let our_addr = wallet.get_addr().unwrap();
+        let old_tx_id = nigiri::fund_address(0.1, &our_addr).unwrap();
+        nigiri::wait_for_electrum_to_see_tx(&old_tx_id);
+        wallet.sync().unwrap();
+
+        // The tx paid to the address before watching started.
+        assert!(wallet
+            .watch_for_incoming(our_addr.clone(), 0)
+            .unwrap()
+            .is_none());
+
+        let new_tx_id = nigiri::fund_address_without_conf(0.05, &our_addr).unwrap();
+        nigiri::wait_for_electrum_to_see_tx(&new_tx_id);
+        let incoming_tx = wallet.watch_for_incoming(our_addr, 30).unwrap().unwrap();
+        assert_eq!(incoming_tx.id, new_tx_id.to_string());
+        assert_eq!(incoming_tx.amount.sat, 5_000_000);
+        assert_eq!(incoming_tx.status, TxStatus::InMempool);
+    }
+
+    #[test]
+    fn test_sweep_flow() {
+        let _ = remove_dir_all(".bdk-database-sweep");
+
+        nigiri::start();
+
+        let wallet = Wallet::new(Config {
+            blockchain_backend: BlockchainBackend::Electrum {
+                url: "localhost:50000".to_string(),
+            },
+            wallet_db_path: ".bdk-database-sweep".to_string(),
+            network: Network::Regtest,
+            watch_descriptor: REGTEST_WATCH_DESCRIPTOR.to_string(),
+            stop_gap: None,
+            database: DatabaseBackend::Sled,
+            socks5_proxy: None,
+            timeout_secs: None,
+            electrum_retries: None,
+            broadcast_fallback_url: None,
+            min_confirm_in_blocks: None,
+            max_confirm_in_blocks: None,
+        })
+        .unwrap();
+        wallet.sync().unwrap();
+        let get_total_balance_sat = || {
+            let balance = wallet.get_balance().unwrap();
+            balance.confirmed.sat + balance.trusted_pending.sat + balance.untrusted_pending.sat
+        };
+        let balance_before_sat = get_total_balance_sat();
+
+        // A new key every run, so that funds of previous runs don't count.
+        let secret_key = Vec::from_hex(&generate_keypair().secret_key).unwrap();
+        let private_key = PrivateKey::from_slice(&secret_key, Network::Regtest).unwrap();
+        let public_key = private_key.public_key(&Secp256k1::new());
+        let p2wpkh_addr = Address::p2wpkh(&public_key, Network::Regtest).unwrap();
+        let p2pkh_addr = Address::p2pkh(&public_key, Network::Regtest);
+
+        let tx_id_p2wpkh = nigiri::fund_address(0.1, &p2wpkh_addr.to_string()).unwrap();
+        let tx_id_p2pkh = nigiri::fund_address(0.05, &p2pkh_addr.to_string()).unwrap();
+        nigiri::wait_for_electrum_to_see_tx(&tx_id_p2wpkh);
+        nigiri::wait_for_electrum_to_see_tx(&tx_id_p2pkh);
+
+        let sweep_tx = wallet
+            .prepare_sweep_from_key(private_key.to_wif(), 1)
+            .unwrap();
+        assert_eq!(sweep_tx.output.sat + sweep_tx.on_chain_fee.sat, 15_000_000);
+        let psbt = deserialize::<Psbt>(&sweep_tx.blob).unwrap();
+        assert_eq!(psbt.unsigned_tx.input.len(), 2);
+        assert_eq!(psbt.unsigned_tx.output.len(), 1);
+
+        // The spend descriptor isn't used to sign a sweep tx, the swept key is.
+        let broadcasted_tx = wallet
+            .sign_and_broadcast_tx(sweep_tx.blob, REGTEST_SPEND_DESCRIPTOR.to_string())
+            .unwrap();
+        assert_eq!(broadcasted_tx.output, sweep_tx.output);
+        assert_eq!(broadcasted_tx.on_chain_fee, sweep_tx.on_chain_fee);
+        assert_eq!(broadcasted_tx.outputs.len(), 1);
+        assert!(broadcasted_tx.outputs[0].is_mine);
+        assert_eq!(broadcasted_tx.status, TxStatus::InMempool);
+
+        nigiri::mine_blocks(1).unwrap();
+        sleep(Duration::from_secs(5));
+
+        wallet.sync().unwrap();
+        assert_eq!(
+            get_total_balance_sat(),
+            balance_before_sat + sweep_tx.output.sat
+        );
+        assert!(matches!(
+            wallet.get_tx_status(broadcasted_tx.id).unwrap(),
+            TxStatus::Confirmed {
+                number_of_blocks: 1,
+                ..
+            }
+        ));
+    }
 }